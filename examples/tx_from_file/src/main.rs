@@ -0,0 +1,83 @@
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use num_complex::Complex32;
+
+use uhd_usrp::{
+    playback::{FileFormat, FileSource, Playback},
+    timespec, Channel, Usrp,
+};
+
+type Sample = Complex32;
+
+#[derive(clap::Parser)]
+struct Args {
+    /// A SigMF recording, or a raw file of interleaved samples.
+    #[arg(long)]
+    file: PathBuf,
+    /// Format of a raw file: fc32 or sc16. Omit for SigMF recordings.
+    #[arg(long)]
+    format: Option<String>,
+    #[arg(long)]
+    freq: f64,
+    #[arg(long)]
+    rate: f64,
+    #[arg(long)]
+    bw: f64,
+    #[arg(long)]
+    gain: f64,
+    #[arg(long)]
+    ant: String,
+    #[arg(long)]
+    args: String,
+    #[arg(long)]
+    channel: usize,
+    /// Repeat the file until interrupted.
+    #[arg(long)]
+    repeat: bool,
+}
+
+fn open_source(args: &Args) -> Result<FileSource, Box<dyn Error>> {
+    let source = match args.format.as_deref() {
+        None => FileSource::open_sigmf(&args.file)?,
+        Some("fc32") => {
+            FileSource::open_raw(&args.file, FileFormat::Cf32)?.with_sample_rate(args.rate)
+        }
+        Some("sc16") => {
+            FileSource::open_raw(&args.file, FileFormat::Ci16)?.with_sample_rate(args.rate)
+        }
+        Some(other) => return Err(format!("unsupported format {other:?}").into()),
+    };
+    Ok(source)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let mut source = open_source(&args)?;
+
+    let usrp = Usrp::open_with_args(&args.args)?;
+    let channel = usrp
+        .channel(Channel::Tx(args.channel))
+        .expect("invalid channel");
+    channel
+        .set_antenna(&args.ant)?
+        .set_center_freq(args.freq)?
+        .set_bandwidth(args.bw)?
+        .set_gain(None, args.gain)?
+        .set_sample_rate(args.rate)?
+        .print_common()?;
+    source.check_channel(&channel)?;
+
+    let mut tx_stream = usrp
+        .tx_stream::<Sample>()
+        .with_channels(&[args.channel])
+        .open()?;
+    let start = usrp.mboard(0).time()? + timespec!(500 ms);
+    let sent = Playback::new()
+        .with_looping(args.repeat)
+        .with_start_time(start)
+        .with_timeout(Duration::from_secs(1))
+        .play(&mut source, &mut tx_stream)?;
+    println!("sent {sent} samples");
+    Ok(())
+}
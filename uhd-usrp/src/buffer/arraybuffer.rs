@@ -313,6 +313,10 @@ where
     }
 }
 
+// SAFETY: the sample memory is uniquely owned by the buffer.
+unsafe impl<S: Sample + Send> Send for ArrayBuffer<S> {}
+unsafe impl<S: Sample + Sync> Sync for ArrayBuffer<S> {}
+
 impl<S> Drop for ArrayBuffer<S>
 where
     S: Sample,
@@ -320,9 +324,9 @@ where
     fn drop(&mut self) {
         for i in self.inner.iter() {
             // SAFETY: the data being reclaimed with `Box::from_raw` was originally obtained
-            // using `Box::into_raw`.
+            // using `Box::into_raw` on a boxed slice of length `samples`.
             unsafe {
-                let _ = Box::from_raw(i.cast::<&mut [S]>());
+                let _ = Box::from_raw(std::ptr::slice_from_raw_parts_mut(*i, self.samples));
             }
         }
     }
@@ -423,6 +427,14 @@ mod test {
         assert!(buff.iter_channels().all(|c| c.len() == 13))
     }
 
+    #[test]
+    pub fn test_empty() {
+        let buff: ArrayBuffer<Complex32> = ArrayBuffer::new(2, 0);
+        assert!(buff.iter_channels().all(|c| c.is_empty()));
+        let buff: ArrayBuffer<i16> = ArrayBuffer::from_vec_channels(vec![Vec::new(); 3]);
+        assert_eq!(buff.into_vec(), vec![Vec::<i16>::new(); 3]);
+    }
+
     #[test]
     pub fn test_iter_samples() {
         const CHANNELS: usize = 10;
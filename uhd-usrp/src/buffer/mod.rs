@@ -3,8 +3,10 @@ use std::ops::DerefMut;
 use crate::Sample;
 
 mod arraybuffer;
+mod ring;
 
 pub use arraybuffer::ArrayBuffer;
+pub(crate) use ring::{ring, RingConsumer, RingProducer};
 
 /// Trait indicating a type is compatible with UHD's notion of a sample buffer.
 ///
@@ -0,0 +1,227 @@
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Fixed-capacity single-producer single-consumer ring of preallocated slots.
+///
+/// Slots are reused rather than moved in and out of the ring, so the producer
+/// fills a slot in place and the consumer reads it in place.
+struct Ring<B> {
+    slots: Box<[UnsafeCell<B>]>,
+    /// Number of slots released by the consumer. Only written by the consumer.
+    head: AtomicUsize,
+    /// Number of slots committed by the producer. Only written by the producer.
+    tail: AtomicUsize,
+}
+
+// SAFETY: a slot is only ever accessed by one side at a time, as determined
+// by the head and tail counters.
+unsafe impl<B: Send> Sync for Ring<B> {}
+
+/// The writing half of a ring.
+pub(crate) struct RingProducer<B> {
+    ring: Arc<Ring<B>>,
+}
+
+/// The reading half of a ring.
+pub(crate) struct RingConsumer<B> {
+    ring: Arc<Ring<B>>,
+}
+
+/// Create a ring holding the given slots.
+///
+/// # Panics
+///
+/// Panics if no slots are given.
+pub(crate) fn ring<B>(slots: impl IntoIterator<Item = B>) -> (RingProducer<B>, RingConsumer<B>) {
+    let slots: Box<[_]> = slots.into_iter().map(UnsafeCell::new).collect();
+    assert!(!slots.is_empty(), "ring must have at least one slot");
+    let ring = Arc::new(Ring {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (RingProducer { ring: ring.clone() }, RingConsumer { ring })
+}
+
+impl<B> Ring<B> {
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: usize) -> *mut B {
+        self.slots[index % self.capacity()].get()
+    }
+}
+
+impl<B> RingProducer<B> {
+    /// Get the next free slot, or `None` if the ring is full.
+    ///
+    /// The slot still holds whatever was last written to it. Nothing is
+    /// visible to the consumer until [`RingProducer::commit`] is called.
+    pub fn slot(&mut self) -> Option<&mut B> {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        if tail - head == self.ring.capacity() {
+            return None;
+        }
+        // SAFETY: slots between head and tail belong to the consumer; this one doesn't.
+        Some(unsafe { &mut *self.ring.slot(tail) })
+    }
+
+    /// Make the slot returned by [`RingProducer::slot`] available to the consumer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ring is full.
+    pub fn commit(&mut self) {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        assert!(tail - head < self.ring.capacity(), "ring is full");
+        self.ring.tail.store(tail + 1, Ordering::Release);
+    }
+
+    /// Returns `true` if the consumer has been dropped.
+    pub fn is_disconnected(&self) -> bool {
+        Arc::strong_count(&self.ring) == 1
+    }
+}
+
+impl<B> RingConsumer<B> {
+    /// Get the oldest committed slot, or `None` if the ring is empty.
+    pub fn peek(&self) -> Option<&B> {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        // SAFETY: the slot has been committed and won't be touched by the producer
+        // until it is released.
+        Some(unsafe { &*self.ring.slot(head) })
+    }
+
+    /// Return the slot returned by [`RingConsumer::peek`] to the producer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ring is empty.
+    pub fn release(&mut self) {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        assert!(head != tail, "ring is empty");
+        self.ring.head.store(head + 1, Ordering::Release);
+    }
+
+    /// Number of committed slots waiting to be read.
+    pub fn len(&self) -> usize {
+        self.ring.tail.load(Ordering::Acquire) - self.ring.head.load(Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_fill_and_drain() {
+        let (mut tx, mut rx) = ring(vec![0; 3]);
+        assert!(rx.peek().is_none());
+        for i in 1..=3 {
+            *tx.slot().unwrap() = i;
+            tx.commit();
+        }
+        assert!(tx.slot().is_none());
+        assert_eq!(rx.len(), 3);
+        assert_eq!(*rx.peek().unwrap(), 1);
+        // Peeking doesn't consume.
+        assert_eq!(*rx.peek().unwrap(), 1);
+        rx.release();
+        *tx.slot().unwrap() = 4;
+        tx.commit();
+        let mut drained = Vec::new();
+        while let Some(&v) = rx.peek() {
+            drained.push(v);
+            rx.release();
+        }
+        assert_eq!(drained, [2, 3, 4]);
+        assert_eq!(rx.len(), 0);
+    }
+
+    #[test]
+    pub fn test_slots_are_reused() {
+        let (mut tx, mut rx) = ring((0..2).map(|_| Vec::<u32>::with_capacity(8)));
+        for i in 0..10 {
+            let slot = tx.slot().unwrap();
+            slot.clear();
+            slot.push(i);
+            tx.commit();
+            let slot = rx.peek().unwrap();
+            assert_eq!(slot, &[i]);
+            assert!(slot.capacity() >= 8);
+            rx.release();
+        }
+    }
+
+    #[test]
+    pub fn test_uncommitted_slot_is_invisible() {
+        let (mut tx, rx) = ring(vec![0; 2]);
+        *tx.slot().unwrap() = 7;
+        assert!(rx.peek().is_none());
+        tx.commit();
+        assert_eq!(*rx.peek().unwrap(), 7);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_release_empty() {
+        let (_tx, mut rx) = ring(vec![0; 2]);
+        rx.release();
+    }
+
+    #[test]
+    pub fn test_disconnect() {
+        let (tx, rx) = ring(vec![0; 2]);
+        assert!(!tx.is_disconnected());
+        drop(rx);
+        assert!(tx.is_disconnected());
+    }
+
+    #[test]
+    pub fn test_threads() {
+        const COUNT: u64 = 10_000;
+        let (mut tx, mut rx) = ring(vec![0u64; 16]);
+        let producer = std::thread::spawn(move || {
+            let mut next = 0;
+            while next < COUNT {
+                match tx.slot() {
+                    Some(slot) => {
+                        *slot = next;
+                        tx.commit();
+                        next += 1;
+                    }
+                    None => std::thread::yield_now(),
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < COUNT {
+            match rx.peek() {
+                Some(&v) => {
+                    assert_eq!(v, expected);
+                    rx.release();
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,440 @@
+//! Frequency-dependent calibration tables.
+//!
+//! A [`CalibrationTable`] holds corrections measured at a number of frequencies.
+//! Once attached to a channel with [`ChannelConfig::set_calibration`], the
+//! corrections are interpolated and applied whenever the channel is tuned:
+//!
+//! - The gain offset is added to the overall gain set on the device, so that
+//!   [`ChannelConfig::set_gain`] and [`ChannelConfig::gain`] deal in calibrated
+//!   gains. The requested gain is remembered and set again with the new offset
+//!   on each tune, so [`ChannelConfig::gain`] returns it rather than a value
+//!   rounded or clamped by the device. Named gain elements are not affected.
+//! - The DC offset and IQ imbalance corrections can't be set through UHD's C API
+//!   (see [`correction`](crate::correction)), so the interpolated [`IqCorrection`]
+//!   is made available through [`ChannelConfig::iq_correction`] to be applied to
+//!   received samples.
+//!
+//! # File formats
+//!
+//! Tables can be read from and written to CSV files with a header line and one
+//! point per line. Blank lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! freq,gain_offset,dc_offset_i,dc_offset_q,iq_balance_re,iq_balance_im
+//! 2.4e9,1.5,0.01,-0.002,0.03,0.001
+//! 2.5e9,2.0,0.012,-0.001,0.028,0.002
+//! ```
+//!
+//! With the `serde` feature enabled, tables serialize as a list of points, which
+//! in JSON looks like:
+//!
+//! ```json
+//! [
+//!   { "freq": 2.4e9, "gain_offset": 1.5, "dc_offset": [0.01, -0.002], "iq_balance": [0.03, 0.001] }
+//! ]
+//! ```
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use uhd_usrp::{calibration::CalibrationTable, Channel, Usrp};
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let ch = usrp.channel(Channel::Rx(0)).unwrap();
+//! ch.set_calibration(CalibrationTable::load_csv("rx0.csv").unwrap())
+//!     .unwrap()
+//!     .set_center_freq(2.45e9)
+//!     .unwrap()
+//!     .set_gain(None, 30.0)
+//!     .unwrap();
+//! println!("apply {:?} to received samples", ch.iq_correction());
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use num_complex::Complex64;
+
+use crate::{correction::IqCorrection, ChannelConfig, Result};
+
+/// Header line of the CSV format.
+const CSV_HEADER: &str = "freq,gain_offset,dc_offset_i,dc_offset_q,iq_balance_re,iq_balance_im";
+
+/// An error which occurred while reading a calibration table.
+#[derive(thiserror::Error, Debug)]
+pub enum CalibrationError {
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Corrections measured at a single frequency.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibrationPoint {
+    /// The frequency in Hz.
+    pub freq: f64,
+    /// Gain in dB added to the overall gain.
+    pub gain_offset: f64,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub correction: IqCorrection,
+}
+
+/// Corrections measured at a number of frequencies, ordered by frequency.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "Vec<CalibrationPoint>", into = "Vec<CalibrationPoint>")
+)]
+pub struct CalibrationTable {
+    points: Vec<CalibrationPoint>,
+}
+
+impl From<Vec<CalibrationPoint>> for CalibrationTable {
+    fn from(points: Vec<CalibrationPoint>) -> Self {
+        points.into_iter().fold(Self::new(), Self::with_point)
+    }
+}
+
+impl From<CalibrationTable> for Vec<CalibrationPoint> {
+    fn from(table: CalibrationTable) -> Self {
+        table.points
+    }
+}
+
+impl CalibrationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a point, replacing any existing point at the same frequency.
+    pub fn insert(&mut self, point: CalibrationPoint) {
+        let i = self.points.partition_point(|p| p.freq < point.freq);
+        match self.points.get_mut(i) {
+            Some(p) if p.freq == point.freq => *p = point,
+            _ => self.points.insert(i, point),
+        }
+    }
+
+    /// Builder-style variant of [`CalibrationTable::insert`].
+    pub fn with_point(mut self, point: CalibrationPoint) -> Self {
+        self.insert(point);
+        self
+    }
+
+    pub fn points(&self) -> &[CalibrationPoint] {
+        &self.points
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Interpolate the corrections at the given frequency.
+    ///
+    /// Corrections are interpolated linearly between the two nearest points.
+    /// Frequencies outside the table use the corrections of the nearest point.
+    /// Returns `None` if the table is empty.
+    pub fn interpolate(&self, freq: f64) -> Option<CalibrationPoint> {
+        let i = self.points.partition_point(|p| p.freq < freq);
+        let (lo, hi) = match (i.checked_sub(1), self.points.get(i)) {
+            (Some(lo), Some(hi)) => (&self.points[lo], hi),
+            (Some(lo), None) => (&self.points[lo], &self.points[lo]),
+            (None, Some(hi)) => (hi, hi),
+            (None, None) => return None,
+        };
+        let t = if hi.freq > lo.freq {
+            (freq - lo.freq) / (hi.freq - lo.freq)
+        } else {
+            0.0
+        };
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        let clerp = |a: Complex64, b: Complex64| a + (b - a) * t;
+        Some(CalibrationPoint {
+            freq,
+            gain_offset: lerp(lo.gain_offset, hi.gain_offset),
+            correction: IqCorrection::new(
+                clerp(lo.correction.dc_offset, hi.correction.dc_offset),
+                clerp(lo.correction.iq_balance, hi.correction.iq_balance),
+            ),
+        })
+    }
+
+    /// Read a table in CSV format.
+    pub fn read_csv(reader: impl BufRead) -> Result<Self, CalibrationError> {
+        let mut table = Self::new();
+        let mut header = false;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parse_error = |message: String| CalibrationError::Parse {
+                line: i + 1,
+                message,
+            };
+            if !header {
+                let columns: Vec<_> = line.split(',').map(str::trim).collect();
+                if columns.join(",") != CSV_HEADER {
+                    return Err(parse_error(format!("expected header {CSV_HEADER:?}")));
+                }
+                header = true;
+                continue;
+            }
+            let values = line
+                .split(',')
+                .map(|v| v.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| parse_error(e.to_string()))?;
+            match values[..] {
+                [freq, gain_offset, dc_i, dc_q, iq_re, iq_im] => table.insert(CalibrationPoint {
+                    freq,
+                    gain_offset,
+                    correction: IqCorrection::new(
+                        Complex64::new(dc_i, dc_q),
+                        Complex64::new(iq_re, iq_im),
+                    ),
+                }),
+                _ => {
+                    return Err(parse_error(format!(
+                        "expected 6 values, got {}",
+                        values.len()
+                    )))
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    /// Write the table in CSV format.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{CSV_HEADER}")?;
+        for p in &self.points {
+            let IqCorrection {
+                dc_offset,
+                iq_balance,
+            } = p.correction;
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                p.freq, p.gain_offset, dc_offset.re, dc_offset.im, iq_balance.re, iq_balance.im
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Read a table from a CSV file.
+    pub fn load_csv(path: impl AsRef<Path>) -> Result<Self, CalibrationError> {
+        Self::read_csv(BufReader::new(File::open(path)?))
+    }
+
+    /// Write the table to a CSV file.
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_csv(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// A calibration table attached to a channel, along with the requested gain
+/// and the corrections applied at the current frequency.
+#[derive(Clone, Debug)]
+pub(crate) struct ChannelCalibration {
+    table: CalibrationTable,
+    gain: f64,
+    gain_offset: f64,
+    correction: IqCorrection,
+}
+
+/// Calibration
+impl<'u> ChannelConfig<'u> {
+    /// Attach a calibration table to the channel, replacing any existing table.
+    ///
+    /// The table's corrections are applied immediately and whenever the channel is tuned.
+    /// See the [`calibration`](crate::calibration) module for details.
+    pub fn set_calibration(&self, table: CalibrationTable) -> Result<&Self> {
+        let gain = self.gain(None)?;
+        let freq = self.center_freq()?;
+        self.usrp.calibrations().borrow_mut().insert(
+            self.channel,
+            ChannelCalibration {
+                table,
+                gain,
+                gain_offset: 0.0,
+                correction: IqCorrection::default(),
+            },
+        );
+        self.update_calibration(freq)?;
+        Ok(self)
+    }
+
+    /// Detach the calibration table from the channel, removing its gain offset.
+    pub fn clear_calibration(&self) -> Result<&Self> {
+        let removed = self.usrp.calibrations().borrow_mut().remove(&self.channel);
+        if let Some(c) = removed {
+            self.set_gain(None, c.gain)?;
+        }
+        Ok(self)
+    }
+
+    /// The calibration table attached to the channel, if any.
+    pub fn calibration(&self) -> Option<CalibrationTable> {
+        let calibrations = self.usrp.calibrations().borrow();
+        calibrations.get(&self.channel).map(|c| c.table.clone())
+    }
+
+    /// The DC offset and IQ imbalance corrections for the current frequency,
+    /// if a calibration table is attached.
+    ///
+    /// These should be applied to received samples using [`IqCorrection::apply`].
+    pub fn iq_correction(&self) -> Option<IqCorrection> {
+        let calibrations = self.usrp.calibrations().borrow();
+        calibrations.get(&self.channel).map(|c| c.correction)
+    }
+
+    /// The overall gain last requested, if a calibration table is attached.
+    pub(crate) fn calibrated_gain(&self) -> Option<f64> {
+        let calibrations = self.usrp.calibrations().borrow();
+        calibrations.get(&self.channel).map(|c| c.gain)
+    }
+
+    /// Record a requested overall gain and return the gain to set on the device.
+    pub(crate) fn calibrate_gain(&self, gain: f64) -> f64 {
+        let mut calibrations = self.usrp.calibrations().borrow_mut();
+        match calibrations.get_mut(&self.channel) {
+            Some(c) => {
+                c.gain = gain;
+                gain + c.gain_offset
+            }
+            None => gain,
+        }
+    }
+
+    /// Apply the corrections for the given frequency, if a table is attached.
+    ///
+    /// The requested gain is set again with the new offset, rather than read back,
+    /// since the device may have rounded or clamped it.
+    pub(crate) fn update_calibration(&self, freq: f64) -> Result<()> {
+        let gain = {
+            let mut calibrations = self.usrp.calibrations().borrow_mut();
+            let c = match calibrations.get_mut(&self.channel) {
+                Some(c) => c,
+                None => return Ok(()),
+            };
+            let point = c.table.interpolate(freq).unwrap_or_default();
+            c.gain_offset = point.gain_offset;
+            c.correction = point.correction;
+            c.gain
+        };
+        self.set_gain(None, gain)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn point(freq: f64, gain_offset: f64, dc: f64, iq: f64) -> CalibrationPoint {
+        CalibrationPoint {
+            freq,
+            gain_offset,
+            correction: IqCorrection::new(Complex64::new(dc, -dc), Complex64::new(iq, 0.0)),
+        }
+    }
+
+    fn table() -> CalibrationTable {
+        CalibrationTable::new()
+            .with_point(point(2.5e9, 3.0, 0.02, 0.04))
+            .with_point(point(1e9, 1.0, 0.0, 0.0))
+            .with_point(point(2e9, 2.0, 0.01, 0.02))
+    }
+
+    #[test]
+    fn sorted_by_freq() {
+        let mut table = table();
+        let freqs: Vec<_> = table.points().iter().map(|p| p.freq).collect();
+        assert_eq!(freqs, [1e9, 2e9, 2.5e9]);
+        table.insert(point(2e9, 5.0, 0.0, 0.0));
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.points()[1].gain_offset, 5.0);
+    }
+
+    #[test]
+    fn interpolation() {
+        let table = table();
+        let p = table.interpolate(1.5e9).unwrap();
+        assert_eq!(p.freq, 1.5e9);
+        assert!((p.gain_offset - 1.5).abs() < 1e-12);
+        assert!((p.correction.dc_offset - Complex64::new(0.005, -0.005)).norm() < 1e-12);
+        assert!((p.correction.iq_balance.re - 0.01).abs() < 1e-12);
+
+        let p = table.interpolate(2.25e9).unwrap();
+        assert!((p.gain_offset - 2.5).abs() < 1e-12);
+
+        // Exact points and frequencies outside the table.
+        assert_eq!(table.interpolate(2e9).unwrap().gain_offset, 2.0);
+        assert_eq!(table.interpolate(100e6).unwrap().gain_offset, 1.0);
+        assert_eq!(table.interpolate(6e9).unwrap().gain_offset, 3.0);
+
+        assert_eq!(CalibrationTable::new().interpolate(1e9), None);
+        let single = CalibrationTable::new().with_point(point(1e9, 4.0, 0.0, 0.0));
+        assert_eq!(single.interpolate(2e9).unwrap().gain_offset, 4.0);
+    }
+
+    #[test]
+    fn csv() {
+        let table = table();
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        let text = String::from_utf8(csv).unwrap();
+        assert!(text.starts_with(CSV_HEADER));
+        assert_eq!(CalibrationTable::read_csv(text.as_bytes()).unwrap(), table);
+
+        let commented = format!("# measured 2024-01-01\n\n{CSV_HEADER}\n1e9, 1, 0, 0, 0, 0\n");
+        let parsed = CalibrationTable::read_csv(commented.as_bytes()).unwrap();
+        assert_eq!(parsed.points(), [point(1e9, 1.0, 0.0, 0.0)]);
+    }
+
+    #[test]
+    fn csv_errors() {
+        let err = CalibrationTable::read_csv("freq,gain\n".as_bytes()).unwrap_err();
+        assert!(
+            matches!(err, CalibrationError::Parse { line: 1, .. }),
+            "{err}"
+        );
+        let bad = format!("{CSV_HEADER}\n1e9,1,0,0,0,0\n2e9,x,0,0,0,0\n");
+        let err = CalibrationTable::read_csv(bad.as_bytes()).unwrap_err();
+        assert!(
+            matches!(err, CalibrationError::Parse { line: 3, .. }),
+            "{err}"
+        );
+        let short = format!("{CSV_HEADER}\n1e9,1,0\n");
+        let err = CalibrationTable::read_csv(short.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: expected 6 values, got 3");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let table = table();
+        let json = serde_json::to_string(&table).unwrap();
+        assert!(
+            json.starts_with(r#"[{"freq":1000000000.0,"gain_offset":1.0,"dc_offset":[0.0,-0.0]"#)
+        );
+        assert_eq!(
+            serde_json::from_str::<CalibrationTable>(&json).unwrap(),
+            table
+        );
+    }
+}
@@ -0,0 +1,536 @@
+//! Conversions between sample formats.
+//!
+//! Samples can be received in a compact format such as `sc16` to save bandwidth
+//! and converted on the host to the format used for processing. Conversions
+//! follow UHD's scaling convention, where integer full scale corresponds to a
+//! floating-point magnitude of `1.0` (see [`IqSample`]). Conversions to integers
+//! round to the nearest value and saturate.
+//!
+//! The conversions between `i16` or `i8` and `f32` samples use SSE2 on x86-64.
+//! Other conversions, and all conversions on other architectures, use plain
+//! loops which the compiler is free to vectorize.
+//!
+//! # Examples
+//!
+//! Convert samples received as `sc16`:
+//!
+//! ```no_run
+//! use num_complex::{Complex, Complex32};
+//! use uhd_usrp::{convert, Usrp};
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let mut stream = usrp.rx_stream::<Complex<i16>>().with_channels(&[0]).open().unwrap();
+//! let mut raw = vec![Complex::<i16>::default(); stream.max_samples_per_channel()];
+//! let mut samples = vec![Complex32::default(); raw.len()];
+//! stream.start_command().send().unwrap();
+//!
+//! let n = stream.reader().recv(&mut raw).unwrap();
+//! convert::convert(&raw[..n], &mut samples[..n]);
+//! ```
+//!
+//! Or let a [`ConvertingReader`] take care of it:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use num_complex::{Complex, Complex32};
+//! use uhd_usrp::{ArrayBuffer, Usrp};
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let mut stream = usrp.rx_stream::<Complex<i16>>().with_channels(&[0, 1]).open().unwrap();
+//! let mut buff = ArrayBuffer::<Complex32>::new(2, 10_000);
+//! stream.start_command().send().unwrap();
+//!
+//! let mut reader = stream.reader().into_converting::<Complex32>();
+//! reader.reader_mut().with_timeout(Duration::from_millis(100));
+//! let n = reader.recv(&mut buff).unwrap();
+//! ```
+
+use std::marker::PhantomData;
+
+use crate::{stream::RxStreamReader, ArrayBuffer, IqSample, Result, SampleBuffer, UhdError};
+
+mod private {
+    /// The type of the real and imaginary parts of a sample.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Kind {
+        I8,
+        I16,
+        F32,
+        F64,
+    }
+
+    pub trait Scalar: Copy + 'static {
+        const KIND: Kind;
+    }
+
+    impl Scalar for i8 {
+        const KIND: Kind = Kind::I8;
+    }
+
+    impl Scalar for i16 {
+        const KIND: Kind = Kind::I16;
+    }
+
+    impl Scalar for f32 {
+        const KIND: Kind = Kind::F32;
+    }
+
+    impl Scalar for f64 {
+        const KIND: Kind = Kind::F64;
+    }
+
+    /// A sample made up of two consecutive scalars, the real part first.
+    ///
+    /// # Safety
+    ///
+    /// The type must have the layout of `[Self::Scalar; 2]`.
+    pub unsafe trait Interleaved {
+        type Scalar: Scalar;
+    }
+}
+
+use private::{Interleaved, Kind, Scalar};
+
+/// A sample type supported by the conversions in this module.
+///
+/// Implemented for `[T; 2]` and `num_complex::Complex<T>`, where `T` is one of
+/// `i8`, `i16`, `f32` and `f64`.
+pub trait Convertible: IqSample + Interleaved {}
+
+macro_rules! convertible {
+    ($t:ty) => {
+        unsafe impl Interleaved for [$t; 2] {
+            type Scalar = $t;
+        }
+
+        impl Convertible for [$t; 2] {}
+
+        // `Complex` is `repr(C)`.
+        #[cfg(feature = "num")]
+        unsafe impl Interleaved for num_complex::Complex<$t> {
+            type Scalar = $t;
+        }
+
+        #[cfg(feature = "num")]
+        impl Convertible for num_complex::Complex<$t> {}
+    };
+}
+
+convertible!(i8);
+convertible!(i16);
+convertible!(f32);
+convertible!(f64);
+
+/// Convert a slice of samples into another format.
+///
+/// # Panics
+///
+/// Panics if the slices have different lengths.
+pub fn convert<S: Convertible, D: Convertible>(src: &[S], dst: &mut [D]) {
+    assert_eq!(
+        src.len(),
+        dst.len(),
+        "source and destination have different lengths"
+    );
+    let src = scalars(src);
+    let dst = scalars_mut(dst);
+    // The scalar types are checked before each cast, so the casts are between identical types.
+    match (S::Scalar::KIND, D::Scalar::KIND) {
+        (s, d) if s == d => dst.copy_from_slice(unsafe { cast(src) }),
+        (Kind::I16, Kind::F32) => {
+            kernels::i16_to_f32(unsafe { cast(src) }, unsafe { cast_mut(dst) })
+        }
+        (Kind::F32, Kind::I16) => {
+            kernels::f32_to_i16(unsafe { cast(src) }, unsafe { cast_mut(dst) })
+        }
+        (Kind::I8, Kind::F32) => kernels::i8_to_f32(unsafe { cast(src) }, unsafe { cast_mut(dst) }),
+        (Kind::F32, Kind::I8) => kernels::f32_to_i8(unsafe { cast(src) }, unsafe { cast_mut(dst) }),
+        _ => {
+            let src =
+                unsafe { std::slice::from_raw_parts(src.as_ptr() as *const S, src.len() / 2) };
+            let dst = unsafe {
+                std::slice::from_raw_parts_mut(dst.as_mut_ptr() as *mut D, dst.len() / 2)
+            };
+            for (d, s) in dst.iter_mut().zip(src) {
+                *d = D::from_iq(s.to_iq());
+            }
+        }
+    }
+}
+
+fn scalars<S: Convertible>(samples: &[S]) -> &[S::Scalar] {
+    // Safety: `Interleaved` guarantees the layout.
+    unsafe { std::slice::from_raw_parts(samples.as_ptr().cast(), samples.len() * 2) }
+}
+
+fn scalars_mut<S: Convertible>(samples: &mut [S]) -> &mut [S::Scalar] {
+    // Safety: `Interleaved` guarantees the layout.
+    unsafe { std::slice::from_raw_parts_mut(samples.as_mut_ptr().cast(), samples.len() * 2) }
+}
+
+/// # Safety
+///
+/// `A` and `B` must be the same type.
+unsafe fn cast<A, B>(s: &[A]) -> &[B] {
+    std::slice::from_raw_parts(s.as_ptr().cast(), s.len())
+}
+
+/// # Safety
+///
+/// `A` and `B` must be the same type.
+unsafe fn cast_mut<A, B>(s: &mut [A]) -> &mut [B] {
+    std::slice::from_raw_parts_mut(s.as_mut_ptr().cast(), s.len())
+}
+
+/// Conversions between integer and `f32` scalars.
+///
+/// The bulk of each slice is converted with SIMD where available, and the rest
+/// with the scalar functions, which give identical results.
+mod kernels {
+    const I16_SCALE: f32 = 1.0 / i16::MAX as f32;
+    const I8_SCALE: f32 = 1.0 / i8::MAX as f32;
+
+    pub fn i16_to_f32(src: &[i16], dst: &mut [f32]) {
+        let done = simd::i16_to_f32(src, dst, I16_SCALE);
+        for (d, s) in dst[done..].iter_mut().zip(&src[done..]) {
+            *d = *s as f32 * I16_SCALE;
+        }
+    }
+
+    pub fn i8_to_f32(src: &[i8], dst: &mut [f32]) {
+        let done = simd::i8_to_f32(src, dst, I8_SCALE);
+        for (d, s) in dst[done..].iter_mut().zip(&src[done..]) {
+            *d = *s as f32 * I8_SCALE;
+        }
+    }
+
+    pub fn f32_to_i16(src: &[f32], dst: &mut [i16]) {
+        let done = simd::f32_to_i16(src, dst);
+        for (d, s) in dst[done..].iter_mut().zip(&src[done..]) {
+            *d = to_int(*s, i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+
+    pub fn f32_to_i8(src: &[f32], dst: &mut [i8]) {
+        let done = simd::f32_to_i8(src, dst);
+        for (d, s) in dst[done..].iter_mut().zip(&src[done..]) {
+            *d = to_int(*s, i8::MIN as f32, i8::MAX as f32) as i8;
+        }
+    }
+
+    /// Scale to an integer range, round half away from zero and saturate.
+    pub(super) fn to_int(x: f32, min: f32, max: f32) -> i32 {
+        let v = x * max;
+        (v + 0.5f32.copysign(v)).min(max).max(min) as i32
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    mod simd {
+        use std::arch::x86_64::*;
+
+        // SSE2 is part of the x86-64 baseline, so it is always available.
+
+        pub fn i16_to_f32(src: &[i16], dst: &mut [f32], scale: f32) -> usize {
+            let n = src.len() / 8 * 8;
+            unsafe {
+                let scale = _mm_set1_ps(scale);
+                for i in (0..n).step_by(8) {
+                    let v = _mm_loadu_si128(src.as_ptr().add(i).cast());
+                    let [lo, hi] = widen_i16(v);
+                    let out = dst.as_mut_ptr().add(i);
+                    _mm_storeu_ps(out, _mm_mul_ps(_mm_cvtepi32_ps(lo), scale));
+                    _mm_storeu_ps(out.add(4), _mm_mul_ps(_mm_cvtepi32_ps(hi), scale));
+                }
+            }
+            n
+        }
+
+        pub fn i8_to_f32(src: &[i8], dst: &mut [f32], scale: f32) -> usize {
+            let n = src.len() / 16 * 16;
+            unsafe {
+                let scale = _mm_set1_ps(scale);
+                for i in (0..n).step_by(16) {
+                    let v = _mm_loadu_si128(src.as_ptr().add(i).cast());
+                    let lo = _mm_srai_epi16(_mm_unpacklo_epi8(v, v), 8);
+                    let hi = _mm_srai_epi16(_mm_unpackhi_epi8(v, v), 8);
+                    let [a, b] = widen_i16(lo);
+                    let [c, d] = widen_i16(hi);
+                    let out = dst.as_mut_ptr().add(i);
+                    for (k, v) in [a, b, c, d].into_iter().enumerate() {
+                        _mm_storeu_ps(out.add(4 * k), _mm_mul_ps(_mm_cvtepi32_ps(v), scale));
+                    }
+                }
+            }
+            n
+        }
+
+        pub fn f32_to_i16(src: &[f32], dst: &mut [i16]) -> usize {
+            let n = src.len() / 8 * 8;
+            unsafe {
+                let (min, max) = (i16::MIN as f32, i16::MAX as f32);
+                for i in (0..n).step_by(8) {
+                    let p = src.as_ptr().add(i);
+                    let a = to_int(_mm_loadu_ps(p), min, max);
+                    let b = to_int(_mm_loadu_ps(p.add(4)), min, max);
+                    _mm_storeu_si128(dst.as_mut_ptr().add(i).cast(), _mm_packs_epi32(a, b));
+                }
+            }
+            n
+        }
+
+        pub fn f32_to_i8(src: &[f32], dst: &mut [i8]) -> usize {
+            let n = src.len() / 16 * 16;
+            unsafe {
+                let (min, max) = (i8::MIN as f32, i8::MAX as f32);
+                for i in (0..n).step_by(16) {
+                    let p = src.as_ptr().add(i);
+                    let [a, b, c, d] =
+                        [0, 4, 8, 12].map(|k| to_int(_mm_loadu_ps(p.add(k)), min, max));
+                    let v = _mm_packs_epi16(_mm_packs_epi32(a, b), _mm_packs_epi32(c, d));
+                    _mm_storeu_si128(dst.as_mut_ptr().add(i).cast(), v);
+                }
+            }
+            n
+        }
+
+        /// Sign-extend eight `i16`s to two vectors of `i32`s.
+        unsafe fn widen_i16(v: __m128i) -> [__m128i; 2] {
+            [
+                _mm_srai_epi32(_mm_unpacklo_epi16(v, v), 16),
+                _mm_srai_epi32(_mm_unpackhi_epi16(v, v), 16),
+            ]
+        }
+
+        /// Vectorized [`super::to_int`]. `min_ps` returns its second operand
+        /// for NaN, like `f32::min`, so NaN saturates the same way.
+        unsafe fn to_int(x: __m128, min: f32, max: f32) -> __m128i {
+            let v = _mm_mul_ps(x, _mm_set1_ps(max));
+            let half = _mm_or_ps(_mm_and_ps(v, _mm_set1_ps(-0.0)), _mm_set1_ps(0.5));
+            let v = _mm_add_ps(v, half);
+            let v = _mm_max_ps(_mm_min_ps(v, _mm_set1_ps(max)), _mm_set1_ps(min));
+            _mm_cvttps_epi32(v)
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    mod simd {
+        pub fn i16_to_f32(_src: &[i16], _dst: &mut [f32], _scale: f32) -> usize {
+            0
+        }
+
+        pub fn i8_to_f32(_src: &[i8], _dst: &mut [f32], _scale: f32) -> usize {
+            0
+        }
+
+        pub fn f32_to_i16(_src: &[f32], _dst: &mut [i16]) -> usize {
+            0
+        }
+
+        pub fn f32_to_i8(_src: &[f32], _dst: &mut [i8]) -> usize {
+            0
+        }
+    }
+}
+
+/// An RX stream reader which converts samples into another format.
+///
+/// Samples are received into a scratch buffer in the stream's format and
+/// converted into the caller's buffer, which may have any number of samples
+/// per channel. At most [`RxStream::max_samples_per_channel`](crate::RxStream::max_samples_per_channel)
+/// samples are received per call.
+///
+/// Created by [`RxStreamReader::into_converting`].
+pub struct ConvertingReader<'stream, 'md, S: Convertible, D: Convertible> {
+    reader: RxStreamReader<'stream, 'md, S>,
+    scratch: ArrayBuffer<S>,
+    _phantom: PhantomData<D>,
+}
+
+impl<'stream, 'md, S, D> ConvertingReader<'stream, 'md, S, D>
+where
+    S: Convertible + Default,
+    D: Convertible,
+{
+    pub fn new(reader: RxStreamReader<'stream, 'md, S>) -> Self {
+        let stream = reader.stream();
+        let scratch = ArrayBuffer::new(stream.channels(), stream.max_samples_per_channel());
+        Self {
+            reader,
+            scratch,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Access the wrapped reader, e.g. to set its timeout.
+    pub fn reader_mut(&mut self) -> &mut RxStreamReader<'stream, 'md, S> {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> RxStreamReader<'stream, 'md, S> {
+        self.reader
+    }
+
+    /// Receive and convert samples, returning the number of samples received per channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Index`] if the buffer has a different number of
+    /// channels than the stream.
+    pub fn recv(&mut self, buff: &mut impl SampleBuffer<D>) -> Result<usize> {
+        if buff.channels() != self.scratch.channels() {
+            return Err(UhdError::Index);
+        }
+        let samples = buff.samples().min(self.scratch.samples());
+        let received = unsafe { self.reader.recv_raw(self.scratch.as_mut_ptr(), samples)? };
+        let ptrs = buff.as_mut_ptr();
+        for (c, src) in self.scratch.iter_channels().enumerate() {
+            // Safety: the buffer has as many channels as the scratch buffer, each
+            // of at least `samples` samples.
+            let dst = unsafe { std::slice::from_raw_parts_mut(*ptrs.add(c), received) };
+            convert(&src[..received], dst);
+        }
+        Ok(received)
+    }
+}
+
+impl<'stream, 'md, S> RxStreamReader<'stream, 'md, S>
+where
+    S: Convertible + Default,
+{
+    /// Wrap the reader to convert received samples into another format.
+    pub fn into_converting<D: Convertible>(self) -> ConvertingReader<'stream, 'md, S, D> {
+        ConvertingReader::new(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Values around rounding and saturation boundaries, plus a sweep.
+    fn floats() -> Vec<f32> {
+        let mut values = vec![
+            0.0,
+            -0.0,
+            1.0,
+            -1.0,
+            1.5,
+            -1.5,
+            0.5 / 32767.0,
+            -0.5 / 32767.0,
+            1.5 / 32767.0,
+            0.5 / 127.0,
+            -2.5 / 127.0,
+            f32::MAX,
+            f32::MIN,
+            f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+        ];
+        values.extend((0..1000).map(|i| ((i as f32) * 0.7331).sin() * 1.1));
+        values
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        let src = floats();
+        let mut i16s = vec![0i16; src.len()];
+        kernels::f32_to_i16(&src, &mut i16s);
+        let mut i8s = vec![0i8; src.len()];
+        kernels::f32_to_i8(&src, &mut i8s);
+        for (k, &x) in src.iter().enumerate() {
+            let expected = kernels::to_int(x, i16::MIN as f32, i16::MAX as f32) as i16;
+            assert_eq!(i16s[k], expected, "{x}");
+            let expected = kernels::to_int(x, i8::MIN as f32, i8::MAX as f32) as i8;
+            assert_eq!(i8s[k], expected, "{x}");
+        }
+
+        let ints: Vec<i16> = (i16::MIN..=i16::MAX).step_by(7).collect();
+        let mut floats = vec![0f32; ints.len()];
+        kernels::i16_to_f32(&ints, &mut floats);
+        for (i, f) in ints.iter().zip(&floats) {
+            assert_eq!(*f, *i as f32 * (1.0 / 32767.0));
+        }
+        let ints: Vec<i8> = (i8::MIN..=i8::MAX).collect();
+        let mut floats = vec![0f32; ints.len()];
+        kernels::i8_to_f32(&ints, &mut floats);
+        for (i, f) in ints.iter().zip(&floats) {
+            assert_eq!(*f, *i as f32 * (1.0 / 127.0));
+        }
+    }
+
+    #[test]
+    fn follows_uhd_scaling() {
+        let src = [[i16::MAX, -i16::MAX], [16384, 0], [i16::MIN, 1]];
+        let mut dst = [[0f32; 2]; 3];
+        convert(&src, &mut dst);
+        assert_eq!(dst[0], [1.0, -1.0]);
+        assert!((dst[1][0] - 0.5).abs() < 1e-4);
+        assert!(dst[2][0] < -1.0);
+
+        let mut back = [[0i16; 2]; 3];
+        convert(&dst, &mut back);
+        assert_eq!(back, src);
+
+        let src = [[1.2f32, -1.2], [0.5, -0.5], [f32::NAN, 0.0]];
+        let mut dst = [[0i16; 2]; 3];
+        convert(&src, &mut dst);
+        assert_eq!(dst[0], [i16::MAX, i16::MIN]);
+        assert_eq!(dst[1], [16384, -16384]);
+        let mut dst = [[0i8; 2]; 3];
+        convert(&src, &mut dst);
+        assert_eq!(dst[0], [i8::MAX, i8::MIN]);
+        assert_eq!(dst[1], [64, -64]);
+    }
+
+    #[test]
+    fn agrees_with_iq_sample() {
+        let src: Vec<[f32; 2]> = floats()
+            .chunks_exact(2)
+            .filter(|c| c.iter().all(|x| x.is_finite()))
+            .map(|c| [c[0], c[1]])
+            .collect();
+        let mut i16s = vec![[0i16; 2]; src.len()];
+        convert(&src, &mut i16s);
+        let mut f64s = vec![[0f64; 2]; src.len()];
+        convert(&i16s, &mut f64s);
+        let mut i8s = vec![[0i8; 2]; src.len()];
+        convert(&i16s, &mut i8s);
+        for k in 0..src.len() {
+            let expected = <[i16; 2]>::from_iq(src[k].to_iq());
+            for c in 0..2 {
+                assert!((i16s[k][c] as i32 - expected[c] as i32).abs() <= 1);
+            }
+            assert_eq!(f64s[k], i16s[k].to_iq());
+            assert_eq!(i8s[k], <[i8; 2]>::from_iq(i16s[k].to_iq()));
+        }
+    }
+
+    #[cfg(feature = "num")]
+    #[test]
+    fn converts_complex() {
+        use num_complex::{Complex, Complex32, Complex64};
+
+        let src: Vec<Complex<i16>> = (0..37).map(|i| Complex::new(i * 100, -i * 50)).collect();
+        let mut c32 = vec![Complex32::default(); src.len()];
+        convert(&src, &mut c32);
+        let mut c64 = vec![Complex64::default(); src.len()];
+        convert(&c32, &mut c64);
+        let mut arrays = vec![[0i16; 2]; src.len()];
+        convert(&c64, &mut arrays);
+        for k in 0..src.len() {
+            assert_eq!(arrays[k], [src[k].re, src[k].im]);
+            assert!((c64[k].re - src[k].re as f64 / 32767.0).abs() < 1e-6);
+        }
+        let mut same = vec![Complex::<i16>::default(); src.len()];
+        convert(&arrays, &mut same);
+        assert_eq!(same, src);
+    }
+
+    #[test]
+    #[should_panic]
+    fn length_mismatch_panics() {
+        convert(&[[0i16; 2]; 3], &mut [[0f32; 2]; 2]);
+    }
+}
@@ -0,0 +1,208 @@
+//! Estimation and correction of DC offset and IQ imbalance.
+//!
+//! UHD can correct DC offset and IQ imbalance automatically (see
+//! [`ChannelConfig::set_dc_offset_enabled`](crate::ChannelConfig::set_dc_offset_enabled)
+//! and [`ChannelConfig::set_iq_balance_enabled`](crate::ChannelConfig::set_iq_balance_enabled)),
+//! but its C API has no way of setting or reading explicit correction values.
+//! An [`IqCorrection`] is instead estimated from a block of received samples
+//! and applied to samples on the host.
+//!
+//! # Examples
+//!
+//! Estimate the corrections from a test tone offset from the center frequency,
+//! then correct subsequent blocks:
+//!
+//! ```no_run
+//! use num_complex::Complex32;
+//! use uhd_usrp::{correction::IqCorrection, Usrp};
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let mut stream = usrp.rx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+//! let mut buf = vec![Complex32::default(); 100_000];
+//! stream.start_command().send().unwrap();
+//!
+//! let n = stream.reader().recv(&mut buf).unwrap();
+//! let correction = IqCorrection::estimate(&buf[..n]).unwrap();
+//! let irr = IqCorrection::image_rejection(&buf[..n]).unwrap();
+//! println!("image rejection before correction: {irr:.1} dB");
+//!
+//! let n = stream.reader().recv(&mut buf).unwrap();
+//! correction.apply(&mut buf[..n]);
+//! ```
+
+use num_complex::Complex64;
+
+use crate::IqSample;
+
+/// Smallest block from which corrections are estimated.
+const MIN_SAMPLES: usize = 16;
+
+/// An error which occurred while estimating corrections.
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum CorrectionError {
+    #[error("at least {MIN_SAMPLES} samples are needed, got {0}")]
+    TooFewSamples(usize),
+    #[error("the samples contain no signal apart from DC")]
+    NoSignal,
+}
+
+/// DC offset and IQ imbalance corrections.
+///
+/// A sample `x` is corrected by first removing the DC offset, then adding
+/// a scaled copy of its complex conjugate to cancel the image:
+///
+/// ```text
+/// y = (x - dc_offset) + iq_balance * conj(x - dc_offset)
+/// ```
+///
+/// The default value applies no correction.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IqCorrection {
+    pub dc_offset: Complex64,
+    pub iq_balance: Complex64,
+}
+
+impl IqCorrection {
+    pub fn new(dc_offset: Complex64, iq_balance: Complex64) -> Self {
+        Self {
+            dc_offset,
+            iq_balance,
+        }
+    }
+
+    /// Estimate the corrections from a block of received samples.
+    ///
+    /// The block should contain a single tone away from DC, such as a test
+    /// signal offset from the center frequency, and span many periods of it.
+    /// The DC offset is estimated as the mean of the samples, and the IQ
+    /// imbalance from the correlation between the samples and their conjugates,
+    /// which is zero for a perfectly balanced receiver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block is too short or contains only DC.
+    pub fn estimate<T: IqSample>(samples: &[T]) -> Result<Self, CorrectionError> {
+        let dc_offset = mean(samples)?;
+        let rho = improperness(samples, dc_offset)?;
+        // The samples are x = a*s + b*conj(s) for a tone s. Cancelling the image
+        // requires iq_balance = -b/conj(a), which is found exactly from
+        // rho = E[x^2] / E[|x|^2] = 2z / (1 + |z|^2) where z = b/conj(a).
+        let z = rho / (1.0 + (1.0 - rho.norm_sqr()).max(0.0).sqrt());
+        Ok(Self {
+            dc_offset,
+            iq_balance: -z,
+        })
+    }
+
+    /// Measure the image rejection ratio of a block of samples in dB.
+    ///
+    /// The block should contain a single tone away from DC, see [`Self::estimate`].
+    pub fn image_rejection<T: IqSample>(samples: &[T]) -> Result<f64, CorrectionError> {
+        let z = Self::estimate(samples)?.iq_balance;
+        Ok(-20.0 * z.norm().log10())
+    }
+
+    /// Correct a single sample.
+    pub fn correct(&self, x: Complex64) -> Complex64 {
+        let x = x - self.dc_offset;
+        x + self.iq_balance * x.conj()
+    }
+
+    /// Correct a block of samples in place.
+    pub fn apply<T: IqSample>(&self, samples: &mut [T]) {
+        for sample in samples {
+            let [re, im] = sample.to_iq();
+            let y = self.correct(Complex64::new(re, im));
+            *sample = T::from_iq([y.re, y.im]);
+        }
+    }
+}
+
+fn mean<T: IqSample>(samples: &[T]) -> Result<Complex64, CorrectionError> {
+    if samples.len() < MIN_SAMPLES {
+        return Err(CorrectionError::TooFewSamples(samples.len()));
+    }
+    let sum: Complex64 = samples
+        .iter()
+        .map(|s| {
+            let [re, im] = s.to_iq();
+            Complex64::new(re, im)
+        })
+        .sum();
+    Ok(sum / samples.len() as f64)
+}
+
+/// The ratio `E[x^2] / E[|x|^2]` of the samples with the DC offset removed.
+fn improperness<T: IqSample>(samples: &[T], dc: Complex64) -> Result<Complex64, CorrectionError> {
+    let (square, power) = samples
+        .iter()
+        .fold((Complex64::default(), 0.0), |(square, power), s| {
+            let [re, im] = s.to_iq();
+            let x = Complex64::new(re, im) - dc;
+            (square + x * x, power + x.norm_sqr())
+        });
+    if power <= f64::EPSILON * samples.len() as f64 {
+        return Err(CorrectionError::NoSignal);
+    }
+    Ok(square / power)
+}
+
+#[cfg(test)]
+mod test {
+    use num_complex::Complex32;
+
+    use super::*;
+
+    /// A tone with the given gain and phase imbalance between I and Q, plus a DC offset.
+    fn tone(len: usize, gain: f64, phase: f64, dc: Complex64) -> Vec<Complex32> {
+        (0..len)
+            .map(|n| {
+                let theta = 2.0 * std::f64::consts::PI * 0.0123 * n as f64;
+                let i = 0.5 * theta.cos() + dc.re;
+                let q = 0.5 * gain * (theta + phase).sin() + dc.im;
+                Complex32::new(i as f32, q as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn balanced_tone() {
+        let samples = tone(10_000, 1.0, 0.0, Complex64::default());
+        let correction = IqCorrection::estimate(&samples).unwrap();
+        assert!(correction.dc_offset.norm() < 1e-3);
+        assert!(correction.iq_balance.norm() < 1e-3);
+        assert!(IqCorrection::image_rejection(&samples).unwrap() > 60.0);
+    }
+
+    #[test]
+    fn corrects_imbalance() {
+        let dc = Complex64::new(0.05, -0.02);
+        let mut samples = tone(10_000, 1.1, 0.05, dc);
+        let before = IqCorrection::image_rejection(&samples).unwrap();
+        assert!(before < 30.0, "{before}");
+
+        let correction = IqCorrection::estimate(&samples).unwrap();
+        assert!((correction.dc_offset - dc).norm() < 1e-3);
+        correction.apply(&mut samples);
+
+        let after = IqCorrection::estimate(&samples).unwrap();
+        assert!(after.dc_offset.norm() < 1e-3);
+        assert!(IqCorrection::image_rejection(&samples).unwrap() > 60.0);
+    }
+
+    #[test]
+    fn errors() {
+        let samples = tone(8, 1.0, 0.0, Complex64::default());
+        assert_eq!(
+            IqCorrection::estimate(&samples),
+            Err(CorrectionError::TooFewSamples(8))
+        );
+        let dc = vec![Complex32::new(0.1, 0.1); 100];
+        assert_eq!(IqCorrection::estimate(&dc), Err(CorrectionError::NoSignal));
+        let mut samples = tone(100, 1.0, 0.0, Complex64::default());
+        let before = samples.clone();
+        IqCorrection::default().apply(&mut samples);
+        assert_eq!(samples, before);
+    }
+}
@@ -5,7 +5,7 @@ use uhd_usrp_sys::uhd_error;
 pub type Result<T, E = UhdError> = std::result::Result<T, E>;
 
 /// An error that occurred during a UHD operation.
-#[derive(thiserror::Error, Debug, Clone)]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
 #[repr(u32)]
 pub enum UhdError {
     #[error("invalid device arguments")]
@@ -128,6 +128,35 @@ impl FfiString {
         self.s.len()
     }
 
+    /// Returns `true` if the string filled the whole buffer without a null
+    /// terminator, which means it was most likely truncated.
+    pub fn is_truncated(&self) -> bool {
+        !self.s.contains(&0)
+    }
+
+    /// Receive a string of unknown length, retrying with larger buffers
+    /// until it fits.
+    ///
+    /// `f` is called with the buffer to fill, and must not fail due to
+    /// the buffer being too short.
+    pub fn read_growing(
+        initial: usize,
+        max: usize,
+        mut f: impl FnMut(&mut FfiString) -> Result<()>,
+    ) -> Result<String> {
+        let mut buf = FfiString::with_capacity(initial);
+        loop {
+            f(&mut buf)?;
+            if !buf.is_truncated() {
+                return buf.to_string();
+            }
+            if buf.max_chars() >= max {
+                return Err(UhdError::Value);
+            }
+            buf = FfiString::with_capacity((buf.max_chars() * 2).min(max));
+        }
+    }
+
     /// Convert this struct into a string.
     ///
     /// Returns an error if the string is not valid UTF-8, or
@@ -181,7 +210,8 @@ impl FfiStringVec {
     ///
     /// Returns `None` if the index is out of bounds.
     pub fn get(&self, index: usize) -> Option<String> {
-        let mut s = FfiString::with_capacity(128);
+        // Device address strings returned by `uhd_usrp_find` can be fairly long.
+        let mut s = FfiString::with_capacity(512);
         try_uhd!(unsafe {
             uhd_usrp_sys::uhd_string_vector_at(
                 self.handle.as_mut_ptr(),
@@ -0,0 +1,508 @@
+//! Frequency hopping using timed tune commands.
+//!
+//! A [`HopSchedule`] places a list of hops in device time. A [`HopScheduler`]
+//! issues a timed tune command for each hop ahead of the device time, so that
+//! the device retunes at the start of each hop regardless of host jitter, and
+//! tags received blocks with the hop which was active when they were received.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use num_complex::Complex32;
+//! use uhd_usrp::{
+//!     hopping::{Hop, HopSchedule, HopScheduler},
+//!     timespec, Channel, RxMetadata, Usrp,
+//! };
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let start = usrp.mboard(0).time().unwrap() + timespec!(500 ms);
+//! let hops = vec![
+//!     Hop::new(915e6, timespec!(10 ms)),
+//!     Hop::new(920e6, timespec!(10 ms)),
+//!     Hop::new(925e6, timespec!(20 ms)),
+//! ];
+//! let schedule = HopSchedule::new(start, hops).unwrap().with_looping(true);
+//! let mut scheduler = HopScheduler::new(usrp.channel(Channel::Rx(0)).unwrap(), schedule);
+//!
+//! let mut stream = usrp.rx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+//! let mut buf = vec![Complex32::default(); 1000];
+//! let mut md = RxMetadata::new();
+//! stream.start_command().with_time(start).send().unwrap();
+//! loop {
+//!     let report = scheduler.poll().unwrap();
+//!     for hop in &report.late {
+//!         eprintln!("hop {} was tuned late", hop.seq);
+//!     }
+//!     let n = stream
+//!         .reader()
+//!         .with_timeout(Duration::from_secs(1))
+//!         .with_metadata_output(&mut md)
+//!         .recv(&mut buf)
+//!         .unwrap();
+//!     if let Some(hop) = scheduler.tag(&md) {
+//!         println!("{n} samples at {} Hz", hop.freq);
+//!     }
+//! }
+//! ```
+
+use std::ops::Range;
+
+use crate::{ChannelConfig, CommandTimeError, RxMetadata, TimeSpec, UhdError};
+
+/// How far ahead of the device time tune commands are issued by default.
+const DEFAULT_LEAD_TIME: TimeSpec = TimeSpec::from_parts_unchecked(0, 0.1);
+
+/// An error which occurred while scheduling hops.
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum HopError {
+    #[error("the hop list is empty")]
+    Empty,
+    #[error("hop {index} has a dwell time which is not positive")]
+    InvalidDwell { index: usize },
+    #[error(transparent)]
+    CommandTime(#[from] CommandTimeError),
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+/// A frequency to tune to and how long to stay there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hop {
+    /// The center frequency in Hz.
+    pub freq: f64,
+    pub dwell: TimeSpec,
+}
+
+impl Hop {
+    pub fn new(freq: f64, dwell: TimeSpec) -> Self {
+        Self { freq, dwell }
+    }
+}
+
+/// A hop placed in time by a [`HopSchedule`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledHop {
+    /// The number of hops scheduled before this one.
+    pub seq: u64,
+    /// The position of the hop in the hop list.
+    pub index: usize,
+    /// The center frequency in Hz.
+    pub freq: f64,
+    /// The device time at which the hop starts.
+    pub start: TimeSpec,
+    /// The device time at which the next hop starts.
+    pub end: TimeSpec,
+}
+
+/// A list of hops starting at a given device time.
+///
+/// The hops follow each other without gaps. Once the list ends the schedule
+/// either ends too, or starts over from the first hop if it is looped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HopSchedule {
+    hops: Vec<Hop>,
+    /// The offset of each hop from the start of the list.
+    offsets: Vec<TimeSpec>,
+    period: TimeSpec,
+    start: TimeSpec,
+    looped: bool,
+}
+
+impl HopSchedule {
+    /// Schedule a list of hops, the first of which starts at the given device time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the list is empty or a hop's dwell time isn't positive.
+    pub fn new(start: TimeSpec, hops: Vec<Hop>) -> Result<Self, HopError> {
+        if hops.is_empty() {
+            return Err(HopError::Empty);
+        }
+        let mut offsets = Vec::with_capacity(hops.len());
+        let mut period = TimeSpec::ZERO;
+        for (index, hop) in hops.iter().enumerate() {
+            if hop.dwell <= TimeSpec::ZERO {
+                return Err(HopError::InvalidDwell { index });
+            }
+            offsets.push(period);
+            period += hop.dwell;
+        }
+        Ok(Self {
+            hops,
+            offsets,
+            period,
+            start,
+            looped: false,
+        })
+    }
+
+    /// Start over from the first hop once the list ends, indefinitely.
+    pub fn with_looping(mut self, looped: bool) -> Self {
+        self.looped = looped;
+        self
+    }
+
+    pub fn hops(&self) -> &[Hop] {
+        &self.hops
+    }
+
+    /// The device time at which the first hop starts.
+    pub fn start(&self) -> TimeSpec {
+        self.start
+    }
+
+    /// The total dwell time of the hop list.
+    pub fn period(&self) -> TimeSpec {
+        self.period
+    }
+
+    pub fn is_looped(&self) -> bool {
+        self.looped
+    }
+
+    /// Get the hop with the given sequence number.
+    ///
+    /// Returns `None` if the schedule ends before it.
+    pub fn hop(&self, seq: u64) -> Option<ScheduledHop> {
+        let n = self.hops.len() as u64;
+        let cycle = seq / n;
+        if cycle > 0 && !self.looped {
+            return None;
+        }
+        let index = (seq % n) as usize;
+        let start = self.start + self.period * cycle + self.offsets[index];
+        Some(ScheduledHop {
+            seq,
+            index,
+            freq: self.hops[index].freq,
+            start,
+            end: start + self.hops[index].dwell,
+        })
+    }
+
+    /// Get the hop which is active at the given device time.
+    ///
+    /// Returns `None` before the schedule starts or after it ends.
+    pub fn hop_at(&self, time: TimeSpec) -> Option<ScheduledHop> {
+        if time < self.start {
+            return None;
+        }
+        let elapsed = time - self.start;
+        let mut cycle = (elapsed / self.period).floor() as u64;
+        let mut offset = elapsed - self.period * cycle;
+        // Correct for rounding errors at the boundaries between cycles.
+        if offset < TimeSpec::ZERO && cycle > 0 {
+            cycle -= 1;
+            offset += self.period;
+        } else if offset >= self.period {
+            cycle += 1;
+            offset -= self.period;
+        }
+        let index = self.offsets.partition_point(|o| *o <= offset).max(1) - 1;
+        self.hop(cycle * self.hops.len() as u64 + index as u64)
+    }
+
+    /// Split a block of samples into the hops active while they were received.
+    ///
+    /// `time` is the device time of the first sample, and `rate` the sample rate.
+    /// Samples outside of the schedule are returned with `None`.
+    pub fn split(
+        &self,
+        time: TimeSpec,
+        samples: usize,
+        rate: f64,
+    ) -> Vec<(Range<usize>, Option<ScheduledHop>)> {
+        // The first sample at or after a device time.
+        let index_at = |t: TimeSpec| {
+            let index = ((t - time).as_secs() * rate - 1e-6).ceil().max(0.0) as usize;
+            index.min(samples)
+        };
+        let mut segments = Vec::new();
+        let mut i = 0;
+        let mut hop = self.hop_at(time);
+        if hop.is_none() && time < self.start {
+            i = index_at(self.start);
+            if i > 0 {
+                segments.push((0..i, None));
+            }
+            hop = self.hop(0);
+        }
+        while i < samples {
+            match hop {
+                Some(h) => {
+                    let end = index_at(h.end).max(i);
+                    if end > i {
+                        segments.push((i..end, hop));
+                    }
+                    i = end;
+                    hop = self.hop(h.seq + 1);
+                }
+                None => {
+                    segments.push((i..samples, None));
+                    break;
+                }
+            }
+        }
+        segments
+    }
+
+    /// Plan which hops to issue, given the next hop to issue, the device time
+    /// and how far ahead of it to issue hops.
+    ///
+    /// Returns the number of hops which ended before they could be issued,
+    /// and the hops to issue.
+    fn plan(&self, next: u64, now: TimeSpec, lead: TimeSpec) -> (u64, Vec<ScheduledHop>) {
+        let mut seq = match self.hop_at(now) {
+            Some(current) if current.seq > next => current.seq,
+            _ => next,
+        };
+        while let Some(hop) = self.hop(seq) {
+            if hop.end > now {
+                break;
+            }
+            seq += 1;
+        }
+        let until = now + lead;
+        let due = (seq..)
+            .map_while(|seq| self.hop(seq))
+            .take_while(|hop| hop.start < until)
+            .collect();
+        (seq - next, due)
+    }
+}
+
+/// The outcome of [`HopScheduler::poll`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HopReport {
+    /// The hops whose tune commands were issued.
+    pub issued: Vec<ScheduledHop>,
+    /// Issued hops which had already started by the time their commands were sent.
+    ///
+    /// The device tunes to these as soon as it receives their commands.
+    pub late: Vec<ScheduledHop>,
+    /// The number of hops which ended before they could be issued, and were skipped.
+    pub skipped: u64,
+}
+
+/// Issues timed tune commands for a [`HopSchedule`] on a channel.
+///
+/// [`HopScheduler::poll`] should be called regularly, for example between
+/// receives. Each call issues a timed tune command for every hop starting
+/// within the lead time of the current device time. The lead time must be
+/// longer than the time between polls, but short enough that the commands
+/// fit in the device's command queue.
+///
+/// UHD doesn't report when a timed command arrives after its time. Instead,
+/// the device time is read back after the commands are issued, and hops
+/// which had started by then are reported as late.
+pub struct HopScheduler<'a> {
+    channel: ChannelConfig<'a>,
+    schedule: HopSchedule,
+    mboard: usize,
+    lead_time: TimeSpec,
+    next: u64,
+}
+
+impl<'a> HopScheduler<'a> {
+    pub fn new(channel: ChannelConfig<'a>, schedule: HopSchedule) -> Self {
+        Self {
+            channel,
+            schedule,
+            mboard: 0,
+            lead_time: DEFAULT_LEAD_TIME,
+            next: 0,
+        }
+    }
+
+    /// How far ahead of the device time to issue tune commands (100 ms by default).
+    pub fn with_lead_time(mut self, lead_time: TimeSpec) -> Self {
+        self.lead_time = lead_time;
+        self
+    }
+
+    /// The motherboard of the channel, whose time the schedule is in (0 by default).
+    ///
+    /// [`HopScheduler::poll`] returns [`CommandTimeError::WrongMboard`] if the
+    /// channel is on another motherboard.
+    pub fn with_mboard(mut self, mboard: usize) -> Self {
+        self.mboard = mboard;
+        self
+    }
+
+    pub fn schedule(&self) -> &HopSchedule {
+        &self.schedule
+    }
+
+    /// The next hop whose tune command will be issued.
+    pub fn next_hop(&self) -> Option<ScheduledHop> {
+        self.schedule.hop(self.next)
+    }
+
+    /// Returns true once every hop of the schedule has been issued or skipped.
+    pub fn is_finished(&self) -> bool {
+        self.next_hop().is_none()
+    }
+
+    /// Issue the tune commands for the hops starting within the lead time.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CommandTimeError::Unsupported`] if the device does not support
+    /// timed commands, [`CommandTimeError::WrongMboard`] if the channel is not on
+    /// the scheduler's motherboard, or an error from reading the device time or tuning.
+    pub fn poll(&mut self) -> Result<HopReport, HopError> {
+        let mboard = self.channel.usrp.mboard(self.mboard);
+        let now = mboard.time()?;
+        let (skipped, due) = self.schedule.plan(self.next, now, self.lead_time);
+        self.next += skipped;
+
+        let mut report = HopReport {
+            skipped,
+            ..HopReport::default()
+        };
+        for hop in due {
+            let guard = self.channel.usrp.at_time(hop.start, self.mboard)?;
+            guard
+                .channel(self.channel.channel)?
+                .set_center_freq(hop.freq)?;
+            guard.clear()?;
+            self.next = hop.seq + 1;
+            report.issued.push(hop);
+        }
+        if !report.issued.is_empty() {
+            let now = mboard.time()?;
+            report.late = late_hops(&report.issued, now);
+        }
+        Ok(report)
+    }
+
+    /// Get the hop which was active when a block was received.
+    ///
+    /// Returns `None` if the block has no time or is outside of the schedule.
+    /// Use [`HopSchedule::split`] for blocks which may span several hops.
+    pub fn tag(&self, md: &RxMetadata) -> Option<ScheduledHop> {
+        self.schedule.hop_at(md.time_spec()?)
+    }
+}
+
+/// The hops which had started by the given device time.
+fn late_hops(issued: &[ScheduledHop], time: TimeSpec) -> Vec<ScheduledHop> {
+    issued
+        .iter()
+        .filter(|hop| hop.start <= time)
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::timespec;
+
+    fn schedule() -> HopSchedule {
+        let hops = vec![
+            Hop::new(1e9, timespec!(10 ms)),
+            Hop::new(2e9, timespec!(20 ms)),
+            Hop::new(3e9, timespec!(10 ms)),
+        ];
+        HopSchedule::new(timespec!(5 s), hops).unwrap()
+    }
+
+    fn ms(millis: i64) -> TimeSpec {
+        TimeSpec::from_secs(5) + TimeSpec::from_millis(millis)
+    }
+
+    fn assert_close(a: TimeSpec, b: TimeSpec) {
+        assert!((a - b).abs() < timespec!(1 ns), "{a} != {b}");
+    }
+
+    #[test]
+    fn places_hops() {
+        let schedule = schedule();
+        assert_close(schedule.period(), timespec!(40 ms));
+        let hop = schedule.hop(1).unwrap();
+        assert_eq!((hop.index, hop.freq), (1, 2e9));
+        assert_close(hop.start, ms(10));
+        assert_close(hop.end, ms(30));
+        assert_eq!(schedule.hop(3), None);
+
+        let schedule = schedule.with_looping(true);
+        let hop = schedule.hop(7).unwrap();
+        assert_eq!(hop.index, 1);
+        assert_close(hop.start, ms(90));
+    }
+
+    #[test]
+    fn finds_active_hop() {
+        let schedule = schedule();
+        assert_eq!(schedule.hop_at(ms(-1)), None);
+        assert_eq!(schedule.hop_at(ms(0)).unwrap().seq, 0);
+        assert_eq!(schedule.hop_at(ms(10)).unwrap().seq, 1);
+        assert_eq!(schedule.hop_at(ms(29)).unwrap().seq, 1);
+        assert_eq!(schedule.hop_at(ms(35)).unwrap().seq, 2);
+        assert_eq!(schedule.hop_at(ms(40)), None);
+
+        let schedule = schedule.with_looping(true);
+        assert_eq!(schedule.hop_at(ms(40)).unwrap().seq, 3);
+        assert_eq!(schedule.hop_at(ms(4075)).unwrap().seq, 305);
+    }
+
+    #[test]
+    fn splits_blocks() {
+        let schedule = schedule();
+        // 1 kHz sample rate, starting 5 samples before the schedule.
+        let segments = schedule.split(ms(-5), 50, 1e3);
+        let seqs: Vec<_> = segments
+            .iter()
+            .map(|(r, hop)| (r.clone(), hop.map(|h| h.seq)))
+            .collect();
+        assert_eq!(
+            seqs,
+            [
+                (0..5, None),
+                (5..15, Some(0)),
+                (15..35, Some(1)),
+                (35..45, Some(2)),
+                (45..50, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn plans_ahead() {
+        let schedule = schedule().with_looping(true);
+        let (skipped, due) = schedule.plan(0, ms(-50), timespec!(85 ms));
+        assert_eq!(skipped, 0);
+        assert_eq!(due.iter().map(|h| h.seq).collect::<Vec<_>>(), [0, 1, 2]);
+
+        // Hops which already ended are skipped, the current one is still issued.
+        let (skipped, due) = schedule.plan(3, ms(95), timespec!(20 ms));
+        assert_eq!(skipped, 4);
+        assert_eq!(due.iter().map(|h| h.seq).collect::<Vec<_>>(), [7, 8]);
+
+        let schedule = schedule.with_looping(false);
+        let (skipped, due) = schedule.plan(1, ms(100), timespec!(20 ms));
+        assert_eq!((skipped, due.len()), (2, 0));
+
+        let late = late_hops(&schedule.plan(0, ms(15), timespec!(20 ms)).1, ms(15));
+        assert_eq!(late.iter().map(|h| h.seq).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn rejects_invalid_hops() {
+        assert!(matches!(
+            HopSchedule::new(TimeSpec::ZERO, vec![]),
+            Err(HopError::Empty)
+        ));
+        let hops = vec![
+            Hop::new(1e9, timespec!(1 ms)),
+            Hop::new(2e9, TimeSpec::ZERO),
+        ];
+        assert!(matches!(
+            HopSchedule::new(TimeSpec::ZERO, hops),
+            Err(HopError::InvalidDwell { index: 1 })
+        ));
+    }
+}
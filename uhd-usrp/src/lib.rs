@@ -63,19 +63,43 @@
 //!     // Do something with the samples
 //! }
 //! ```
+//!
+//! ## Concurrency
+//!
+//! - [`Usrp`] is `Send` but not `Sync`: it can be opened on one thread and moved to
+//!   another, but only one thread can use it at a time. Views borrowed from it, such
+//!   as [`ChannelConfig`] and [`Motherboard`], stay on that thread.
+//! - [`SharedUsrp`] wraps a `Usrp` in a lock. Put it in an `Arc` to configure the
+//!   device from several threads; control calls are serialized by the lock.
+//! - [`RxStream`] and [`TxStream`] are `Send` but not `Sync`. They don't borrow the
+//!   `Usrp`, so a stream can be opened on a control thread, moved to a streaming
+//!   thread, and keep running while the device is retuned.
 
 mod buffer;
+#[cfg(feature = "num")]
+pub mod calibration;
+pub mod convert;
+#[cfg(feature = "num")]
+pub mod correction;
 mod error;
 pub(crate) mod ffi;
+pub mod hopping;
 pub mod logging;
+pub mod monitor;
+pub mod playback;
+#[cfg(feature = "profile")]
+pub mod profile;
 mod sample;
+#[cfg(feature = "sigmf")]
+pub mod sigmf;
+pub mod sim;
 pub mod types;
 pub mod usrp;
 
 pub(crate) use crate::error::try_uhd;
 pub use buffer::{ArrayBuffer, SampleBuffer};
 pub use error::{last_error_message, Result, UhdError};
-pub use sample::Sample;
+pub use sample::{IqSample, Sample};
 pub use types::{DeviceArgs, RxMetadata, TimeSpec, TxMetadata, TxMetadataBuilder};
 pub use usrp::*;
 
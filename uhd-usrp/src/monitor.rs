@@ -0,0 +1,554 @@
+//! Periodic monitoring of device sensors.
+//!
+//! A [`SensorMonitor`] reads every motherboard and frontend sensor of a
+//! device at a configurable interval, keeps a short history of each, and
+//! reports changes such as an LO losing lock as [`SensorEvent`]s.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::{sync::atomic::AtomicBool, time::Duration};
+//! use uhd_usrp::{monitor::{SensorEvent, SensorMonitor}, Usrp};
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let stop = AtomicBool::new(false);
+//! let mut monitor = SensorMonitor::new()
+//!     .with_default_interval(Duration::from_secs(10))
+//!     .with_interval("lo_locked", Duration::from_millis(500))
+//!     .with_callback(|event| {
+//!         if let SensorEvent::Changed { key, current, .. } = event {
+//!             println!("{key} is now {current}");
+//!         }
+//!     });
+//! monitor.run(&usrp, &stop).unwrap();
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    types::{SensorValue, SensorValueValue},
+    Channel, Result, UhdError, Usrp,
+};
+
+/// Longest time [`SensorMonitor::run`] sleeps before checking whether to stop.
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+/// Where a sensor is located.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SensorLocation {
+    Mboard(usize),
+    Channel(Channel),
+}
+
+impl Display for SensorLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SensorLocation::Mboard(i) => write!(f, "mboard {i}"),
+            SensorLocation::Channel(c) => write!(f, "{c}"),
+        }
+    }
+}
+
+/// Identifies a single sensor of a device.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SensorKey {
+    pub location: SensorLocation,
+    pub name: String,
+}
+
+impl SensorKey {
+    pub fn new(location: SensorLocation, name: &str) -> Self {
+        Self {
+            location,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Display for SensorKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.location, self.name)
+    }
+}
+
+/// A device whose sensors can be monitored.
+pub trait SensorSource {
+    /// List the motherboards and channels which may have sensors.
+    fn sensor_locations(&self) -> Result<Vec<SensorLocation>>;
+    /// List the sensors at a location.
+    fn sensor_names(&self, location: SensorLocation) -> Result<Vec<String>>;
+    /// Read a sensor.
+    fn sensor_value(&self, location: SensorLocation, name: &str) -> Result<SensorValue>;
+}
+
+impl SensorSource for Usrp {
+    fn sensor_locations(&self) -> Result<Vec<SensorLocation>> {
+        let mut locations: Vec<_> = (0..self.n_mboards()?).map(SensorLocation::Mboard).collect();
+        locations.extend((0..self.rx_channels()?).map(|i| SensorLocation::Channel(Channel::Rx(i))));
+        locations.extend((0..self.tx_channels()?).map(|i| SensorLocation::Channel(Channel::Tx(i))));
+        Ok(locations)
+    }
+
+    fn sensor_names(&self, location: SensorLocation) -> Result<Vec<String>> {
+        match location {
+            SensorLocation::Mboard(i) => self.mboard(i).sensor_names(),
+            SensorLocation::Channel(c) => self.channel(c)?.sensor_names(),
+        }
+    }
+
+    fn sensor_value(&self, location: SensorLocation, name: &str) -> Result<SensorValue> {
+        match location {
+            SensorLocation::Mboard(i) => self.mboard(i).sensor_value(name),
+            SensorLocation::Channel(c) => self.channel(c)?.sensor_value(name),
+        }
+    }
+}
+
+/// Something noteworthy which happened to a monitored sensor.
+#[derive(Clone, Debug)]
+pub enum SensorEvent {
+    /// The sensor's value changed.
+    Changed {
+        key: SensorKey,
+        previous: SensorValue,
+        current: SensorValue,
+    },
+    /// The sensor could not be read.
+    Error { key: SensorKey, error: UhdError },
+}
+
+impl SensorEvent {
+    /// The sensor the event is about.
+    pub fn key(&self) -> &SensorKey {
+        match self {
+            SensorEvent::Changed { key, .. } | SensorEvent::Error { key, .. } => key,
+        }
+    }
+}
+
+/// State of a single monitored sensor.
+struct Tracked {
+    key: SensorKey,
+    interval: Duration,
+    next_due: Instant,
+    history: VecDeque<(Instant, SensorValue)>,
+    /// The value changes are measured against.
+    reported: Option<SensorValue>,
+}
+
+type Callback = Box<dyn FnMut(&SensorEvent)>;
+
+/// Periodically reads the sensors of a device.
+///
+/// The monitor doesn't own the device. Either call [`SensorMonitor::poll`]
+/// regularly from your own loop, or let [`SensorMonitor::run`] do so.
+/// Sensors are discovered on the first poll.
+///
+/// A sensor is reported as [`SensorEvent::Changed`] whenever a boolean,
+/// integer or string value changes. Real values, such as temperatures, change
+/// on nearly every read; they are only reported once they move by more than a
+/// threshold set with [`SensorMonitor::with_threshold`].
+pub struct SensorMonitor {
+    default_interval: Duration,
+    intervals: HashMap<String, Duration>,
+    thresholds: HashMap<String, f64>,
+    history_len: usize,
+    sensors: Option<Vec<Tracked>>,
+    callbacks: Vec<Callback>,
+    senders: Vec<Sender<SensorEvent>>,
+}
+
+impl SensorMonitor {
+    pub fn new() -> Self {
+        Self {
+            default_interval: Duration::from_secs(1),
+            intervals: HashMap::new(),
+            thresholds: HashMap::new(),
+            history_len: 64,
+            sensors: None,
+            callbacks: Vec::new(),
+            senders: Vec::new(),
+        }
+    }
+
+    /// How often to read sensors without their own interval. Defaults to one second.
+    pub fn with_default_interval(mut self, interval: Duration) -> Self {
+        self.default_interval = interval;
+        self
+    }
+
+    /// How often to read sensors with the given name, wherever they are located.
+    pub fn with_interval(mut self, name: &str, interval: Duration) -> Self {
+        self.intervals.insert(name.to_string(), interval);
+        self
+    }
+
+    /// Report changes of real-valued sensors with the given name once they
+    /// differ from the last reported value by more than `threshold`.
+    pub fn with_threshold(mut self, name: &str, threshold: f64) -> Self {
+        self.thresholds.insert(name.to_string(), threshold);
+        self
+    }
+
+    /// How many readings to keep for each sensor. Defaults to 64.
+    pub fn with_history_len(mut self, len: usize) -> Self {
+        self.history_len = len.max(1);
+        self
+    }
+
+    /// Call a function for every event.
+    pub fn with_callback(mut self, callback: impl FnMut(&SensorEvent) + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Get a channel receiving every event from now on.
+    pub fn subscribe(&mut self) -> Receiver<SensorEvent> {
+        let (sender, receiver) = channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    /// Forget the discovered sensors, so that they are discovered again on the next poll.
+    ///
+    /// History is discarded as well.
+    pub fn rediscover(&mut self) {
+        self.sensors = None;
+    }
+
+    /// Read all sensors which are due, returning how many were read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sensors could not be discovered. Errors reading
+    /// individual sensors are reported as [`SensorEvent::Error`] instead.
+    pub fn poll(&mut self, source: &impl SensorSource) -> Result<usize> {
+        self.poll_at(source, Instant::now())
+    }
+
+    /// Poll until `stop` is set, sleeping in between.
+    pub fn run(&mut self, source: &impl SensorSource, stop: &AtomicBool) -> Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            self.poll(source)?;
+            let sleep = self.next_due().map_or(MAX_SLEEP, |due| {
+                due.saturating_duration_since(Instant::now())
+            });
+            std::thread::sleep(sleep.min(MAX_SLEEP));
+        }
+        Ok(())
+    }
+
+    /// When the next sensor is due to be read, or `None` before the first poll.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.sensors.as_ref()?.iter().map(|s| s.next_due).min()
+    }
+
+    /// The monitored sensors, or nothing before the first poll.
+    pub fn keys(&self) -> impl Iterator<Item = &SensorKey> {
+        self.sensors.iter().flatten().map(|s| &s.key)
+    }
+
+    /// The latest reading of a sensor.
+    pub fn latest(&self, key: &SensorKey) -> Option<&SensorValue> {
+        self.tracked(key)?.history.back().map(|(_, v)| v)
+    }
+
+    /// The readings of a sensor, oldest first.
+    pub fn history(&self, key: &SensorKey) -> impl Iterator<Item = &(Instant, SensorValue)> {
+        self.tracked(key).into_iter().flat_map(|s| s.history.iter())
+    }
+
+    /// The latest reading of every sensor which has been read.
+    pub fn snapshot(&self) -> Vec<(SensorKey, SensorValue)> {
+        self.sensors
+            .iter()
+            .flatten()
+            .filter_map(|s| Some((s.key.clone(), s.history.back()?.1.clone())))
+            .collect()
+    }
+
+    fn tracked(&self, key: &SensorKey) -> Option<&Tracked> {
+        self.sensors.as_ref()?.iter().find(|s| &s.key == key)
+    }
+
+    fn discover(&self, source: &impl SensorSource, now: Instant) -> Result<Vec<Tracked>> {
+        let mut sensors = Vec::new();
+        for location in source.sensor_locations()? {
+            for name in source.sensor_names(location)? {
+                let interval = *self.intervals.get(&name).unwrap_or(&self.default_interval);
+                sensors.push(Tracked {
+                    key: SensorKey { location, name },
+                    interval,
+                    next_due: now,
+                    history: VecDeque::with_capacity(self.history_len),
+                    reported: None,
+                });
+            }
+        }
+        Ok(sensors)
+    }
+
+    fn poll_at(&mut self, source: &impl SensorSource, now: Instant) -> Result<usize> {
+        let mut sensors = match self.sensors.take() {
+            Some(sensors) => sensors,
+            None => self.discover(source, now)?,
+        };
+        let mut read = 0;
+        let mut events = Vec::new();
+        for sensor in sensors.iter_mut().filter(|s| s.next_due <= now) {
+            // Keep to the schedule, unless polling fell behind it.
+            sensor.next_due += sensor.interval;
+            if sensor.next_due <= now {
+                sensor.next_due = now + sensor.interval;
+            }
+            read += 1;
+            let value = match source.sensor_value(sensor.key.location, &sensor.key.name) {
+                Ok(value) => value,
+                Err(error) => {
+                    events.push(SensorEvent::Error {
+                        key: sensor.key.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            match &sensor.reported {
+                Some(previous) if self.is_change(&sensor.key, previous, &value) => {
+                    events.push(SensorEvent::Changed {
+                        key: sensor.key.clone(),
+                        previous: previous.clone(),
+                        current: value.clone(),
+                    });
+                    sensor.reported = Some(value.clone());
+                }
+                Some(_) => {}
+                None => sensor.reported = Some(value.clone()),
+            }
+            if sensor.history.len() == self.history_len {
+                sensor.history.pop_front();
+            }
+            sensor.history.push_back((now, value));
+        }
+        self.sensors = Some(sensors);
+        for event in events {
+            self.emit(event);
+        }
+        Ok(read)
+    }
+
+    /// Returns `true` if `current` is a reportable change from `previous`.
+    fn is_change(&self, key: &SensorKey, previous: &SensorValue, current: &SensorValue) -> bool {
+        match (previous.value(), current.value()) {
+            (SensorValueValue::Real(a), SensorValueValue::Real(b)) => self
+                .thresholds
+                .get(&key.name)
+                .map_or(false, |&threshold| (a - b).abs() > threshold),
+            (a, b) => a != b,
+        }
+    }
+
+    fn emit(&mut self, event: SensorEvent) {
+        for callback in &mut self.callbacks {
+            callback(&event);
+        }
+        self.senders.retain(|s| s.send(event.clone()).is_ok());
+    }
+}
+
+impl Default for SensorMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeSource {
+        values: RefCell<Vec<(SensorKey, Result<SensorValue>)>>,
+        reads: RefCell<usize>,
+    }
+
+    impl FakeSource {
+        fn set(&self, location: SensorLocation, name: &str, value: Result<SensorValueValue>) {
+            let key = SensorKey::new(location, name);
+            let value = value.map(|v| SensorValue::new(name, v, ""));
+            let mut values = self.values.borrow_mut();
+            match values.iter_mut().find(|(k, _)| k == &key) {
+                Some(entry) => entry.1 = value,
+                None => values.push((key, value)),
+            }
+        }
+    }
+
+    impl SensorSource for FakeSource {
+        fn sensor_locations(&self) -> Result<Vec<SensorLocation>> {
+            Ok(vec![
+                SensorLocation::Mboard(0),
+                SensorLocation::Channel(Channel::Rx(0)),
+            ])
+        }
+
+        fn sensor_names(&self, location: SensorLocation) -> Result<Vec<String>> {
+            Ok(self
+                .values
+                .borrow()
+                .iter()
+                .filter(|(k, _)| k.location == location)
+                .map(|(k, _)| k.name.clone())
+                .collect())
+        }
+
+        fn sensor_value(&self, location: SensorLocation, name: &str) -> Result<SensorValue> {
+            *self.reads.borrow_mut() += 1;
+            let key = SensorKey::new(location, name);
+            let values = self.values.borrow();
+            values.iter().find(|(k, _)| k == &key).unwrap().1.clone()
+        }
+    }
+
+    const RX0: SensorLocation = SensorLocation::Channel(Channel::Rx(0));
+    const MB0: SensorLocation = SensorLocation::Mboard(0);
+
+    fn source() -> FakeSource {
+        let source = FakeSource::default();
+        source.set(MB0, "ref_locked", Ok(SensorValueValue::Boolean(true)));
+        source.set(MB0, "temp", Ok(SensorValueValue::Real(40.0)));
+        source.set(RX0, "lo_locked", Ok(SensorValueValue::Boolean(true)));
+        source
+    }
+
+    #[test]
+    fn transitions_are_reported() {
+        let source = source();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut monitor = SensorMonitor::new().with_callback({
+            let events = events.clone();
+            move |e| events.borrow_mut().push(e.clone())
+        });
+        let receiver = monitor.subscribe();
+        let start = Instant::now();
+        assert_eq!(monitor.poll_at(&source, start).unwrap(), 3);
+        assert!(events.borrow().is_empty());
+
+        source.set(RX0, "lo_locked", Ok(SensorValueValue::Boolean(false)));
+        source.set(MB0, "temp", Ok(SensorValueValue::Real(41.0)));
+        monitor
+            .poll_at(&source, start + Duration::from_secs(1))
+            .unwrap();
+        let events = events.borrow();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            SensorEvent::Changed {
+                key,
+                previous,
+                current,
+            } => {
+                assert_eq!(key, &SensorKey::new(RX0, "lo_locked"));
+                assert_eq!(previous.as_bool(), Some(true));
+                assert_eq!(current.as_bool(), Some(false));
+            }
+            e => panic!("unexpected event {e:?}"),
+        }
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn thresholds() {
+        let source = source();
+        let mut monitor = SensorMonitor::new().with_threshold("temp", 2.0);
+        let receiver = monitor.subscribe();
+        let start = Instant::now();
+        for (i, temp) in [40.0, 41.0, 42.5, 43.0].into_iter().enumerate() {
+            source.set(MB0, "temp", Ok(SensorValueValue::Real(temp)));
+            monitor
+                .poll_at(&source, start + Duration::from_secs(i as u64))
+                .unwrap();
+        }
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].key(), &SensorKey::new(MB0, "temp"));
+    }
+
+    #[test]
+    fn intervals() {
+        let source = source();
+        let mut monitor = SensorMonitor::new()
+            .with_default_interval(Duration::from_secs(10))
+            .with_interval("lo_locked", Duration::from_secs(1));
+        let start = Instant::now();
+        assert_eq!(monitor.poll_at(&source, start).unwrap(), 3);
+        assert_eq!(monitor.next_due(), Some(start + Duration::from_secs(1)));
+        for i in 1..10 {
+            let read = monitor
+                .poll_at(&source, start + Duration::from_secs(i))
+                .unwrap();
+            assert_eq!(read, 1);
+        }
+        assert_eq!(
+            monitor
+                .poll_at(&source, start + Duration::from_secs(10))
+                .unwrap(),
+            3
+        );
+        assert_eq!(*source.reads.borrow(), 3 + 9 + 3);
+        assert_eq!(
+            monitor.history(&SensorKey::new(RX0, "lo_locked")).count(),
+            11
+        );
+        assert_eq!(monitor.history(&SensorKey::new(MB0, "temp")).count(), 2);
+    }
+
+    #[test]
+    fn history_and_snapshot() {
+        let source = source();
+        let mut monitor = SensorMonitor::new().with_history_len(2);
+        let start = Instant::now();
+        for i in 0..5 {
+            source.set(MB0, "temp", Ok(SensorValueValue::Real(i as f64)));
+            monitor
+                .poll_at(&source, start + Duration::from_secs(i))
+                .unwrap();
+        }
+        let key = SensorKey::new(MB0, "temp");
+        let temps: Vec<_> = monitor
+            .history(&key)
+            .map(|(_, v)| v.as_f64().unwrap())
+            .collect();
+        assert_eq!(temps, [3.0, 4.0]);
+        assert_eq!(monitor.latest(&key).unwrap().as_f64(), Some(4.0));
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(monitor.keys().count(), 3);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let source = source();
+        source.set(RX0, "lo_locked", Err(UhdError::Io));
+        let mut monitor = SensorMonitor::new();
+        let receiver = monitor.subscribe();
+        monitor.poll(&source).unwrap();
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert!(matches!(
+            &events[..],
+            [SensorEvent::Error {
+                error: UhdError::Io,
+                ..
+            }]
+        ));
+        assert!(monitor.latest(&SensorKey::new(RX0, "lo_locked")).is_none());
+    }
+}
@@ -0,0 +1,650 @@
+//! Playback of recorded waveforms through a TX stream.
+//!
+//! Samples are read from raw files of interleaved IQ samples, or from SigMF
+//! recordings when the `sigmf` feature is enabled, and converted to the
+//! stream's sample type on the fly.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use num_complex::Complex32;
+//! use uhd_usrp::{
+//!     playback::{FileFormat, FileSource, Playback},
+//!     timespec, Channel, Usrp,
+//! };
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let mut source = FileSource::open_raw("waveform.sc16", FileFormat::Ci16)
+//!     .unwrap()
+//!     .with_sample_rate(1e6);
+//! source.check_channel(&usrp.channel(Channel::Tx(0)).unwrap()).unwrap();
+//!
+//! let mut stream = usrp.tx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+//! let time = usrp.mboard(0).time().unwrap() + timespec!(100 ms);
+//! Playback::new()
+//!     .with_start_time(time)
+//!     .play(&mut source, &mut stream)
+//!     .unwrap();
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    ArrayBuffer, ChannelConfig, IqSample, SampleBuffer, TimeSpec, TxMetadata, TxStreamer, UhdError,
+};
+
+/// Relative difference tolerated between the sample rates of a file and a device.
+const SAMPLE_RATE_TOLERANCE: f64 = 1e-6;
+
+/// An error which occurred during playback.
+#[derive(thiserror::Error, Debug)]
+pub enum PlaybackError {
+    #[error("unsupported datatype {0:?}")]
+    UnsupportedDatatype(String),
+    #[error("invalid metadata: {0}")]
+    InvalidMetadata(String),
+    #[error("file sample rate {file} Hz does not match device sample rate {device} Hz")]
+    SampleRateMismatch { file: f64, device: f64 },
+    #[error("expected {expected} channels, got {actual}")]
+    ChannelMismatch { expected: usize, actual: usize },
+    #[error("timed out sending samples")]
+    Timeout,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[cfg(feature = "sigmf")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+/// Format of the samples in a file.
+///
+/// Samples are interleaved IQ pairs, stored in little-endian byte order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    /// Complex `f64` samples (`fc64`).
+    Cf64,
+    /// Complex `f32` samples (`fc32`).
+    Cf32,
+    /// Complex `i16` samples (`sc16`).
+    Ci16,
+    /// Complex `i8` samples (`sc8`).
+    Ci8,
+}
+
+impl FileFormat {
+    /// Parse a SigMF datatype, e.g. `cf32_le`.
+    pub fn from_sigmf(datatype: &str) -> Option<Self> {
+        match datatype {
+            "cf64_le" => Some(Self::Cf64),
+            "cf32_le" => Some(Self::Cf32),
+            "ci16_le" => Some(Self::Ci16),
+            "ci8" => Some(Self::Ci8),
+            _ => None,
+        }
+    }
+
+    /// Size of a sample, in bytes.
+    pub fn sample_size(&self) -> usize {
+        match self {
+            FileFormat::Cf64 => 16,
+            FileFormat::Cf32 => 8,
+            FileFormat::Ci16 => 4,
+            FileFormat::Ci8 => 2,
+        }
+    }
+
+    /// Decode a single sample, using UHD's scaling convention for integers.
+    fn decode(&self, bytes: &[u8]) -> [f64; 2] {
+        match self {
+            FileFormat::Cf64 => [
+                f64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+                f64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            ],
+            FileFormat::Cf32 => [
+                f32::from_le_bytes(bytes[0..4].try_into().unwrap()) as f64,
+                f32::from_le_bytes(bytes[4..8].try_into().unwrap()) as f64,
+            ],
+            FileFormat::Ci16 => [
+                i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / i16::MAX as f64,
+                i16::from_le_bytes([bytes[2], bytes[3]]) as f64 / i16::MAX as f64,
+            ],
+            FileFormat::Ci8 => [
+                bytes[0] as i8 as f64 / i8::MAX as f64,
+                bytes[1] as i8 as f64 / i8::MAX as f64,
+            ],
+        }
+    }
+}
+
+/// A file of samples to be played back.
+///
+/// Files with multiple channels interleave them sample by sample.
+pub struct FileSource {
+    reader: BufReader<File>,
+    format: FileFormat,
+    channels: usize,
+    sample_rate: Option<f64>,
+    /// Number of samples per channel in the file.
+    samples: u64,
+    scratch: Vec<u8>,
+}
+
+impl FileSource {
+    /// Open a raw file holding a single channel of samples.
+    pub fn open_raw(path: impl AsRef<Path>, format: FileFormat) -> io::Result<Self> {
+        Self::open_raw_with_channels(path, format, 1)
+    }
+
+    /// Open a raw file holding interleaved samples of multiple channels.
+    pub fn open_raw_with_channels(
+        path: impl AsRef<Path>,
+        format: FileFormat,
+        channels: usize,
+    ) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            reader: BufReader::new(file),
+            format,
+            channels,
+            sample_rate: None,
+            samples: len / (format.sample_size() * channels) as u64,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// Open a SigMF recording.
+    ///
+    /// `path` may be either the base name of the recording or the path of its
+    /// `.sigmf-meta` or `.sigmf-data` file.
+    #[cfg(feature = "sigmf")]
+    pub fn open_sigmf(path: impl AsRef<Path>) -> Result<Self, PlaybackError> {
+        let path = path.as_ref().to_string_lossy();
+        let base = path
+            .strip_suffix(".sigmf-meta")
+            .or_else(|| path.strip_suffix(".sigmf-data"))
+            .unwrap_or(&path);
+        let meta: serde_json::Value =
+            serde_json::from_reader(BufReader::new(File::open(format!("{base}.sigmf-meta"))?))?;
+        let global = &meta["global"];
+        let datatype = global["core:datatype"]
+            .as_str()
+            .ok_or_else(|| PlaybackError::InvalidMetadata("missing core:datatype".into()))?;
+        let format = FileFormat::from_sigmf(datatype)
+            .ok_or_else(|| PlaybackError::UnsupportedDatatype(datatype.to_string()))?;
+        let channels =
+            match &global["core:num_channels"] {
+                serde_json::Value::Null => 1,
+                n => n.as_u64().filter(|&n| n > 0).ok_or_else(|| {
+                    PlaybackError::InvalidMetadata("invalid core:num_channels".into())
+                })? as usize,
+            };
+        let source = Self::open_raw_with_channels(format!("{base}.sigmf-data"), format, channels)?;
+        Ok(match global["core:sample_rate"].as_f64() {
+            Some(rate) => source.with_sample_rate(rate),
+            None => source,
+        })
+    }
+
+    /// Set the sample rate the file was recorded at.
+    pub fn with_sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    pub fn format(&self) -> FileFormat {
+        self.format
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The sample rate the file was recorded at, if known.
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Number of samples per channel in the file.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Check that the file was recorded at the given sample rate.
+    ///
+    /// Files with an unknown sample rate always pass.
+    pub fn check_sample_rate(&self, rate: f64) -> Result<(), PlaybackError> {
+        match self.sample_rate {
+            Some(file) if ((file - rate) / file).abs() > SAMPLE_RATE_TOLERANCE => {
+                Err(PlaybackError::SampleRateMismatch { file, device: rate })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the file was recorded at the sample rate of a channel.
+    pub fn check_channel(&self, channel: &ChannelConfig) -> Result<(), PlaybackError> {
+        self.check_sample_rate(channel.sample_rate()?)
+    }
+
+    /// Go back to the start of the file.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    /// Read and convert the next samples of each channel into the buffer,
+    /// returning the number of samples read per channel.
+    ///
+    /// Returns `0` at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the buffer
+    /// doesn't have as many channels as the file.
+    pub fn read<T: IqSample>(&mut self, buff: &mut ArrayBuffer<T>) -> io::Result<usize> {
+        if buff.channels() != self.channels {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer and file have different channel counts",
+            ));
+        }
+        let size = self.format.sample_size();
+        let frame = size * self.channels;
+        self.scratch.resize(buff.samples() * frame, 0);
+        let mut filled = 0;
+        while filled < self.scratch.len() {
+            match self.reader.read(&mut self.scratch[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let samples = filled / frame;
+        for (i, frame) in self.scratch[..samples * frame]
+            .chunks_exact(frame)
+            .enumerate()
+        {
+            for (c, bytes) in frame.chunks_exact(size).enumerate() {
+                buff[c][i] = T::from_iq(self.format.decode(bytes));
+            }
+        }
+        Ok(samples)
+    }
+}
+
+/// Settings for playing a [`FileSource`] through a TX stream.
+///
+/// The whole playback is sent as a single burst, which starts immediately
+/// unless a start time is given.
+///
+/// # Sample rate
+///
+/// A TX stream doesn't know its sample rate, so a file recorded at a different
+/// rate than the device's is played back at the wrong speed **without any
+/// error**, unless the rate is given with [`Playback::with_sample_rate`] or
+/// checked beforehand with [`FileSource::check_channel`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Playback {
+    looped: bool,
+    start_time: Option<TimeSpec>,
+    timeout: Duration,
+    limit: Option<u64>,
+    sample_rate: Option<f64>,
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self {
+            looped: false,
+            start_time: None,
+            timeout: Duration::from_secs(1),
+            limit: None,
+            sample_rate: None,
+        }
+    }
+
+    /// Restart from the beginning of the file once it ends.
+    ///
+    /// Unless a limit is set, looped playback only stops on an error.
+    pub fn with_looping(mut self, looped: bool) -> Self {
+        self.looped = looped;
+        self
+    }
+
+    /// Start sending at the given device time.
+    pub fn with_start_time(mut self, time: TimeSpec) -> Self {
+        self.start_time = Some(time);
+        self
+    }
+
+    /// How long to wait for the device to accept samples.
+    ///
+    /// When starting at a later time, this should include the delay until then.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stop after the given number of samples per channel.
+    pub fn with_limit(mut self, samples: u64) -> Self {
+        self.limit = Some(samples);
+        self
+    }
+
+    /// The sample rate of the stream, which the file's sample rate is checked
+    /// against before playing, see [`FileSource::check_sample_rate`].
+    pub fn with_sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    /// Play the file through the stream, returning the number of samples
+    /// sent per channel.
+    ///
+    /// The file is read from its current position. The sample rate is only
+    /// checked if one was given with [`Playback::with_sample_rate`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PlaybackError::ChannelMismatch`] if the file and stream have a
+    /// different number of channels, and [`PlaybackError::SampleRateMismatch`]
+    /// if the file was recorded at another sample rate than the one given.
+    pub fn play<T, S>(&self, source: &mut FileSource, stream: &mut S) -> Result<u64, PlaybackError>
+    where
+        T: IqSample + Clone + Default,
+        S: TxStreamer<T>,
+    {
+        if source.channels() != stream.channels() {
+            return Err(PlaybackError::ChannelMismatch {
+                expected: stream.channels(),
+                actual: source.channels(),
+            });
+        }
+        if let Some(rate) = self.sample_rate {
+            source.check_sample_rate(rate)?;
+        }
+        let mut buff = ArrayBuffer::new(stream.channels(), stream.max_samples_per_channel());
+        let mut md = TxMetadata::new();
+        md.set_start_of_burst(true);
+        md.set_time_spec(self.start_time);
+        let mut sent = 0;
+        while self.limit.map_or(true, |limit| sent < limit) {
+            let mut read = source.read(&mut buff)?;
+            if read == 0 {
+                if !self.looped || source.samples() == 0 {
+                    break;
+                }
+                source.rewind()?;
+                continue;
+            }
+            if let Some(limit) = self.limit {
+                read = read.min((limit - sent) as usize);
+            }
+            self.send(stream, &mut buff, read, &mut md)?;
+            sent += read as u64;
+        }
+        if sent > 0 {
+            md.set_end_of_burst(true);
+            stream.send(&Window::new(&mut buff, 0, 0), &md, self.timeout)?;
+        }
+        Ok(sent)
+    }
+
+    /// Send the first `samples` samples of the buffer, continuing after partial sends.
+    fn send<T: IqSample>(
+        &self,
+        stream: &mut impl TxStreamer<T>,
+        buff: &mut ArrayBuffer<T>,
+        samples: usize,
+        md: &mut TxMetadata,
+    ) -> Result<(), PlaybackError> {
+        let mut offset = 0;
+        while offset < samples {
+            let sent = stream.send(
+                &Window::new(buff, offset, samples - offset),
+                md,
+                self.timeout,
+            )?;
+            if sent == 0 {
+                return Err(PlaybackError::Timeout);
+            }
+            offset += sent;
+            // Everything after the first packet continues the burst.
+            md.set_start_of_burst(false);
+            md.set_time_spec(None);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A range of samples of each channel of an [`ArrayBuffer`].
+///
+/// Borrows the buffer mutably, since [`SampleBuffer`] gives out mutable pointers.
+struct Window<'a, T> {
+    ptrs: Vec<*mut T>,
+    samples: usize,
+    _phantom: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: IqSample> Window<'a, T> {
+    fn new(buff: &'a mut ArrayBuffer<T>, offset: usize, samples: usize) -> Self {
+        assert!(offset + samples <= buff.samples());
+        Self {
+            ptrs: buff
+                .iter_channels_mut()
+                .map(|c| c[offset..].as_mut_ptr())
+                .collect(),
+            samples,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: IqSample> SampleBuffer<T> for Window<'a, T> {
+    fn channels(&self) -> usize {
+        self.ptrs.len()
+    }
+
+    fn samples(&self) -> usize {
+        self.samples
+    }
+
+    fn as_ptr(&self) -> *const *const T {
+        self.ptrs.as_ptr().cast()
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut *mut T {
+        self.ptrs.as_mut_ptr()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::{sim::SimDevice, StreamDevice};
+
+    fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("uhd-usrp-{name}-{}", std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn ci16_file(name: &str, samples: &[[i16; 2]]) -> PathBuf {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|s| s.iter().flat_map(|v| v.to_le_bytes()))
+            .collect();
+        temp_file(name, &bytes)
+    }
+
+    #[test]
+    fn converts_formats() {
+        let path = ci16_file("playback-convert", &[[i16::MAX, 0], [0, -i16::MAX]]);
+        let mut source = FileSource::open_raw(&path, FileFormat::Ci16).unwrap();
+        assert_eq!(source.samples(), 2);
+        let mut buff = ArrayBuffer::<[f32; 2]>::new(1, 4);
+        assert_eq!(source.read(&mut buff).unwrap(), 2);
+        assert_eq!(&buff[0][..2], &[[1.0, 0.0], [0.0, -1.0]]);
+        assert_eq!(source.read(&mut buff).unwrap(), 0);
+
+        source.rewind().unwrap();
+        let mut buff = ArrayBuffer::<[i16; 2]>::new(1, 4);
+        assert_eq!(source.read(&mut buff).unwrap(), 2);
+        assert_eq!(&buff[0][..2], &[[i16::MAX, 0], [0, -i16::MAX]]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn checks_sample_rate() {
+        let path = ci16_file("playback-rate", &[[0, 0]]);
+        let source = FileSource::open_raw(&path, FileFormat::Ci16).unwrap();
+        assert!(source.check_sample_rate(1e6).is_ok());
+        let source = source.with_sample_rate(1e6);
+        assert!(source.check_sample_rate(1e6).is_ok());
+        assert!(matches!(
+            source.check_sample_rate(2e6),
+            Err(PlaybackError::SampleRateMismatch { .. })
+        ));
+
+        let mut source = source;
+        let device = SimDevice::new(0, 1);
+        let mut stream = device.open_tx_stream(&[0]).unwrap();
+        assert!(matches!(
+            Playback::new()
+                .with_sample_rate(2e6)
+                .play::<[i16; 2], _>(&mut source, &mut stream),
+            Err(PlaybackError::SampleRateMismatch { .. })
+        ));
+        assert!(device.tx_captures().is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plays_a_single_burst() {
+        let samples: Vec<[i16; 2]> = (0..4500).map(|i| [i as i16, 0]).collect();
+        let path = ci16_file("playback-burst", &samples);
+        let mut source = FileSource::open_raw(&path, FileFormat::Ci16).unwrap();
+
+        let device = SimDevice::new(0, 1);
+        let mut stream = device.open_tx_stream(&[0]).unwrap();
+        let time = TimeSpec::from_secs(1);
+        let sent = Playback::new()
+            .with_start_time(time)
+            .play::<[i16; 2], _>(&mut source, &mut stream)
+            .unwrap();
+        assert_eq!(sent, 4500);
+
+        let captures = device.tx_captures();
+        let lengths: Vec<_> = captures.iter().map(|c| c.samples.len()).collect();
+        assert_eq!(lengths, [2000, 2000, 500, 0]);
+        assert!(captures[0].start_of_burst);
+        assert_eq!(captures[0].time_spec, Some(time));
+        assert!(captures[1..]
+            .iter()
+            .all(|c| !c.start_of_burst && c.time_spec.is_none()));
+        assert!(captures[..3].iter().all(|c| !c.end_of_burst));
+        assert!(captures[3].end_of_burst);
+        let played: Vec<_> = device
+            .tx_samples(0)
+            .iter()
+            .map(|s| [s[0] * i16::MAX as f64, s[1]])
+            .collect();
+        assert!(played
+            .iter()
+            .zip(&samples)
+            .all(|(a, b)| a[0].round() as i16 == b[0]));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn loops_until_limit() {
+        let path = ci16_file("playback-loop", &[[1, 1]; 300]);
+        let mut source = FileSource::open_raw(&path, FileFormat::Ci16).unwrap();
+        let device = SimDevice::new(0, 1);
+        let mut stream = device.open_tx_stream(&[0]).unwrap();
+        let sent = Playback::new()
+            .with_looping(true)
+            .with_limit(1000)
+            .play::<[f32; 2], _>(&mut source, &mut stream)
+            .unwrap();
+        assert_eq!(sent, 1000);
+        assert_eq!(device.tx_samples(0).len(), 1000);
+        let captures = device.tx_captures();
+        assert_eq!(captures.iter().filter(|c| c.start_of_burst).count(), 1);
+        assert_eq!(captures.iter().filter(|c| c.end_of_burst).count(), 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_channel_mismatch() {
+        let path = ci16_file("playback-channels", &[[0, 0]; 4]);
+        let mut source = FileSource::open_raw_with_channels(&path, FileFormat::Ci16, 2).unwrap();
+        assert_eq!(source.samples(), 2);
+        let device = SimDevice::new(0, 1);
+        let mut stream = device.open_tx_stream(&[0]).unwrap();
+        assert!(matches!(
+            Playback::new().play::<[f32; 2], _>(&mut source, &mut stream),
+            Err(PlaybackError::ChannelMismatch {
+                expected: 1,
+                actual: 2
+            })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "sigmf")]
+    #[test]
+    fn plays_sigmf_recordings() {
+        use crate::{
+            sigmf::{SigmfCaptureInfo, SigmfWriter},
+            RxMetadata,
+        };
+
+        let base =
+            std::env::temp_dir().join(format!("uhd-usrp-playback-sigmf-{}", std::process::id()));
+        let info = SigmfCaptureInfo {
+            sample_rate: 2e6,
+            ..Default::default()
+        };
+        let mut writer = SigmfWriter::<[f32; 2]>::create_with_channels(&base, info, 2).unwrap();
+        let buff = ArrayBuffer::from_vec_channels(vec![vec![[0.5, 0.0]; 10], vec![[0.0, 0.5]; 10]]);
+        writer.write(&buff, 10, &RxMetadata::new()).unwrap();
+        writer.finish().unwrap();
+
+        let mut meta = base.clone().into_os_string();
+        meta.push(".sigmf-meta");
+        let mut source = FileSource::open_sigmf(&meta).unwrap();
+        assert_eq!(source.format(), FileFormat::Cf32);
+        assert_eq!(source.channels(), 2);
+        assert_eq!(source.sample_rate(), Some(2e6));
+        assert_eq!(source.samples(), 10);
+
+        let mut read = ArrayBuffer::<[i16; 2]>::new(2, 10);
+        assert_eq!(source.read(&mut read).unwrap(), 10);
+        assert_eq!(read[0][9], [i16::MAX / 2 + 1, 0]);
+        assert_eq!(read[1][0], [0, i16::MAX / 2 + 1]);
+
+        fs::remove_file(&meta).unwrap();
+        let mut data = base.into_os_string();
+        data.push(".sigmf-data");
+        fs::remove_file(data).unwrap();
+    }
+}
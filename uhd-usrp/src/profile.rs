@@ -0,0 +1,721 @@
+//! Declarative radio profiles.
+//!
+//! A [`RadioProfile`] describes how a device should be opened and configured:
+//! the device arguments, the motherboards' clock and time sources, the settings
+//! of each channel, and the formats of the RX and TX streams. Profiles are
+//! loaded from TOML, or from YAML with the `yaml` feature enabled.
+//!
+//! Before a profile is applied it is checked against the capabilities reported
+//! by the device, and every problem found is reported along with the key of the
+//! profile it came from.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use uhd_usrp::profile::RadioProfile;
+//!
+//! let profile = RadioProfile::from_toml_str(
+//!     r#"
+//!     args = "type=b200"
+//!
+//!     [[mboards]]
+//!     clock_source = "internal"
+//!
+//!     [[rx]]
+//!     antenna = "RX2"
+//!     center_freq = 915e6
+//!     gain = 30.0
+//!     sample_rate = 1e6
+//!
+//!     [rx_stream]
+//!     cpu_format = "fc32"
+//!     otw_format = "sc16"
+//!     channels = [0]
+//!     "#,
+//! )
+//! .unwrap();
+//!
+//! let usrp = profile.open().unwrap();
+//! if let Err(e) = profile.apply(&usrp) {
+//!     eprintln!("{e}");
+//! }
+//! ```
+
+use std::{fmt::Display, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    stream::{OtwFormat, RxStreamBuilder, TxStreamBuilder},
+    types::{DeviceArgsError, Range},
+    Channel, ChannelConfig, ChannelSettings, DeviceArgs, DeviceConfig, FieldError, MboardSettings,
+    Sample, UhdError, Usrp,
+};
+
+/// CPU sample formats supported by UHD.
+const CPU_FORMATS: &[&str] = &["fc64", "fc32", "sc16", "sc8", "f64", "f32", "s16", "s8"];
+
+/// An error which occurred while loading or applying a profile.
+#[derive(thiserror::Error, Debug)]
+pub enum ProfileError {
+    #[error("failed to read profile: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid profile: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[cfg(feature = "yaml")]
+    #[error("invalid profile: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("unsupported profile format {0:?}")]
+    UnsupportedFormat(String),
+    #[error("invalid profile:{}", list(.0))]
+    Invalid(Vec<Diagnostic>),
+    #[error("failed to apply profile:{}", list(.0))]
+    Apply(Vec<FieldError>),
+    #[error(transparent)]
+    DeviceArgs(#[from] DeviceArgsError),
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+fn list<T: Display>(items: &[T]) -> String {
+    items.iter().map(|item| format!("\n  {item}")).collect()
+}
+
+/// A problem with a single key of a profile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Path of the offending key, e.g. `rx[0].gain`.
+    pub key: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// A declarative description of how to open and configure a device.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RadioProfile {
+    /// Device arguments used to open the device, e.g. `"type=b200"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<String>,
+    pub mboards: Vec<MboardProfile>,
+    pub rx: Vec<ChannelProfile>,
+    pub tx: Vec<ChannelProfile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_stream: Option<StreamProfile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_stream: Option<StreamProfile>,
+}
+
+/// Settings of a single motherboard.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MboardProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_source: Option<String>,
+}
+
+/// Settings of a single RX or TX channel.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub antenna: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub center_freq: Option<f64>,
+    /// The overall gain in dB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+}
+
+/// Formats and channels of an RX or TX stream.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamProfile {
+    /// The host sample format, e.g. `"fc32"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_format: Option<String>,
+    /// The over-the-wire sample format. Chosen by UHD if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otw_format: Option<OtwFormat>,
+    /// The channels to stream. Defaults to channel 0.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<usize>,
+}
+
+/// The values supported by a device, used to validate a profile.
+///
+/// Empty lists mean the device did not report the corresponding values,
+/// and are not checked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceCapabilities {
+    pub mboards: Vec<MboardCapabilities>,
+    pub rx: Vec<ChannelCapabilities>,
+    pub tx: Vec<ChannelCapabilities>,
+}
+
+/// The values supported by a single motherboard.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MboardCapabilities {
+    pub clock_sources: Vec<String>,
+    pub time_sources: Vec<String>,
+}
+
+/// The values supported by a single channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelCapabilities {
+    pub antennas: Vec<String>,
+    pub center_freq: Vec<Range>,
+    pub gain: Vec<Range>,
+    pub bandwidth: Vec<Range>,
+    pub sample_rate: Vec<Range>,
+}
+
+impl DeviceCapabilities {
+    /// Read the capabilities of a device.
+    pub fn read(usrp: &Usrp) -> Result<Self, UhdError> {
+        let mboards = (0..usrp.n_mboards()?)
+            .map(|mb| {
+                let mb = usrp.mboard(mb);
+                MboardCapabilities {
+                    clock_sources: mb.clock_sources().unwrap_or_default(),
+                    time_sources: mb.time_sources().unwrap_or_default(),
+                }
+            })
+            .collect();
+        let rx = (0..usrp.rx_channels()?)
+            .map(|i| Ok(ChannelCapabilities::read(&usrp.channel(Channel::Rx(i))?)))
+            .collect::<Result<_, UhdError>>()?;
+        let tx = (0..usrp.tx_channels()?)
+            .map(|i| Ok(ChannelCapabilities::read(&usrp.channel(Channel::Tx(i))?)))
+            .collect::<Result<_, UhdError>>()?;
+        Ok(Self { mboards, rx, tx })
+    }
+}
+
+impl ChannelCapabilities {
+    fn read(ch: &ChannelConfig) -> Self {
+        Self {
+            antennas: ch.antennas().unwrap_or_default(),
+            center_freq: ch
+                .center_freq_ranges()
+                .map(|r| r.ranges().to_vec())
+                .unwrap_or_default(),
+            gain: ch
+                .gain_ranges(None)
+                .map(|r| r.ranges().to_vec())
+                .unwrap_or_default(),
+            bandwidth: ch
+                .bandwidth_ranges()
+                .map(|r| r.ranges().to_vec())
+                .unwrap_or_default(),
+            sample_rate: ch
+                .sample_rates()
+                .map(|r| r.ranges().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl RadioProfile {
+    /// Parse a profile from TOML.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProfileError::Toml`] if the profile is malformed or contains
+    /// unknown keys, and [`ProfileError::Invalid`] if values such as the device
+    /// arguments or sample formats are invalid.
+    pub fn from_toml_str(s: &str) -> Result<Self, ProfileError> {
+        let profile: Self = toml::from_str(s)?;
+        profile.check()?;
+        Ok(profile)
+    }
+
+    /// Parse a profile from YAML.
+    ///
+    /// See [`RadioProfile::from_toml_str`].
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(s: &str) -> Result<Self, ProfileError> {
+        let profile: Self = serde_yaml::from_str(s)?;
+        profile.check()?;
+        Ok(profile)
+    }
+
+    /// Load a profile from a file.
+    ///
+    /// Files ending in `.yaml` or `.yml` are read as YAML, and all others as TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Self::from_yaml_str(&contents),
+            #[cfg(not(feature = "yaml"))]
+            Some(ext @ ("yaml" | "yml")) => Err(ProfileError::UnsupportedFormat(ext.to_string())),
+            _ => Self::from_toml_str(&contents),
+        }
+    }
+
+    /// Serialize the profile to TOML.
+    pub fn to_toml_string(&self) -> String {
+        // unwrap(): profiles only contain types representable in TOML.
+        toml::to_string(self).unwrap()
+    }
+
+    /// The device arguments of the profile.
+    pub fn device_args(&self) -> Result<DeviceArgs, ProfileError> {
+        match &self.args {
+            Some(args) => args
+                .parse()
+                .map_err(|e| ProfileError::Invalid(vec![Diagnostic::new("args", format!("{e}"))])),
+            None => Ok(DeviceArgs::new()),
+        }
+    }
+
+    /// Open the device described by the profile's device arguments.
+    pub fn open(&self) -> Result<Usrp, ProfileError> {
+        Ok(Usrp::open(self.device_args()?)?)
+    }
+
+    /// Check the profile against the capabilities of a device.
+    ///
+    /// Returns a diagnostic for each value the device does not support.
+    pub fn validate(&self, caps: &DeviceCapabilities) -> Vec<Diagnostic> {
+        let mut diags = self.diagnostics();
+        for (i, mb) in self.mboards.iter().enumerate() {
+            let key = format!("mboards[{i}]");
+            let mb_caps = match caps.mboards.get(i) {
+                Some(mb_caps) => mb_caps,
+                None => {
+                    diags.push(Diagnostic::new(
+                        key,
+                        format!("device only has {} motherboard(s)", caps.mboards.len()),
+                    ));
+                    continue;
+                }
+            };
+            check_choice(
+                &mut diags,
+                &format!("{key}.clock_source"),
+                mb.clock_source.as_deref(),
+                &mb_caps.clock_sources,
+            );
+            check_choice(
+                &mut diags,
+                &format!("{key}.time_source"),
+                mb.time_source.as_deref(),
+                &mb_caps.time_sources,
+            );
+        }
+        for (dir, profiles, caps) in [("rx", &self.rx, &caps.rx), ("tx", &self.tx, &caps.tx)] {
+            for (i, ch) in profiles.iter().enumerate() {
+                let key = format!("{dir}[{i}]");
+                match caps.get(i) {
+                    Some(ch_caps) => ch.validate(&mut diags, &key, ch_caps),
+                    None => diags.push(Diagnostic::new(
+                        key,
+                        format!(
+                            "device only has {} {} channel(s)",
+                            caps.len(),
+                            dir.to_uppercase()
+                        ),
+                    )),
+                }
+            }
+        }
+        for (dir, stream, n) in [
+            ("rx", &self.rx_stream, caps.rx.len()),
+            ("tx", &self.tx_stream, caps.tx.len()),
+        ] {
+            let stream = match stream {
+                Some(stream) => stream,
+                None => continue,
+            };
+            for (i, &ch) in stream.channels.iter().enumerate() {
+                if ch >= n {
+                    diags.push(Diagnostic::new(
+                        format!("{dir}_stream.channels[{i}]"),
+                        format!(
+                            "channel {ch} does not exist, device only has {n} {} channel(s)",
+                            dir.to_uppercase()
+                        ),
+                    ));
+                }
+            }
+        }
+        diags
+    }
+
+    /// Validate the profile against the device, then apply it.
+    ///
+    /// Nothing is applied if validation fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProfileError::Invalid`] listing every problem found during
+    /// validation, or [`ProfileError::Apply`] listing every setting which
+    /// the device rejected.
+    pub fn apply(&self, usrp: &Usrp) -> Result<(), ProfileError> {
+        let diags = self.validate(&DeviceCapabilities::read(usrp)?);
+        if !diags.is_empty() {
+            return Err(ProfileError::Invalid(diags));
+        }
+        usrp.apply(&self.to_config())
+            .into_result()
+            .map_err(ProfileError::Apply)
+    }
+
+    /// The device configuration described by the profile.
+    pub fn to_config(&self) -> DeviceConfig {
+        DeviceConfig {
+            mboards: self
+                .mboards
+                .iter()
+                .map(|mb| MboardSettings {
+                    clock_source: mb.clock_source.clone(),
+                    time_source: mb.time_source.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            rx: self.rx.iter().map(ChannelProfile::to_settings).collect(),
+            tx: self.tx.iter().map(ChannelProfile::to_settings).collect(),
+        }
+    }
+
+    /// Create an RX stream builder with the profile's stream settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProfileError::Invalid`] if the profile's CPU format doesn't match `T`.
+    pub fn rx_stream<'a, T: Sample>(
+        &self,
+        usrp: &'a Usrp,
+    ) -> Result<RxStreamBuilder<'a, T>, ProfileError> {
+        let mut builder = usrp.rx_stream::<T>();
+        if let Some(stream) = &self.rx_stream {
+            stream.check_cpu_format::<T>("rx_stream")?;
+            if let Some(otw) = stream.otw_format {
+                builder.with_otw_format(otw);
+            }
+            if !stream.channels.is_empty() {
+                builder.with_channels(&stream.channels);
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Create a TX stream builder with the profile's stream settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProfileError::Invalid`] if the profile's CPU format doesn't match `T`.
+    pub fn tx_stream<'a, T: Sample>(
+        &self,
+        usrp: &'a Usrp,
+    ) -> Result<TxStreamBuilder<'a, T>, ProfileError> {
+        let mut builder = usrp.tx_stream::<T>();
+        if let Some(stream) = &self.tx_stream {
+            stream.check_cpu_format::<T>("tx_stream")?;
+            if let Some(otw) = stream.otw_format {
+                builder.with_otw_format(otw);
+            }
+            if !stream.channels.is_empty() {
+                builder.with_channels(&stream.channels);
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Fail if any values are invalid regardless of the device.
+    fn check(&self) -> Result<(), ProfileError> {
+        let diags = self.diagnostics();
+        if diags.is_empty() {
+            Ok(())
+        } else {
+            Err(ProfileError::Invalid(diags))
+        }
+    }
+
+    /// Problems with values which are invalid regardless of the device.
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diags = Vec::new();
+        if let Err(ProfileError::Invalid(mut d)) = self.device_args() {
+            diags.append(&mut d);
+        }
+        for (key, stream) in [
+            ("rx_stream", &self.rx_stream),
+            ("tx_stream", &self.tx_stream),
+        ] {
+            let format = match stream.as_ref().and_then(|s| s.cpu_format.as_deref()) {
+                Some(format) => format,
+                None => continue,
+            };
+            if !CPU_FORMATS.contains(&format) {
+                diags.push(Diagnostic::new(
+                    format!("{key}.cpu_format"),
+                    format!(
+                        "unknown format {format:?}, expected one of {}",
+                        CPU_FORMATS.join(", ")
+                    ),
+                ));
+            }
+        }
+        diags
+    }
+}
+
+impl ChannelProfile {
+    fn validate(&self, diags: &mut Vec<Diagnostic>, key: &str, caps: &ChannelCapabilities) {
+        check_choice(
+            diags,
+            &format!("{key}.antenna"),
+            self.antenna.as_deref(),
+            &caps.antennas,
+        );
+        let ranges = [
+            ("center_freq", self.center_freq, &caps.center_freq, "Hz"),
+            ("gain", self.gain, &caps.gain, "dB"),
+            ("bandwidth", self.bandwidth, &caps.bandwidth, "Hz"),
+            ("sample_rate", self.sample_rate, &caps.sample_rate, "Hz"),
+        ];
+        for (name, value, ranges, unit) in ranges {
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+            if !ranges.is_empty() && !ranges.iter().any(|r| in_range(r, value)) {
+                diags.push(Diagnostic::new(
+                    format!("{key}.{name}"),
+                    format!(
+                        "{value} {unit} is outside the supported range {}",
+                        format_ranges(ranges, unit)
+                    ),
+                ));
+            }
+        }
+    }
+
+    fn to_settings(&self) -> ChannelSettings {
+        ChannelSettings {
+            antenna: self.antenna.clone(),
+            sample_rate: self.sample_rate,
+            center_freq: self.center_freq,
+            bandwidth: self.bandwidth,
+            gain: self.gain,
+            ..Default::default()
+        }
+    }
+}
+
+impl StreamProfile {
+    fn check_cpu_format<T: Sample>(&self, key: &str) -> Result<(), ProfileError> {
+        match self.cpu_format.as_deref() {
+            Some(format) if format != T::name() => {
+                Err(ProfileError::Invalid(vec![Diagnostic::new(
+                    format!("{key}.cpu_format"),
+                    format!(
+                        "profile requires {format:?} but the stream uses {:?}",
+                        T::name()
+                    ),
+                )]))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check_choice(diags: &mut Vec<Diagnostic>, key: &str, value: Option<&str>, choices: &[String]) {
+    let value = match value {
+        Some(value) => value,
+        None => return,
+    };
+    if !choices.is_empty() && !choices.iter().any(|c| c == value) {
+        diags.push(Diagnostic::new(
+            key,
+            format!(
+                "{value:?} is not supported, expected one of {}",
+                choices.join(", ")
+            ),
+        ));
+    }
+}
+
+/// Whether a value lies within a range, allowing for rounding errors.
+///
+/// Steps aren't checked since UHD coerces values to the nearest step.
+fn in_range(range: &Range, value: f64) -> bool {
+    let eps = 1e-9 * range.start.abs().max(range.stop.abs()).max(1.0);
+    value >= range.start - eps && value <= range.stop + eps
+}
+
+fn format_ranges(ranges: &[Range], unit: &str) -> String {
+    ranges
+        .iter()
+        .map(|r| {
+            if r.start == r.stop {
+                format!("{} {unit}", r.start)
+            } else {
+                format!("{} to {} {unit}", r.start, r.stop)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROFILE: &str = r#"
+        args = "type=b200,serial=3123ABC"
+
+        [[mboards]]
+        clock_source = "external"
+        time_source = "external"
+
+        [[rx]]
+        antenna = "RX2"
+        center_freq = 2.4e9
+        gain = 30.0
+        bandwidth = 20e6
+        sample_rate = 10e6
+
+        [rx_stream]
+        cpu_format = "fc32"
+        otw_format = "sc16"
+        channels = [0]
+    "#;
+
+    fn range(start: f64, stop: f64) -> Range {
+        Range {
+            start,
+            stop,
+            step: 0.0,
+        }
+    }
+
+    fn caps() -> DeviceCapabilities {
+        DeviceCapabilities {
+            mboards: vec![MboardCapabilities {
+                clock_sources: vec!["internal".into(), "external".into()],
+                time_sources: vec!["none".into(), "internal".into(), "external".into()],
+            }],
+            rx: vec![ChannelCapabilities {
+                antennas: vec!["TX/RX".into(), "RX2".into()],
+                center_freq: vec![range(70e6, 6e9)],
+                gain: vec![range(0.0, 76.0)],
+                bandwidth: vec![range(200e3, 56e6)],
+                sample_rate: vec![],
+            }],
+            tx: vec![],
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let profile = RadioProfile::from_toml_str(PROFILE).unwrap();
+        assert_eq!(profile.device_args().unwrap().get_serial(), Some("3123ABC"));
+        assert_eq!(profile.rx[0].center_freq, Some(2.4e9));
+        let stream = profile.rx_stream.as_ref().unwrap();
+        assert_eq!(stream.otw_format, Some(OtwFormat::ComplexInt16));
+        assert_eq!(stream.channels, [0]);
+        assert_eq!(
+            RadioProfile::from_toml_str(&profile.to_toml_string()).unwrap(),
+            profile
+        );
+
+        let config = profile.to_config();
+        assert_eq!(config.mboards[0].clock_source.as_deref(), Some("external"));
+        assert_eq!(config.rx[0].gain, Some(30.0));
+        assert!(config.tx.is_empty());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let err = RadioProfile::from_toml_str("[[rx]]\ngian = 3.0\n").unwrap_err();
+        assert!(err.to_string().contains("gian"), "{err}");
+        let err = RadioProfile::from_toml_str("[rx_stream]\notw_format = \"sc9\"\n").unwrap_err();
+        assert!(matches!(err, ProfileError::Toml(_)), "{err}");
+
+        let err = RadioProfile::from_toml_str(
+            "args = \"type=b200,addr=a=b\"\n[tx_stream]\ncpu_format = \"fc16\"\n",
+        )
+        .unwrap_err();
+        match err {
+            ProfileError::Invalid(diags) => {
+                let keys: Vec<_> = diags.iter().map(|d| d.key.as_str()).collect();
+                assert_eq!(keys, ["args", "tx_stream.cpu_format"]);
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[test]
+    fn validation() {
+        let profile = RadioProfile::from_toml_str(PROFILE).unwrap();
+        assert_eq!(profile.validate(&caps()), []);
+
+        let mut profile = profile;
+        profile.mboards[0].time_source = Some("gpsdo".into());
+        profile.rx[0].antenna = Some("RX1".into());
+        profile.rx[0].gain = Some(90.0);
+        profile.rx[0].sample_rate = Some(1e12);
+        profile.rx.push(ChannelProfile::default());
+        profile.rx_stream.as_mut().unwrap().channels = vec![0, 3];
+        let diags = profile.validate(&caps());
+        let keys: Vec<_> = diags.iter().map(|d| d.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "mboards[0].time_source",
+                "rx[0].antenna",
+                "rx[0].gain",
+                "rx[1]",
+                "rx_stream.channels[1]",
+            ]
+        );
+        assert_eq!(
+            diags[2].to_string(),
+            "rx[0].gain: 90 dB is outside the supported range 0 to 76 dB"
+        );
+
+        let err = ProfileError::Invalid(diags);
+        assert!(err
+            .to_string()
+            .starts_with("invalid profile:\n  mboards[0].time_source: "));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml() {
+        let profile = RadioProfile::from_yaml_str(
+            "args: type=b200\nrx:\n  - antenna: RX2\n    gain: 30.0\ntx_stream:\n  otw_format: sc8\n",
+        )
+        .unwrap();
+        assert_eq!(profile.rx[0].antenna.as_deref(), Some("RX2"));
+        assert_eq!(
+            profile.tx_stream.unwrap().otw_format,
+            Some(OtwFormat::ComplexInt8)
+        );
+        assert!(RadioProfile::from_yaml_str("rx:\n  - gian: 3\n").is_err());
+    }
+}
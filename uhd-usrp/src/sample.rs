@@ -85,3 +85,75 @@ unsafe impl Sample for [i16; 2] {
         "sc16"
     }
 }
+
+/// A complex sample type which can be converted to and from floating-point IQ values.
+///
+/// Integer samples use UHD's scaling convention, where full scale
+/// corresponds to a floating-point magnitude of `1.0`.
+pub trait IqSample: Sample + Copy {
+    /// Convert from IQ values, saturating if they are out of range.
+    fn from_iq(iq: [f64; 2]) -> Self;
+    /// Convert to IQ values.
+    fn to_iq(self) -> [f64; 2];
+}
+
+macro_rules! iq_sample_float {
+    ($t:ty) => {
+        impl IqSample for [$t; 2] {
+            fn from_iq(iq: [f64; 2]) -> Self {
+                [iq[0] as $t, iq[1] as $t]
+            }
+
+            fn to_iq(self) -> [f64; 2] {
+                [self[0] as f64, self[1] as f64]
+            }
+        }
+
+        #[cfg(feature = "num")]
+        impl IqSample for num_complex::Complex<$t> {
+            fn from_iq(iq: [f64; 2]) -> Self {
+                Self::new(iq[0] as $t, iq[1] as $t)
+            }
+
+            fn to_iq(self) -> [f64; 2] {
+                [self.re as f64, self.im as f64]
+            }
+        }
+    };
+}
+
+macro_rules! iq_sample_int {
+    ($t:ty) => {
+        impl IqSample for [$t; 2] {
+            fn from_iq(iq: [f64; 2]) -> Self {
+                // Float to int casts saturate, so out of range values are clipped.
+                let scale = |x: f64| (x * <$t>::MAX as f64).round() as $t;
+                [scale(iq[0]), scale(iq[1])]
+            }
+
+            fn to_iq(self) -> [f64; 2] {
+                [
+                    self[0] as f64 / <$t>::MAX as f64,
+                    self[1] as f64 / <$t>::MAX as f64,
+                ]
+            }
+        }
+
+        #[cfg(feature = "num")]
+        impl IqSample for num_complex::Complex<$t> {
+            fn from_iq(iq: [f64; 2]) -> Self {
+                let [re, im] = <[$t; 2]>::from_iq(iq);
+                Self::new(re, im)
+            }
+
+            fn to_iq(self) -> [f64; 2] {
+                [self.re, self.im].to_iq()
+            }
+        }
+    };
+}
+
+iq_sample_float!(f32);
+iq_sample_float!(f64);
+iq_sample_int!(i8);
+iq_sample_int!(i16);
@@ -0,0 +1,562 @@
+//! Recording of RX streams in the [SigMF](https://sigmf.org) format.
+//!
+//! A recording consists of a `.sigmf-data` file holding the raw samples, and a
+//! `.sigmf-meta` file describing them. Channels are interleaved sample by sample
+//! in the data file.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use num_complex::Complex32;
+//! use uhd_usrp::{sigmf::{SigmfCaptureInfo, SigmfWriter}, Channel, Usrp};
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let info = SigmfCaptureInfo::from_channel(&usrp.channel(Channel::Rx(0)).unwrap()).unwrap();
+//!
+//! let mut stream = usrp.rx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+//! stream.start_command().send().unwrap();
+//!
+//! let mut writer = SigmfWriter::<Complex32>::create("capture", info).unwrap();
+//! writer.record(&mut stream, 1_000_000, Duration::from_secs(1)).unwrap();
+//! writer.finish().unwrap();
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    types::RxErrorCode, ArrayBuffer, ChannelConfig, HardwareInfo, RxMetadata, RxStreamer, Sample,
+    SampleBuffer, TimeSpec, UhdError,
+};
+
+/// Version of the SigMF specification written by [`SigmfWriter`].
+pub const SIGMF_VERSION: &str = "1.2.0";
+
+/// An error which occurred while writing a SigMF recording.
+#[derive(thiserror::Error, Debug)]
+pub enum SigmfError {
+    #[error("sample format {0:?} has no SigMF datatype")]
+    UnsupportedSample(&'static str),
+    #[error("expected {expected} channels, got {actual}")]
+    ChannelMismatch { expected: usize, actual: usize },
+    #[error("invalid sample rate {0}")]
+    InvalidSampleRate(f64),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+/// Channel state recorded in a SigMF recording.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SigmfCaptureInfo {
+    /// The sample rate in samples per second. Must be positive and finite.
+    pub sample_rate: f64,
+    pub center_freq: f64,
+    pub gain: Option<f64>,
+    pub antenna: Option<String>,
+    /// Description of the hardware, written to `core:hw`.
+    pub hardware: Option<String>,
+    pub description: Option<String>,
+    /// Whether the device time is synchronized to UTC (e.g. with the host clock
+    /// or GPS), in which case `core:datetime` is derived from sample timestamps.
+    pub utc_device_time: bool,
+}
+
+/// Writes samples and metadata to a SigMF recording.
+///
+/// A new capture segment is started whenever the sample timestamps jump,
+/// which is annotated as a discontinuity. Overflows reported by the device
+/// are annotated as well.
+///
+/// The metadata file is written by [`SigmfWriter::finish`], or when the
+/// writer is dropped.
+pub struct SigmfWriter<T: Sample> {
+    data: BufWriter<File>,
+    meta_path: PathBuf,
+    info: SigmfCaptureInfo,
+    datatype: String,
+    channels: usize,
+    captures: Vec<Value>,
+    annotations: Vec<Value>,
+    /// Number of samples per channel written so far.
+    written: u64,
+    /// Expected timestamp of the next sample.
+    next_time: Option<TimeSpec>,
+    finished: bool,
+    _phantom: PhantomData<T>,
+}
+
+impl SigmfCaptureInfo {
+    /// Read the current state of a channel.
+    pub fn from_channel(channel: &ChannelConfig) -> Result<Self, UhdError> {
+        Ok(Self {
+            sample_rate: channel.sample_rate()?,
+            center_freq: channel.center_freq()?,
+            gain: Some(channel.gain(None)?),
+            antenna: Some(channel.antenna()?),
+            hardware: Some(describe_hardware(&channel.hardware_info()?)),
+            description: None,
+            utc_device_time: false,
+        })
+    }
+}
+
+/// Get the SigMF datatype of a sample type on this machine, e.g. `cf32_le`.
+///
+/// Returns `None` if the sample format has no SigMF equivalent.
+pub fn datatype<T: Sample>() -> Option<String> {
+    let (name, sized) = match T::name() {
+        "fc64" => ("cf64", true),
+        "fc32" => ("cf32", true),
+        "sc16" => ("ci16", true),
+        "sc8" => ("ci8", false),
+        "f64" => ("rf64", true),
+        "f32" => ("rf32", true),
+        "s16" => ("ri16", true),
+        "s8" => ("ri8", false),
+        _ => return None,
+    };
+    let endian = match cfg!(target_endian = "little") {
+        true => "_le",
+        false => "_be",
+    };
+    Some(match sized {
+        true => format!("{name}{endian}"),
+        false => name.to_string(),
+    })
+}
+
+impl<T: Sample> SigmfWriter<T> {
+    /// Create `<base>.sigmf-data` and `<base>.sigmf-meta`, overwriting existing files.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SigmfError::UnsupportedSample`] if `T` has no SigMF datatype, and
+    /// [`SigmfError::InvalidSampleRate`] if the sample rate is not positive and finite.
+    pub fn create(base: impl AsRef<Path>, info: SigmfCaptureInfo) -> Result<Self, SigmfError> {
+        Self::create_with_channels(base, info, 1)
+    }
+
+    /// Like [`SigmfWriter::create`], for a recording of multiple channels.
+    pub fn create_with_channels(
+        base: impl AsRef<Path>,
+        info: SigmfCaptureInfo,
+        channels: usize,
+    ) -> Result<Self, SigmfError> {
+        let datatype = datatype::<T>().ok_or(SigmfError::UnsupportedSample(T::name()))?;
+        if !(info.sample_rate > 0.0 && info.sample_rate.is_finite()) {
+            return Err(SigmfError::InvalidSampleRate(info.sample_rate));
+        }
+        let base = base.as_ref().as_os_str();
+        let with_extension = |ext: &str| {
+            let mut path = base.to_os_string();
+            path.push(ext);
+            PathBuf::from(path)
+        };
+        let data = BufWriter::new(File::create(with_extension(".sigmf-data"))?);
+        Ok(Self {
+            data,
+            meta_path: with_extension(".sigmf-meta"),
+            info,
+            datatype,
+            channels,
+            captures: Vec::new(),
+            annotations: Vec::new(),
+            written: 0,
+            next_time: None,
+            finished: false,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Number of samples per channel written so far.
+    pub fn samples_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Write the first `samples` samples of each channel of the buffer.
+    ///
+    /// `metadata` is the metadata of the receive which filled the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SigmfError::ChannelMismatch`] if the buffer doesn't have the
+    /// number of channels the writer was created with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `samples` is larger than the buffer.
+    pub fn write(
+        &mut self,
+        buff: &impl SampleBuffer<T>,
+        samples: usize,
+        metadata: &RxMetadata,
+    ) -> Result<(), SigmfError> {
+        if buff.channels() != self.channels {
+            return Err(SigmfError::ChannelMismatch {
+                expected: self.channels,
+                actual: buff.channels(),
+            });
+        }
+        assert!(samples <= buff.samples(), "buffer is too small");
+        if metadata.error_code == Some(RxErrorCode::Overflow) {
+            self.annotate(json!({
+                "core:label": "overflow",
+                "core:comment": "samples were dropped by the device",
+            }));
+        }
+        if samples == 0 {
+            return Ok(());
+        }
+
+        self.update_capture(metadata.time_spec());
+        let ptrs = buff.as_ptr();
+        // Safety: the buffer has `self.channels` channels of at least `samples` samples.
+        let channels: Vec<&[T]> = (0..self.channels)
+            .map(|c| unsafe { std::slice::from_raw_parts(*ptrs.add(c), samples) })
+            .collect();
+        match channels.as_slice() {
+            [channel] => self.data.write_all(as_bytes(channel))?,
+            _ => {
+                for i in 0..samples {
+                    for channel in &channels {
+                        self.data.write_all(as_bytes(&channel[i..=i]))?;
+                    }
+                }
+            }
+        }
+        self.written += samples as u64;
+        let duration = TimeSpec::try_from_parts(0, samples as f64 / self.info.sample_rate);
+        self.next_time = metadata.time_spec().zip(duration).map(|(t, d)| t + d);
+        Ok(())
+    }
+
+    /// Receive up to `samples` samples per channel from the stream and write them.
+    ///
+    /// Stops early once the stream ends a burst or times out, and returns the
+    /// number of samples per channel written.
+    pub fn record(
+        &mut self,
+        stream: &mut impl RxStreamer<T>,
+        samples: usize,
+        timeout: Duration,
+    ) -> Result<usize, SigmfError>
+    where
+        T: Clone + Default,
+    {
+        let mut buff = ArrayBuffer::new(stream.channels(), stream.max_samples_per_channel());
+        let mut md = RxMetadata::new();
+        let mut recorded = 0;
+        while recorded < samples {
+            let received = stream.recv(&mut buff, timeout, &mut md)?;
+            if md.error_code == Some(RxErrorCode::Timeout) {
+                break;
+            }
+            let received = received.min(samples - recorded);
+            self.write(&buff, received, &md)?;
+            recorded += received;
+            if md.end_of_burst() {
+                break;
+            }
+        }
+        Ok(recorded)
+    }
+
+    /// Flush the data file and write the metadata file.
+    pub fn finish(mut self) -> Result<(), SigmfError> {
+        self.write_meta()
+    }
+
+    /// Start a new capture segment if the samples don't follow on from the last ones.
+    fn update_capture(&mut self, time: Option<TimeSpec>) {
+        let gap = match (time, self.next_time) {
+            (Some(time), Some(expected)) => (time - expected).as_secs(),
+            _ => 0.0,
+        };
+        let missing = gap * self.info.sample_rate;
+        let discontinuous = missing.is_finite() && missing.abs() >= 0.5;
+        if !self.captures.is_empty() && !discontinuous {
+            return;
+        }
+        if discontinuous {
+            self.annotate(json!({
+                "core:label": "discontinuity",
+                "core:comment": format!("timestamps jumped by {gap} s"),
+                "uhd:missing_samples": missing.round() as i64,
+            }));
+        }
+
+        let mut capture = Map::new();
+        capture.insert("core:sample_start".into(), json!(self.written));
+        capture.insert("core:frequency".into(), json!(self.info.center_freq));
+        if let Some(time) = time {
+            if self.info.utc_device_time {
+                capture.insert("core:datetime".into(), json!(iso8601(time)));
+            }
+            capture.insert("uhd:time_spec".into(), json!(time.as_secs()));
+        }
+        if let Some(gain) = self.info.gain {
+            capture.insert("uhd:gain".into(), json!(gain));
+        }
+        if let Some(antenna) = &self.info.antenna {
+            capture.insert("uhd:antenna".into(), json!(antenna));
+        }
+        self.captures.push(Value::Object(capture));
+    }
+
+    fn annotate(&mut self, mut annotation: Value) {
+        annotation["core:sample_start"] = json!(self.written);
+        annotation["core:sample_count"] = json!(0);
+        self.annotations.push(annotation);
+    }
+
+    fn write_meta(&mut self) -> Result<(), SigmfError> {
+        self.finished = true;
+        self.data.flush()?;
+        let mut global = Map::new();
+        global.insert("core:datatype".into(), json!(self.datatype));
+        global.insert("core:sample_rate".into(), json!(self.info.sample_rate));
+        global.insert("core:version".into(), json!(SIGMF_VERSION));
+        global.insert("core:num_channels".into(), json!(self.channels));
+        global.insert("core:recorder".into(), json!("uhd-usrp"));
+        if let Some(hardware) = &self.info.hardware {
+            global.insert("core:hw".into(), json!(hardware));
+        }
+        if let Some(description) = &self.info.description {
+            global.insert("core:description".into(), json!(description));
+        }
+        global.insert(
+            "core:extensions".into(),
+            json!([{ "name": "uhd", "version": "0.1.0", "optional": true }]),
+        );
+        let meta = json!({
+            "global": global,
+            "captures": self.captures,
+            "annotations": self.annotations,
+        });
+        let file = BufWriter::new(File::create(&self.meta_path)?);
+        serde_json::to_writer_pretty(file, &meta)?;
+        Ok(())
+    }
+}
+
+impl<T: Sample> Drop for SigmfWriter<T> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.write_meta();
+        }
+    }
+}
+
+fn as_bytes<T: Sample>(samples: &[T]) -> &[u8] {
+    // Safety: sample types are plain data with the memory layout of their format.
+    unsafe { std::slice::from_raw_parts(samples.as_ptr().cast(), std::mem::size_of_val(samples)) }
+}
+
+fn describe_hardware(info: &HardwareInfo) -> String {
+    format!(
+        "{} {} (serial {}), {} {} (serial {})",
+        info.mboard_id(),
+        info.mboard_name(),
+        info.mboard_serial(),
+        info.dboard_id(),
+        info.dboard_subdev_name(),
+        info.dboard_serial(),
+    )
+}
+
+/// Format a time since the UNIX epoch as an ISO 8601 UTC timestamp.
+fn iso8601(time: TimeSpec) -> String {
+    let secs = time.full_secs();
+    let nanos = ((time.frac_secs() * 1e9).round() as u32).min(999_999_999);
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{nanos:09}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+    )
+}
+
+/// Convert days since the UNIX epoch to a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::{sim::SimDevice, stream::StreamCommand, Channel, Device, StreamDevice};
+
+    fn temp_base(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("uhd-usrp-{name}-{}", std::process::id()))
+    }
+
+    fn path(base: &Path, ext: &str) -> PathBuf {
+        let mut path = base.as_os_str().to_os_string();
+        path.push(ext);
+        path.into()
+    }
+
+    /// Read the metadata and the size of the data file, and remove both.
+    fn read_and_remove(base: &Path) -> (Value, u64) {
+        let meta = serde_json::from_slice(&fs::read(path(base, ".sigmf-meta")).unwrap()).unwrap();
+        let len = fs::metadata(path(base, ".sigmf-data")).unwrap().len();
+        fs::remove_file(path(base, ".sigmf-meta")).unwrap();
+        fs::remove_file(path(base, ".sigmf-data")).unwrap();
+        (meta, len)
+    }
+
+    fn info(sample_rate: f64) -> SigmfCaptureInfo {
+        SigmfCaptureInfo {
+            sample_rate,
+            center_freq: 915e6,
+            gain: Some(10.0),
+            antenna: Some("RX2".to_string()),
+            utc_device_time: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn datatypes() {
+        assert_eq!(datatype::<[f32; 2]>().unwrap(), "cf32_le");
+        assert_eq!(datatype::<[i16; 2]>().unwrap(), "ci16_le");
+        assert_eq!(datatype::<[i8; 2]>().unwrap(), "ci8");
+        assert_eq!(datatype::<f64>().unwrap(), "rf64_le");
+    }
+
+    #[test]
+    fn rejects_invalid_sample_rate() {
+        let base = temp_base("invalid-rate");
+        for rate in [0.0, -1e6, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                SigmfWriter::<[f32; 2]>::create(&base, info(rate)),
+                Err(SigmfError::InvalidSampleRate(_))
+            ));
+        }
+        assert!(SigmfWriter::<[f32; 2]>::create(&base, SigmfCaptureInfo::default()).is_err());
+        assert!(!path(&base, ".sigmf-data").exists());
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(iso8601(TimeSpec::ZERO), "1970-01-01T00:00:00.000000000Z");
+        assert_eq!(
+            iso8601(TimeSpec::from_parts(1_709_210_096, 0.25)),
+            "2024-02-29T12:34:56.250000000Z"
+        );
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn records_stream() {
+        let device = SimDevice::new(1, 0);
+        device.set_sample_rate(Channel::Rx(0), 1e6).unwrap();
+        device
+            .set_time(0, TimeSpec::from_secs(1_700_000_000))
+            .unwrap();
+        let mut stream = device.open_rx_stream(&[0]).unwrap();
+        stream
+            .issue_stream_cmd(StreamCommand::NumSamples {
+                samples: 5000,
+                done: true,
+                time: None,
+            })
+            .unwrap();
+
+        let base = temp_base("sigmf-record");
+        let mut writer = SigmfWriter::<[i16; 2]>::create(&base, info(1e6)).unwrap();
+        let recorded = writer
+            .record(&mut stream, 10_000, Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(recorded, 5000);
+        writer.finish().unwrap();
+
+        let (meta, len) = read_and_remove(&base);
+        assert_eq!(len, 5000 * 4);
+        assert_eq!(meta["global"]["core:datatype"], "ci16_le");
+        assert_eq!(meta["global"]["core:sample_rate"], 1e6);
+        assert_eq!(meta["global"]["core:num_channels"], 1);
+        let captures = meta["captures"].as_array().unwrap();
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0]["core:sample_start"], 0);
+        assert_eq!(captures[0]["core:frequency"], 915e6);
+        assert_eq!(
+            captures[0]["core:datetime"],
+            "2023-11-14T22:13:20.000000000Z"
+        );
+        assert_eq!(captures[0]["uhd:antenna"], "RX2");
+        assert!(meta["annotations"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn annotates_gaps() {
+        let base = temp_base("sigmf-gaps");
+        let mut writer =
+            SigmfWriter::<[f32; 2]>::create_with_channels(&base, info(1e3), 2).unwrap();
+        let buff = ArrayBuffer::new(2, 100);
+        let md = |time: f64, error_code| RxMetadata {
+            time_spec: Some(TimeSpec::from_secs_f64(time)),
+            error_code: Some(error_code),
+            ..RxMetadata::new()
+        };
+        writer
+            .write(&buff, 100, &md(0.0, RxErrorCode::None))
+            .unwrap();
+        writer
+            .write(&buff, 100, &md(0.1, RxErrorCode::None))
+            .unwrap();
+        writer
+            .write(&buff, 0, &md(0.2, RxErrorCode::Overflow))
+            .unwrap();
+        writer
+            .write(&buff, 50, &md(0.5, RxErrorCode::None))
+            .unwrap();
+        assert!(matches!(
+            writer.write(&ArrayBuffer::new(1, 10), 10, &RxMetadata::new()),
+            Err(SigmfError::ChannelMismatch { .. })
+        ));
+        assert_eq!(writer.samples_written(), 250);
+        drop(writer);
+
+        let (meta, len) = read_and_remove(&base);
+        assert_eq!(len, 250 * 2 * 8);
+        let starts: Vec<_> = meta["captures"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["core:sample_start"].as_u64().unwrap())
+            .collect();
+        assert_eq!(starts, [0, 200]);
+        let annotations = meta["annotations"].as_array().unwrap();
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0]["core:label"], "overflow");
+        assert_eq!(annotations[0]["core:sample_start"], 200);
+        assert_eq!(annotations[1]["core:label"], "discontinuity");
+        assert_eq!(annotations[1]["uhd:missing_samples"], 300);
+    }
+}
@@ -0,0 +1,680 @@
+//! In-process simulated USRP.
+//!
+//! [`SimDevice`] implements the [`Device`] and [`StreamDevice`] traits without
+//! any hardware, which allows application code to be tested in CI.
+//!
+//! The simulation honors the configured sample rate, center frequency,
+//! bandwidth and gain of each channel, stream commands and timestamps.
+//! RX channels produce a configurable sum of [`SimSignal`]s, and everything
+//! sent to a TX stream is captured for later inspection. TX streams also
+//! report burst acknowledgements, late packets and underflows as
+//! asynchronous messages.
+//!
+//! Device time is virtual: it advances only as samples are streamed or
+//! as a stream waits for a timed command, so simulations run as fast as the
+//! host allows and are fully deterministic.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//! use num_complex::Complex32;
+//! use uhd_usrp::{
+//!     sim::{SimDevice, SimSignal},
+//!     stream::StreamCommand,
+//!     ArrayBuffer, Channel, Device, RxMetadata, RxStreamer, StreamDevice,
+//! };
+//!
+//! let sim = SimDevice::new(1, 1);
+//! sim.set_center_freq(Channel::Rx(0), 100e6).unwrap();
+//! sim.add_rx_signal(0, SimSignal::tone(100.1e6, 0.5)).unwrap();
+//!
+//! let mut stream: <SimDevice as StreamDevice<Complex32>>::RxStream =
+//!     sim.open_rx_stream(&[0]).unwrap();
+//! stream
+//!     .issue_stream_cmd(StreamCommand::NumSamples { samples: 100, done: true, time: None })
+//!     .unwrap();
+//!
+//! let mut buff = ArrayBuffer::<Complex32>::new(1, 100);
+//! let mut md = RxMetadata::new();
+//! let n = stream.recv(&mut buff, Duration::from_secs(1), &mut md).unwrap();
+//! assert_eq!(n, 100);
+//! assert!(md.end_of_burst());
+//! assert!((buff[0][10].norm() - 0.5).abs() < 1e-6);
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{Channel, Device, IqSample, Result, StreamDevice, TimeSpec, UhdError};
+
+mod signal;
+mod stream;
+
+pub use signal::SimSignal;
+pub use stream::{SimRxStream, SimTxStream, TxCapture};
+
+/// A simulated single-motherboard USRP.
+///
+/// Cloning a `SimDevice` returns another handle to the same device, which
+/// can be used to inspect TX captures while the original is in use.
+#[derive(Clone, Debug)]
+pub struct SimDevice {
+    state: Arc<Mutex<SimState>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SimState {
+    pub rx: Vec<ChannelState>,
+    pub tx: Vec<ChannelState>,
+    pub time: TimeSpec,
+    pub captures: Vec<TxCapture>,
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ChannelState {
+    pub antenna: String,
+    pub antennas: &'static [&'static str],
+    pub bandwidth: f64,
+    pub center_freq: f64,
+    pub gain: f64,
+    pub sample_rate: f64,
+    pub signals: Vec<SimSignal>,
+}
+
+impl SimDevice {
+    /// Name of the only gain element of each simulated channel.
+    pub const GAIN_NAME: &'static str = "PGA";
+    /// Maximum number of samples per channel in a packet.
+    pub const MAX_SAMPLES_PER_PACKET: usize = 2000;
+    /// Default sample rate of every channel.
+    pub const DEFAULT_SAMPLE_RATE: f64 = 1e6;
+
+    /// Create a simulated device with the given number of RX and TX channels.
+    ///
+    /// All channels start at a center frequency of 0 Hz with no gain and
+    /// a sample rate (and bandwidth) of [`SimDevice::DEFAULT_SAMPLE_RATE`].
+    pub fn new(rx_channels: usize, tx_channels: usize) -> Self {
+        let channel = |antennas: &'static [&'static str]| ChannelState {
+            antenna: antennas[0].to_owned(),
+            antennas,
+            bandwidth: Self::DEFAULT_SAMPLE_RATE,
+            center_freq: 0.0,
+            gain: 0.0,
+            sample_rate: Self::DEFAULT_SAMPLE_RATE,
+            signals: Vec::new(),
+        };
+        Self {
+            state: Arc::new(Mutex::new(SimState {
+                rx: vec![channel(&["RX2", "TX/RX"]); rx_channels],
+                tx: vec![channel(&["TX/RX"]); tx_channels],
+                time: TimeSpec::ZERO,
+                captures: Vec::new(),
+                seed: 0,
+            })),
+        }
+    }
+
+    /// Set the seed used for noise generation by subsequently opened streams.
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.lock().seed = seed;
+        self
+    }
+
+    /// Add a signal to the input of an RX channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Index`] if the channel does not exist.
+    pub fn add_rx_signal(&self, channel: usize, signal: SimSignal) -> Result<()> {
+        self.lock()
+            .rx
+            .get_mut(channel)
+            .ok_or(UhdError::Index)?
+            .signals
+            .push(signal);
+        Ok(())
+    }
+
+    /// Remove all signals from the input of an RX channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Index`] if the channel does not exist.
+    pub fn clear_rx_signals(&self, channel: usize) -> Result<()> {
+        self.lock()
+            .rx
+            .get_mut(channel)
+            .ok_or(UhdError::Index)?
+            .signals
+            .clear();
+        Ok(())
+    }
+
+    /// Get everything sent to TX streams so far, in the order it was sent.
+    pub fn tx_captures(&self) -> Vec<TxCapture> {
+        self.lock().captures.clone()
+    }
+
+    /// Get all samples sent to a TX channel so far, concatenated.
+    pub fn tx_samples(&self, channel: usize) -> Vec<[f64; 2]> {
+        self.lock()
+            .captures
+            .iter()
+            .filter(|c| c.channel == channel)
+            .flat_map(|c| c.samples.iter().copied())
+            .collect()
+    }
+
+    /// Discard all TX captures.
+    pub fn clear_tx_captures(&self) {
+        self.lock().captures.clear();
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, SimState> {
+        // A panic while holding the lock cannot leave the state inconsistent.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn with_channel<R>(
+        &self,
+        channel: Channel,
+        f: impl FnOnce(&mut ChannelState) -> R,
+    ) -> Result<R> {
+        let mut state = self.lock();
+        let channels = match channel {
+            Channel::Rx(_) => &mut state.rx,
+            Channel::Tx(_) => &mut state.tx,
+        };
+        channels
+            .get_mut(channel.index())
+            .map(f)
+            .ok_or(UhdError::Index)
+    }
+
+    fn check_mboard(mboard: usize) -> Result<()> {
+        match mboard {
+            0 => Ok(()),
+            _ => Err(UhdError::Index),
+        }
+    }
+
+    fn check_gain_name(name: Option<&str>) -> Result<()> {
+        match name {
+            None => Ok(()),
+            Some(name) if name == Self::GAIN_NAME => Ok(()),
+            Some(_) => Err(UhdError::Key),
+        }
+    }
+}
+
+impl SimState {
+    /// Move device time forward, never backward.
+    pub fn advance_time_to(&mut self, time: TimeSpec) {
+        if time > self.time {
+            self.time = time;
+        }
+    }
+}
+
+impl Device for SimDevice {
+    fn n_mboards(&self) -> Result<usize> {
+        Ok(1)
+    }
+
+    fn rx_channels(&self) -> Result<usize> {
+        Ok(self.lock().rx.len())
+    }
+
+    fn tx_channels(&self) -> Result<usize> {
+        Ok(self.lock().tx.len())
+    }
+
+    fn antenna(&self, channel: Channel) -> Result<String> {
+        self.with_channel(channel, |ch| ch.antenna.clone())
+    }
+
+    fn set_antenna(&self, channel: Channel, name: &str) -> Result<()> {
+        self.with_channel(channel, |ch| {
+            if !ch.antennas.contains(&name) {
+                return Err(UhdError::Value);
+            }
+            ch.antenna = name.to_owned();
+            Ok(())
+        })?
+    }
+
+    fn bandwidth(&self, channel: Channel) -> Result<f64> {
+        self.with_channel(channel, |ch| ch.bandwidth)
+    }
+
+    fn set_bandwidth(&self, channel: Channel, bw: f64) -> Result<()> {
+        if bw.is_nan() || bw <= 0.0 {
+            return Err(UhdError::Value);
+        }
+        self.with_channel(channel, |ch| ch.bandwidth = bw)
+    }
+
+    fn center_freq(&self, channel: Channel) -> Result<f64> {
+        self.with_channel(channel, |ch| ch.center_freq)
+    }
+
+    fn set_center_freq(&self, channel: Channel, freq: f64) -> Result<()> {
+        if !freq.is_finite() {
+            return Err(UhdError::Value);
+        }
+        self.with_channel(channel, |ch| ch.center_freq = freq)
+    }
+
+    fn gain(&self, channel: Channel, name: Option<&str>) -> Result<f64> {
+        Self::check_gain_name(name)?;
+        self.with_channel(channel, |ch| ch.gain)
+    }
+
+    fn set_gain(&self, channel: Channel, name: Option<&str>, gain: f64) -> Result<()> {
+        Self::check_gain_name(name)?;
+        if !gain.is_finite() {
+            return Err(UhdError::Value);
+        }
+        self.with_channel(channel, |ch| ch.gain = gain)
+    }
+
+    fn sample_rate(&self, channel: Channel) -> Result<f64> {
+        self.with_channel(channel, |ch| ch.sample_rate)
+    }
+
+    fn set_sample_rate(&self, channel: Channel, rate: f64) -> Result<()> {
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err(UhdError::Value);
+        }
+        self.with_channel(channel, |ch| ch.sample_rate = rate)
+    }
+
+    fn time(&self, mboard: usize) -> Result<TimeSpec> {
+        Self::check_mboard(mboard)?;
+        Ok(self.lock().time)
+    }
+
+    fn set_time(&self, mboard: usize, time: TimeSpec) -> Result<()> {
+        Self::check_mboard(mboard)?;
+        self.lock().time = time;
+        Ok(())
+    }
+}
+
+impl<T: IqSample> StreamDevice<T> for SimDevice {
+    type RxStream = SimRxStream<T>;
+    type TxStream = SimTxStream<T>;
+
+    fn open_rx_stream(&self, channels: &[usize]) -> Result<SimRxStream<T>> {
+        SimRxStream::new(self.clone(), channels)
+    }
+
+    fn open_tx_stream(&self, channels: &[usize]) -> Result<SimTxStream<T>> {
+        SimTxStream::new(self.clone(), channels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        stream::{RxErrorAction, RxErrorPolicy, RxStreamError, StreamCommand},
+        types::{RxErrorCode, TxEventCode},
+        ArrayBuffer, RxMetadata, RxStreamer, TxMetadata, TxMetadataBuilder, TxStreamer,
+    };
+
+    type Stream = SimRxStream<[f64; 2]>;
+
+    fn open_rx(sim: &SimDevice, channels: &[usize]) -> Stream {
+        sim.open_rx_stream(channels).unwrap()
+    }
+
+    fn recv(stream: &mut Stream, samples: usize, timeout: f64) -> (Vec<[f64; 2]>, RxMetadata) {
+        let mut buff = ArrayBuffer::new(1, samples);
+        let mut md = RxMetadata::new();
+        let n = stream
+            .recv(&mut buff, Duration::from_secs_f64(timeout), &mut md)
+            .unwrap();
+        (buff[0][..n].to_vec(), md)
+    }
+
+    fn num_samples(samples: usize, time: Option<TimeSpec>) -> StreamCommand {
+        StreamCommand::NumSamples {
+            samples,
+            done: true,
+            time,
+        }
+    }
+
+    fn phase(iq: [f64; 2]) -> f64 {
+        iq[1].atan2(iq[0])
+    }
+
+    fn magnitude(iq: [f64; 2]) -> f64 {
+        iq[0].hypot(iq[1])
+    }
+
+    #[test]
+    fn tone_is_mixed_to_baseband() {
+        let sim = SimDevice::new(1, 0);
+        sim.set_center_freq(Channel::Rx(0), 100e6).unwrap();
+        sim.set_sample_rate(Channel::Rx(0), 1e6).unwrap();
+        sim.add_rx_signal(0, SimSignal::tone(100.125e6, 0.5))
+            .unwrap();
+
+        let mut stream = open_rx(&sim, &[0]);
+        stream.issue_stream_cmd(num_samples(16, None)).unwrap();
+        let (samples, md) = recv(&mut stream, 16, 1.0);
+        assert_eq!(samples.len(), 16);
+        assert!(md.start_of_burst() && md.end_of_burst());
+        assert_eq!(md.error_code().unwrap(), RxErrorCode::None);
+
+        // 125 kHz at 1 Msps is a quarter turn every other sample.
+        for pair in samples.windows(2) {
+            let step = (phase(pair[1]) - phase(pair[0])).rem_euclid(2.0 * std::f64::consts::PI);
+            assert!((step - std::f64::consts::PI / 4.0).abs() < 1e-9);
+            assert!((magnitude(pair[0]) - 0.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn gain_and_bandwidth_are_honored() {
+        let sim = SimDevice::new(1, 0);
+        sim.set_bandwidth(Channel::Rx(0), 200e3).unwrap();
+        sim.set_gain(Channel::Rx(0), Some(SimDevice::GAIN_NAME), 20.0)
+            .unwrap();
+        sim.add_rx_signal(0, SimSignal::tone(50e3, 0.05)).unwrap();
+        sim.add_rx_signal(0, SimSignal::tone(300e3, 1.0)).unwrap();
+
+        let mut stream = open_rx(&sim, &[0]);
+        stream.issue_stream_cmd(num_samples(8, None)).unwrap();
+        let (samples, _) = recv(&mut stream, 8, 1.0);
+        for iq in samples {
+            assert!((magnitude(iq) - 0.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn timed_commands_and_timeouts() {
+        let sim = SimDevice::new(1, 0);
+        let mut stream = open_rx(&sim, &[0]);
+
+        // Idle streams time out, and device time passes while waiting.
+        let (samples, md) = recv(&mut stream, 10, 0.25);
+        assert!(samples.is_empty());
+        assert_eq!(md.error_code().unwrap(), RxErrorCode::Timeout);
+        assert_eq!(sim.time(0).unwrap(), TimeSpec::from_millis(250));
+
+        let start = TimeSpec::from_secs(1);
+        stream
+            .issue_stream_cmd(num_samples(1000, Some(start)))
+            .unwrap();
+        let (samples, md) = recv(&mut stream, 1000, 0.5);
+        assert!(samples.is_empty());
+        assert_eq!(md.error_code().unwrap(), RxErrorCode::Timeout);
+
+        let (samples, md) = recv(&mut stream, 1000, 0.5);
+        assert_eq!(samples.len(), 1000);
+        assert_eq!(md.time_spec(), Some(start));
+        assert_eq!(sim.time(0).unwrap(), start + TimeSpec::from_millis(1));
+
+        // Commands in the past are reported as late.
+        stream
+            .issue_stream_cmd(num_samples(10, Some(start)))
+            .unwrap();
+        let (samples, md) = recv(&mut stream, 10, 0.1);
+        assert!(samples.is_empty());
+        assert_eq!(md.error_code().unwrap(), RxErrorCode::LateCommand);
+    }
+
+    #[test]
+    fn continuous_stream_timestamps() {
+        let sim = SimDevice::new(2, 0);
+        sim.set_sample_rate(Channel::Rx(0), 2e6).unwrap();
+        sim.set_time(0, TimeSpec::from_secs(10)).unwrap();
+        let mut stream = open_rx(&sim, &[0, 1]);
+        stream
+            .issue_stream_cmd(StreamCommand::StartContinuous { time: None })
+            .unwrap();
+
+        let mut buff = ArrayBuffer::<[f64; 2]>::new(2, 500);
+        let mut md = RxMetadata::new();
+        let mut expected = TimeSpec::from_secs(10);
+        for i in 0..4 {
+            let n = stream
+                .recv(&mut buff, Duration::from_millis(10), &mut md)
+                .unwrap();
+            assert_eq!(n, 500);
+            assert_eq!(md.start_of_burst(), i == 0);
+            assert!(!md.end_of_burst());
+            let time = md.time_spec().unwrap();
+            assert!((time - expected).as_secs().abs() < 1e-12);
+            expected = time + TimeSpec::from_secs_f64(500.0 / 2e6);
+        }
+
+        stream
+            .issue_stream_cmd(StreamCommand::StopContinuous)
+            .unwrap();
+        assert_eq!(stream.recv(&mut buff, Duration::ZERO, &mut md).unwrap(), 0);
+        assert_eq!(md.error_code().unwrap(), RxErrorCode::Timeout);
+    }
+
+    #[test]
+    fn continuous_stream_overflows() {
+        let sim = SimDevice::new(1, 0);
+        let mut stream = open_rx(&sim, &[0]);
+        stream
+            .issue_stream_cmd(StreamCommand::StartContinuous { time: None })
+            .unwrap();
+        sim.set_time(0, TimeSpec::from_secs(60)).unwrap();
+
+        let (samples, md) = recv(&mut stream, 100, 0.1);
+        assert!(samples.is_empty());
+        assert_eq!(md.error_code().unwrap(), RxErrorCode::Overflow);
+        let (samples, md) = recv(&mut stream, 100, 0.1);
+        assert_eq!(samples.len(), 100);
+        assert!(md.start_of_burst());
+        assert_eq!(md.time_spec(), Some(TimeSpec::from_secs(60)));
+    }
+
+    #[test]
+    fn checked_receive_policies() {
+        let sim = SimDevice::new(1, 0);
+        let mut stream = open_rx(&sim, &[0]);
+        let mut buff = ArrayBuffer::new(1, 100);
+        let mut md = RxMetadata::new();
+        let timeout = Duration::from_millis(100);
+
+        let err = stream
+            .recv_checked(&mut buff, timeout, &mut md, &RxErrorPolicy::new())
+            .unwrap_err();
+        assert!(matches!(err, RxStreamError::Timeout(_)));
+
+        let start = TimeSpec::from_millis(350);
+        stream
+            .issue_stream_cmd(StreamCommand::StartContinuous { time: Some(start) })
+            .unwrap();
+        let policy = RxErrorPolicy::new().on_timeout(RxErrorAction::Retry(2));
+        let n = stream
+            .recv_checked(&mut buff, timeout, &mut md, &policy)
+            .unwrap();
+        assert_eq!(n, 100);
+        assert_eq!(md.time_spec(), Some(start));
+
+        sim.set_time(0, TimeSpec::from_secs(60)).unwrap();
+        let policy = RxErrorPolicy::new().on_overflow(RxErrorAction::Ignore);
+        let n = stream
+            .recv_checked(&mut buff, timeout, &mut md, &policy)
+            .unwrap();
+        assert_eq!(n, 0);
+        assert_eq!(md.error_code().unwrap(), RxErrorCode::Overflow);
+
+        sim.set_time(0, TimeSpec::from_secs(120)).unwrap();
+        let policy = RxErrorPolicy::new().on_overflow(RxErrorAction::Retry(1));
+        let n = stream
+            .recv_checked(&mut buff, timeout, &mut md, &policy)
+            .unwrap();
+        assert_eq!(n, 100);
+        assert_eq!(md.time_spec(), Some(TimeSpec::from_secs(120)));
+    }
+
+    #[test]
+    fn recordings_and_noise() {
+        let sim = SimDevice::new(2, 0).with_seed(7);
+        sim.add_rx_signal(0, SimSignal::recording(vec![[1.0, 0.0], [0.0, -1.0]], true))
+            .unwrap();
+        sim.add_rx_signal(1, SimSignal::noise(0.1)).unwrap();
+
+        let mut stream = open_rx(&sim, &[0, 1]);
+        stream.issue_stream_cmd(num_samples(4096, None)).unwrap();
+        let mut buff = ArrayBuffer::<[f64; 2]>::new(2, 4096);
+        let mut md = RxMetadata::new();
+        stream
+            .recv(&mut buff, Duration::from_secs(1), &mut md)
+            .unwrap();
+
+        assert_eq!(
+            &buff[0][..5],
+            &[[1.0, 0.0], [0.0, -1.0], [1.0, 0.0], [0.0, -1.0], [1.0, 0.0]]
+        );
+        let power = buff[1].iter().map(|&iq| magnitude(iq).powi(2)).sum::<f64>() / 4096.0;
+        assert!((power.sqrt() - 0.1).abs() < 0.01);
+
+        // The same seed produces the same noise.
+        let sim2 = SimDevice::new(2, 0).with_seed(7);
+        sim2.add_rx_signal(1, SimSignal::noise(0.1)).unwrap();
+        let mut stream = open_rx(&sim2, &[1]);
+        stream.issue_stream_cmd(num_samples(16, None)).unwrap();
+        let (samples, _) = recv(&mut stream, 16, 1.0);
+        assert_eq!(&samples[..], &buff[1][..16]);
+    }
+
+    #[test]
+    fn recording_from_file() {
+        let path = std::env::temp_dir().join(format!("uhd-sim-{}.fc32", std::process::id()));
+        let bytes: Vec<u8> = [0.5f32, -0.25, 1.0, 0.0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        std::fs::write(&path, &bytes).unwrap();
+        let signal = SimSignal::from_file(&path, false).unwrap();
+        std::fs::write(&path, &bytes[..6]).unwrap();
+        assert!(SimSignal::from_file(&path, false).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        let sim = SimDevice::new(1, 0);
+        sim.add_rx_signal(0, signal).unwrap();
+        let mut stream = open_rx(&sim, &[0]);
+        stream.issue_stream_cmd(num_samples(3, None)).unwrap();
+        let (samples, _) = recv(&mut stream, 3, 1.0);
+        assert_eq!(samples, vec![[0.5, -0.25], [1.0, 0.0], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn tx_is_captured() {
+        let sim = SimDevice::new(0, 2);
+        sim.set_center_freq(Channel::Tx(1), 2.4e9).unwrap();
+        sim.set_sample_rate(Channel::Tx(0), 1e3).unwrap();
+        let mut stream: SimTxStream<[i16; 2]> = sim.open_tx_stream(&[0, 1]).unwrap();
+
+        let mut buff = ArrayBuffer::<[i16; 2]>::new(2, 100);
+        buff[1][0] = [i16::MAX, -i16::MAX];
+        let start = TimeSpec::from_secs(2);
+        let md = TxMetadataBuilder::new()
+            .with_start_of_burst(true)
+            .with_time_spec(start)
+            .build();
+        assert_eq!(stream.send(&buff, &md, Duration::ZERO).unwrap(), 100);
+        let md = TxMetadataBuilder::new().with_end_of_burst(true).build();
+        assert_eq!(stream.send(&buff, &md, Duration::ZERO).unwrap(), 100);
+
+        let captures = sim.tx_captures();
+        assert_eq!(captures.len(), 4);
+        assert_eq!(captures[1].channel, 1);
+        assert_eq!(captures[1].time_spec, Some(start));
+        assert!(captures[1].start_of_burst && !captures[1].end_of_burst);
+        assert_eq!(captures[1].center_freq, 2.4e9);
+        assert!(captures[3].end_of_burst);
+        assert_eq!(captures[1].samples[0], [1.0, -1.0]);
+        assert_eq!(sim.tx_samples(1).len(), 200);
+        assert_eq!(sim.time(0).unwrap(), start + TimeSpec::from_millis(200));
+
+        sim.clear_tx_captures();
+        assert!(sim.tx_captures().is_empty());
+    }
+
+    #[test]
+    fn tx_async_events() {
+        let sim = SimDevice::new(0, 1);
+        let mut stream: SimTxStream<[f32; 2]> = sim.open_tx_stream(&[0]).unwrap();
+        let buff = ArrayBuffer::<[f32; 2]>::new(1, 1000);
+        let timeout = Duration::ZERO;
+        assert_eq!(stream.recv_async_msg(timeout).unwrap(), None);
+
+        let sob = TxMetadataBuilder::new()
+            .with_start_of_burst(true)
+            .with_time_spec(TimeSpec::from_secs(1))
+            .build();
+        stream.send(&buff, &sob, timeout).unwrap();
+        // The device falls idle before the burst continues.
+        sim.set_time(0, TimeSpec::from_secs(2)).unwrap();
+        stream.send(&buff, &TxMetadata::new(), timeout).unwrap();
+        let eob = TxMetadataBuilder::new().with_end_of_burst(true).build();
+        stream.send(&buff, &eob, timeout).unwrap();
+
+        let event = stream.recv_async_msg(timeout).unwrap().unwrap();
+        assert_eq!(event.event_code(), TxEventCode::Underflow);
+        assert_eq!(event.time_spec(), Some(TimeSpec::from_millis(1001)));
+        assert!(event.is_error());
+        let event = stream.recv_async_msg(timeout).unwrap().unwrap();
+        assert_eq!(event.event_code(), TxEventCode::BurstAck);
+        assert_eq!(event.channel(), 0);
+        assert_eq!(event.time_spec(), Some(TimeSpec::from_millis(2002)));
+        assert!(!event.is_error());
+
+        // Packets scheduled in the past are late.
+        stream.send(&buff, &sob, timeout).unwrap();
+        let event = stream.recv_async_msg(timeout).unwrap().unwrap();
+        assert_eq!(event.event_code(), TxEventCode::TimeError);
+        assert_eq!(stream.recv_async_msg(timeout).unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_configuration() {
+        let sim = SimDevice::new(1, 1);
+        assert!(matches!(
+            sim.center_freq(Channel::Rx(1)),
+            Err(UhdError::Index)
+        ));
+        assert!(matches!(
+            sim.gain(Channel::Tx(0), Some("LNA")),
+            Err(UhdError::Key)
+        ));
+        assert!(matches!(
+            sim.set_antenna(Channel::Tx(0), "RX2"),
+            Err(UhdError::Value)
+        ));
+        assert!(matches!(
+            sim.set_sample_rate(Channel::Rx(0), 0.0),
+            Err(UhdError::Value)
+        ));
+        assert!(matches!(sim.time(1), Err(UhdError::Index)));
+        assert!(matches!(
+            StreamDevice::<[f32; 2]>::open_rx_stream(&sim, &[0, 1]),
+            Err(UhdError::Index)
+        ));
+
+        sim.set_antenna(Channel::Rx(0), "TX/RX").unwrap();
+        assert_eq!(sim.antenna(Channel::Rx(0)).unwrap(), "TX/RX");
+    }
+
+    #[test]
+    fn integer_samples_saturate() {
+        assert_eq!(<[i16; 2]>::from_iq([2.0, -0.5]), [i16::MAX, -16384]);
+        assert_eq!(<[i8; 2]>::from_iq([-2.0, 1.0]), [i8::MIN, i8::MAX]);
+        assert_eq!([i16::MAX, 0].to_iq(), [1.0, 0.0]);
+    }
+}
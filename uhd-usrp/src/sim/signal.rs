@@ -0,0 +1,135 @@
+use std::{f64::consts::PI, fs, io, path::Path, sync::Arc};
+
+use crate::TimeSpec;
+
+/// A synthetic signal present at the input of a simulated RX channel.
+///
+/// Signals are specified at RF. The simulated device mixes them down using the
+/// channel's center frequency, drops tones outside of the channel's bandwidth,
+/// and scales everything by the channel's gain (in dB).
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimSignal {
+    /// A complex tone at an absolute RF frequency, in Hz.
+    Tone { freq: f64, amplitude: f64 },
+    /// Complex white gaussian noise with the given RMS amplitude.
+    Noise { amplitude: f64 },
+    /// Recorded baseband samples, played back from the start of the stream.
+    ///
+    /// Once the recording is exhausted it either restarts or falls silent.
+    Recording {
+        samples: Arc<[[f64; 2]]>,
+        looped: bool,
+    },
+}
+
+impl SimSignal {
+    pub fn tone(freq: f64, amplitude: f64) -> Self {
+        Self::Tone { freq, amplitude }
+    }
+
+    pub fn noise(amplitude: f64) -> Self {
+        Self::Noise { amplitude }
+    }
+
+    pub fn recording(samples: impl Into<Arc<[[f64; 2]]>>, looped: bool) -> Self {
+        Self::Recording {
+            samples: samples.into(),
+            looped,
+        }
+    }
+
+    /// Load a recording of interleaved little-endian `fc32` samples,
+    /// as written by the `rx_to_file` example.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or if its length is
+    /// not a multiple of the sample size.
+    pub fn from_file(path: impl AsRef<Path>, looped: bool) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if bytes.len() % 8 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "file length is not a multiple of the fc32 sample size",
+            ));
+        }
+        let samples: Vec<[f64; 2]> = bytes
+            .chunks_exact(8)
+            .map(|c| {
+                let i = f32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+                let q = f32::from_le_bytes([c[4], c[5], c[6], c[7]]);
+                [i as f64, q as f64]
+            })
+            .collect();
+        Ok(Self::recording(samples, looped))
+    }
+
+    /// Compute the baseband value of the signal.
+    ///
+    /// `time` is the device time of the sample and `index` is the number of
+    /// samples produced by the stream before it.
+    pub(crate) fn sample(
+        &self,
+        center_freq: f64,
+        bandwidth: f64,
+        time: TimeSpec,
+        index: u64,
+        rng: &mut Rng,
+    ) -> [f64; 2] {
+        match self {
+            SimSignal::Tone { freq, amplitude } => {
+                let offset = freq - center_freq;
+                if offset.abs() > bandwidth / 2.0 {
+                    return [0.0; 2];
+                }
+                // Split the phase computation to keep precision for large times.
+                let cycles = (offset * time.full_secs() as f64).fract() + offset * time.frac_secs();
+                let phase = 2.0 * PI * cycles;
+                [amplitude * phase.cos(), amplitude * phase.sin()]
+            }
+            SimSignal::Noise { amplitude } => {
+                let (i, q) = rng.gaussian_pair();
+                let std = amplitude / 2f64.sqrt();
+                [std * i, std * q]
+            }
+            SimSignal::Recording { samples, looped } => {
+                let len = samples.len() as u64;
+                match (len, looped) {
+                    (0, _) => [0.0; 2],
+                    (_, true) => samples[(index % len) as usize],
+                    (_, false) => samples.get(index as usize).copied().unwrap_or([0.0; 2]),
+                }
+            }
+        }
+    }
+}
+
+/// Small deterministic PRNG (xorshift64*) used for noise generation.
+#[derive(Clone, Debug)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Self((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform value in `(0, 1]`.
+    fn uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Two independent standard normal values (Box-Muller).
+    pub fn gaussian_pair(&mut self) -> (f64, f64) {
+        let r = (-2.0 * self.uniform().ln()).sqrt();
+        let theta = 2.0 * PI * self.uniform();
+        (r * theta.cos(), r * theta.sin())
+    }
+}
@@ -0,0 +1,338 @@
+use std::{collections::VecDeque, marker::PhantomData, time::Duration};
+
+use super::{signal::Rng, SimDevice};
+use crate::{
+    stream::StreamCommand,
+    types::{RxErrorCode, TxAsyncEvent, TxEventCode},
+    IqSample, Result, RxMetadata, RxStreamer, SampleBuffer, TimeSpec, TxMetadata, TxStreamer,
+    UhdError,
+};
+
+/// Samples sent to a single channel of a simulated TX stream in one call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxCapture {
+    pub channel: usize,
+    /// The time spec requested in the TX metadata.
+    pub time_spec: Option<TimeSpec>,
+    pub start_of_burst: bool,
+    pub end_of_burst: bool,
+    /// Channel configuration at the time the samples were sent.
+    pub center_freq: f64,
+    pub gain: f64,
+    pub sample_rate: f64,
+    /// The samples as sent, before any gain is applied.
+    pub samples: Vec<[f64; 2]>,
+}
+
+/// An RX stream of a [`SimDevice`].
+pub struct SimRxStream<T: IqSample> {
+    device: SimDevice,
+    channels: Vec<usize>,
+    rngs: Vec<Rng>,
+    mode: RxMode,
+    /// Time of the first sample of the current burst.
+    burst_start: TimeSpec,
+    /// Number of samples produced since `burst_start`.
+    burst_samples: u64,
+    /// Number of samples produced since the stream was opened.
+    produced: u64,
+    start_of_burst: bool,
+    late_command: bool,
+    _phantom: PhantomData<T>,
+}
+
+/// A TX stream of a [`SimDevice`].
+///
+/// The stream reports burst acknowledgements, late packets and underflows
+/// through [`TxStreamer::recv_async_msg`].
+pub struct SimTxStream<T: IqSample> {
+    device: SimDevice,
+    channels: Vec<usize>,
+    /// Time after the last sample of the current burst, if one is in progress.
+    burst_end: Option<TimeSpec>,
+    events: VecDeque<TxAsyncEvent>,
+    _phantom: PhantomData<T>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RxMode {
+    Idle,
+    Continuous,
+    NumSamples { remaining: usize, done: bool },
+}
+
+/// Validate a channel list, defaulting to channel `0` like UHD.
+fn stream_channels(channels: &[usize], available: usize) -> Result<Vec<usize>> {
+    let channels = match channels {
+        [] => vec![0],
+        _ => channels.to_vec(),
+    };
+    match channels.iter().all(|&c| c < available) {
+        true => Ok(channels),
+        false => Err(UhdError::Index),
+    }
+}
+
+impl<T: IqSample> SimRxStream<T> {
+    /// Number of samples per channel buffered by the simulated device
+    /// before a continuous stream overflows.
+    pub const BUFFER_SAMPLES: u64 = 1 << 20;
+
+    pub(crate) fn new(device: SimDevice, channels: &[usize]) -> Result<Self> {
+        let (channels, seed) = {
+            let state = device.lock();
+            (stream_channels(channels, state.rx.len())?, state.seed)
+        };
+        Ok(Self {
+            rngs: channels
+                .iter()
+                .map(|&c| Rng::new(seed.wrapping_add(c as u64)))
+                .collect(),
+            device,
+            channels,
+            mode: RxMode::Idle,
+            burst_start: TimeSpec::ZERO,
+            burst_samples: 0,
+            produced: 0,
+            start_of_burst: false,
+            late_command: false,
+            _phantom: PhantomData,
+        })
+    }
+
+    fn start_burst(&mut self, time: TimeSpec) {
+        self.burst_start = time;
+        self.burst_samples = 0;
+        self.start_of_burst = true;
+    }
+}
+
+impl<T: IqSample> RxStreamer<T> for SimRxStream<T> {
+    fn channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    fn max_samples_per_channel(&self) -> usize {
+        SimDevice::MAX_SAMPLES_PER_PACKET
+    }
+
+    fn issue_stream_cmd(&mut self, cmd: StreamCommand) -> Result<()> {
+        let now = self.device.lock().time;
+        let mode = match cmd {
+            StreamCommand::StopContinuous => {
+                self.mode = RxMode::Idle;
+                return Ok(());
+            }
+            StreamCommand::StartContinuous { .. } => RxMode::Continuous,
+            StreamCommand::NumSamples { samples, done, .. } => RxMode::NumSamples {
+                remaining: samples,
+                done,
+            },
+        };
+        match cmd.time() {
+            // Like a real device, late commands are dropped and reported on the next receive.
+            Some(time) if time < now => {
+                self.late_command = true;
+                self.mode = RxMode::Idle;
+            }
+            time => {
+                self.start_burst(time.unwrap_or(now));
+                self.mode = mode;
+            }
+        }
+        Ok(())
+    }
+
+    fn recv(
+        &mut self,
+        buff: &mut impl SampleBuffer<T>,
+        timeout: Duration,
+        metadata: &mut RxMetadata,
+    ) -> Result<usize> {
+        if buff.channels() != self.channels.len() {
+            return Err(UhdError::Index);
+        }
+        *metadata = RxMetadata::new();
+        if std::mem::take(&mut self.late_command) {
+            metadata.error_code = Some(RxErrorCode::LateCommand);
+            return Ok(0);
+        }
+
+        let device = self.device.clone();
+        let mut state = device.lock();
+        let timeout = TimeSpec::try_from(timeout).unwrap_or(TimeSpec::MAX);
+        let deadline = state.time.checked_add(timeout).unwrap_or(TimeSpec::MAX);
+        let rate = state.rx[self.channels[0]].sample_rate;
+        let next_time =
+            self.burst_start + TimeSpec::from_secs_f64(self.burst_samples as f64 / rate);
+        let remaining = match self.mode {
+            RxMode::Idle => 0,
+            RxMode::Continuous => usize::MAX,
+            RxMode::NumSamples { remaining, .. } => remaining,
+        };
+        if remaining == 0 || next_time > deadline {
+            // Nothing arrives before the timeout expires.
+            state.advance_time_to(deadline);
+            metadata.error_code = Some(RxErrorCode::Timeout);
+            return Ok(0);
+        }
+        let buffered = (state.time - next_time).as_secs() * rate;
+        if matches!(self.mode, RxMode::Continuous) && buffered > Self::BUFFER_SAMPLES as f64 {
+            // The application fell too far behind; samples are dropped.
+            self.start_burst(state.time);
+            metadata.error_code = Some(RxErrorCode::Overflow);
+            return Ok(0);
+        }
+
+        let n = buff.samples().min(remaining);
+        let ptrs = buff.as_mut_ptr();
+        for (i, &c) in self.channels.iter().enumerate() {
+            let ch = &state.rx[c];
+            let gain = 10f64.powf(ch.gain / 20.0);
+            let rng = &mut self.rngs[i];
+            // Safety: the buffer has `self.channels.len()` channels of at least `n` samples.
+            let out = unsafe { std::slice::from_raw_parts_mut(*ptrs.add(i), n) };
+            for (k, sample) in out.iter_mut().enumerate() {
+                let offset = self.burst_samples + k as u64;
+                let time = self.burst_start + TimeSpec::from_secs_f64(offset as f64 / rate);
+                let iq = ch.signals.iter().fold([0.0; 2], |acc, signal| {
+                    let v = signal.sample(
+                        ch.center_freq,
+                        ch.bandwidth,
+                        time,
+                        self.produced + k as u64,
+                        rng,
+                    );
+                    [acc[0] + v[0], acc[1] + v[1]]
+                });
+                *sample = T::from_iq([iq[0] * gain, iq[1] * gain]);
+            }
+        }
+
+        self.burst_samples += n as u64;
+        self.produced += n as u64;
+        state.advance_time_to(
+            self.burst_start + TimeSpec::from_secs_f64(self.burst_samples as f64 / rate),
+        );
+        metadata.time_spec = Some(next_time);
+        metadata.start_of_burst = std::mem::take(&mut self.start_of_burst);
+        if let RxMode::NumSamples { remaining, done } = &mut self.mode {
+            *remaining -= n;
+            if *remaining == 0 {
+                metadata.end_of_burst = *done;
+                self.mode = RxMode::Idle;
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<T: IqSample> SimTxStream<T> {
+    pub(crate) fn new(device: SimDevice, channels: &[usize]) -> Result<Self> {
+        let channels = stream_channels(channels, device.lock().tx.len())?;
+        Ok(Self {
+            device,
+            channels,
+            burst_end: None,
+            events: VecDeque::new(),
+            _phantom: PhantomData,
+        })
+    }
+
+    fn push_events(&mut self, event_code: TxEventCode, time: TimeSpec) {
+        let events = (0..self.channels.len()).map(|i| TxAsyncEvent::new(i, Some(time), event_code));
+        self.events.extend(events);
+    }
+}
+
+impl<T: IqSample> TxStreamer<T> for SimTxStream<T> {
+    fn channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    fn max_samples_per_channel(&self) -> usize {
+        SimDevice::MAX_SAMPLES_PER_PACKET
+    }
+
+    fn send(
+        &mut self,
+        buff: &impl SampleBuffer<T>,
+        metadata: &TxMetadata,
+        _timeout: Duration,
+    ) -> Result<usize> {
+        if buff.channels() != self.channels.len() {
+            return Err(UhdError::Index);
+        }
+        let n = buff.samples();
+        let device = self.device.clone();
+        let mut state = device.lock();
+        let ptrs = buff.as_ptr();
+        let captures: Vec<_> = self
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                let ch = &state.tx[c];
+                let samples = match n {
+                    0 => Vec::new(),
+                    // Safety: the buffer has `self.channels.len()` channels of `n` samples.
+                    _ => unsafe { std::slice::from_raw_parts(*ptrs.add(i), n) }
+                        .iter()
+                        .map(|s| s.to_iq())
+                        .collect(),
+                };
+                TxCapture {
+                    channel: c,
+                    time_spec: metadata.time_spec(),
+                    start_of_burst: metadata.start_of_burst(),
+                    end_of_burst: metadata.end_of_burst(),
+                    center_freq: ch.center_freq,
+                    gain: ch.gain,
+                    sample_rate: ch.sample_rate,
+                    samples,
+                }
+            })
+            .collect();
+
+        let rate = state.tx[self.channels[0]].sample_rate;
+        let now = state.time;
+        let start = match (metadata.time_spec(), self.burst_end) {
+            (Some(time), _) if time < now => {
+                self.push_events(TxEventCode::TimeError, now);
+                now
+            }
+            (Some(time), _) => time,
+            (None, Some(end)) if !metadata.start_of_burst() && end < now => {
+                // The burst continued later than its previous samples ran out.
+                self.push_events(TxEventCode::Underflow, end);
+                now
+            }
+            (None, Some(end)) if !metadata.start_of_burst() => end,
+            (None, _) => now,
+        };
+        let end = start + TimeSpec::from_secs_f64(n as f64 / rate);
+        state.advance_time_to(end);
+        state.captures.extend(captures);
+        self.burst_end = match metadata.end_of_burst() {
+            true => {
+                self.push_events(TxEventCode::BurstAck, end);
+                None
+            }
+            false => Some(end),
+        };
+        Ok(n)
+    }
+
+    fn recv_async_msg(&mut self, timeout: Duration) -> Result<Option<TxAsyncEvent>> {
+        let event = self.events.pop_front();
+        if event.is_none() {
+            let mut state = self.device.lock();
+            let deadline = state
+                .time
+                .checked_add(TimeSpec::try_from(timeout).unwrap_or(TimeSpec::MAX))
+                .unwrap_or(TimeSpec::MAX);
+            state.advance_time_to(deadline);
+        }
+        Ok(event)
+    }
+}
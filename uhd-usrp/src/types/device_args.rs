@@ -1,9 +1,25 @@
-use crate::Result;
+use std::{fmt::Display, str::FromStr};
 
-use crate::Usrp;
+use crate::{Result, UhdError, Usrp};
 
 /// Arguments for specifying a USRP available to the system.
 ///
+/// Device arguments are an ordered set of `key=value` pairs. They are used
+/// both as a hint when opening or searching for a device, and to describe
+/// the devices found by [`Usrp::find`].
+///
+/// The string representation follows the same rules as UHD's `device_addr_t`:
+/// - pairs are separated by `,` and keys are separated from values by `=`
+/// - whitespace surrounding keys and values is ignored
+/// - empty pairs are ignored, and a key without a `=` has an empty value
+///
+/// UHD has no escape sequences, so keys and values can never contain `,`, `=`
+/// or null characters. Such input is rejected with a [`DeviceArgsError`]:
+/// immediately by [`DeviceArgs::set`] and when parsing, and by [`Usrp::open`]
+/// and [`Usrp::find`] for arguments built with the infallible builder methods.
+/// Invalid arguments are formatted as-is by `Display`, so the output may not
+/// parse back; use [`DeviceArgs::validate`] to check them beforehand.
+///
 /// # Examples
 ///
 /// ```no_run
@@ -14,14 +30,34 @@ use crate::Usrp;
 ///     .open()
 ///     .unwrap();
 /// ```
-#[derive(Clone, Debug, Default)]
+///
+/// Arbitrary keys can be set and parsed as well:
+///
+/// ```
+/// use uhd_usrp::DeviceArgs;
+///
+/// let mut args: DeviceArgs = "type=x300, addr=192.168.10.2".parse().unwrap();
+/// args.set("master_clock_rate", "200e6").unwrap();
+/// assert_eq!(args.get("master_clock_rate"), Some("200e6"));
+/// assert_eq!(args.to_string(), "type=x300,addr=192.168.10.2,master_clock_rate=200e6");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct DeviceArgs {
-    addr: Option<String>,
-    serial: Option<String>,
-    resource: Option<String>,
-    name: Option<String>,
-    type_: Option<String>,
-    vid_pid: Option<(String, String)>,
+    /// Key/value pairs, kept in insertion order.
+    args: Vec<(String, String)>,
+}
+
+/// An error that occurred while parsing [`DeviceArgs`] or opening a device with them.
+#[derive(thiserror::Error, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceArgsError {
+    #[error("empty key in pair {0:?}")]
+    EmptyKey(String),
+    #[error("invalid pair {0:?}")]
+    InvalidPair(String),
+    #[error("invalid character {1:?} in {0:?}")]
+    InvalidCharacter(String, char),
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
 }
 
 impl DeviceArgs {
@@ -31,67 +67,316 @@ impl DeviceArgs {
         }
     }
 
-    pub fn addr(mut self, ip: &str) -> Self {
-        self.addr = Some(ip.to_owned());
-        self
+    /// Get the value associated with a key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.args
+            .iter()
+            .find(|(k, _)| k == key.trim())
+            .map(|(_, v)| v.as_str())
     }
 
-    fn iter(&self) -> impl Iterator<Item = String> + '_ {
-        let mut args = vec![];
-        if let Some(addr) = &self.addr {
-            args.push(format!("args={addr}"));
-        }
-        if let Some(serial) = &self.serial {
-            args.push(format!("serial={serial}"));
-        }
-        if let Some(resource) = &self.resource {
-            args.push(format!("resource={resource}"));
-        }
-        if let Some(name) = &self.name {
-            args.push(format!("name={name}"));
-        }
-        if let Some(type_) = &self.type_ {
-            args.push(format!("type={type_}"));
-        }
-        if let Some((vid, pid)) = &self.vid_pid {
-            args.push(format!("vid={vid}"));
-            args.push(format!("pid={pid}"));
+    /// Set the value associated with a key, replacing any existing value.
+    ///
+    /// This can be used for keys without a dedicated setter,
+    /// such as `master_clock_rate`, `recv_frame_size` or `mgmt_addr`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is empty, or if the key or value
+    /// contains a `,`, `=` or null character.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<&mut Self, DeviceArgsError> {
+        let (key, value) = (key.trim(), value.trim());
+        validate_pair(key, value)?;
+        self.insert(key, value);
+        Ok(self)
+    }
+
+    fn insert(&mut self, key: &str, value: &str) {
+        match self.args.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => self.args.push((key.to_owned(), value.to_owned())),
         }
-        args.into_iter()
     }
 
-    pub fn name(mut self, name: &str) -> Self {
-        self.name = Some(name.to_owned());
+    /// Check that every key is non-empty, and that no key or value contains
+    /// a `,`, `=` or null character.
+    pub fn validate(&self) -> Result<(), DeviceArgsError> {
+        self.iter().try_for_each(|(k, v)| validate_pair(k, v))
+    }
+
+    /// Remove a key, returning its value if it was present.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self.args.iter().position(|(k, _)| k == key.trim())?;
+        Some(self.args.remove(index).1)
+    }
+
+    /// Returns an iterator over the `(key, value)` pairs in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.args.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the number of pairs.
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Returns `true` if no pairs are set.
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Builder-style variant of [`DeviceArgs::set`].
+    ///
+    /// The key and value are not validated until the arguments are used,
+    /// see [`DeviceArgs::validate`].
+    pub fn arg(mut self, key: &str, value: &str) -> Self {
+        self.insert(key.trim(), value.trim());
         self
     }
 
-    pub fn open(self) -> Result<Usrp> {
+    pub fn addr(self, ip: &str) -> Self {
+        self.arg("addr", ip)
+    }
+
+    pub fn name(self, name: &str) -> Self {
+        self.arg("name", name)
+    }
+
+    pub fn open(self) -> Result<Usrp, DeviceArgsError> {
         Usrp::open(self)
     }
 
-    pub fn resource(mut self, resource: &str) -> Self {
-        self.resource = Some(resource.to_owned());
-        self
+    pub fn product(self, product: &str) -> Self {
+        self.arg("product", product)
     }
 
-    pub fn serial(mut self, serial: &str) -> Self {
-        self.serial = Some(serial.to_owned());
-        self
+    pub fn resource(self, resource: &str) -> Self {
+        self.arg("resource", resource)
     }
 
-    pub fn type_(mut self, type_: &str) -> Self {
-        self.type_ = Some(type_.to_owned());
-        self
+    pub fn serial(self, serial: &str) -> Self {
+        self.arg("serial", serial)
     }
 
-    pub fn vid_pid(mut self, vid: &str, pid: &str) -> Self {
-        self.vid_pid = Some((vid.to_owned(), pid.to_owned()));
-        self
+    pub fn type_(self, type_: &str) -> Self {
+        self.arg("type", type_)
+    }
+
+    pub fn vid_pid(self, vid: &str, pid: &str) -> Self {
+        self.arg("vid", vid).arg("pid", pid)
     }
 }
 
-impl ToString for DeviceArgs {
-    fn to_string(&self) -> String {
-        self.iter().collect::<Vec<String>>().join(",")
+/// Accessors for well-known keys.
+impl DeviceArgs {
+    /// The network address of the device, if any.
+    pub fn get_addr(&self) -> Option<&str> {
+        self.get("addr")
+    }
+
+    /// The user-assigned name of the device, if any.
+    pub fn get_name(&self) -> Option<&str> {
+        self.get("name")
+    }
+
+    /// The product name of the device, e.g. `"B210"` or `"X310"`.
+    pub fn get_product(&self) -> Option<&str> {
+        self.get("product")
+    }
+
+    /// The serial number of the device.
+    pub fn get_serial(&self) -> Option<&str> {
+        self.get("serial")
+    }
+
+    /// The device type, e.g. `"b200"` or `"x300"`.
+    pub fn get_type(&self) -> Option<&str> {
+        self.get("type")
+    }
+}
+
+fn validate_pair(key: &str, value: &str) -> Result<(), DeviceArgsError> {
+    if key.is_empty() {
+        return Err(DeviceArgsError::EmptyKey(format!("{key}={value}")));
+    }
+    validate(key)?;
+    validate(value)
+}
+
+fn validate(s: &str) -> Result<(), DeviceArgsError> {
+    match s.chars().find(|c| matches!(c, ',' | '=' | '\0')) {
+        Some(c) => Err(DeviceArgsError::InvalidCharacter(s.to_owned(), c)),
+        None => Ok(()),
+    }
+}
+
+impl FromStr for DeviceArgs {
+    type Err = DeviceArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('\0') {
+            return Err(DeviceArgsError::InvalidCharacter(s.to_owned(), '\0'));
+        }
+        let mut args = Self::new();
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let mut parts = pair.split('=');
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next().unwrap_or_default();
+            if parts.next().is_some() {
+                return Err(DeviceArgsError::InvalidPair(pair.to_owned()));
+            }
+            if key.trim().is_empty() {
+                return Err(DeviceArgsError::EmptyKey(pair.to_owned()));
+            }
+            args.set(key, value)?;
+        }
+        Ok(args)
+    }
+}
+
+impl TryFrom<&str> for DeviceArgs {
+    type Error = DeviceArgsError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl TryFrom<String> for DeviceArgs {
+    type Error = DeviceArgsError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for DeviceArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (k, v)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{k}={v}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_find_output() {
+        let args: DeviceArgs = "type=x300,addr=192.168.10.2,fpga=HG,name=,serial=31A4B5C,product=X310"
+            .parse()
+            .unwrap();
+        assert_eq!(args.get_type(), Some("x300"));
+        assert_eq!(args.get_addr(), Some("192.168.10.2"));
+        assert_eq!(args.get_name(), Some(""));
+        assert_eq!(args.get_serial(), Some("31A4B5C"));
+        assert_eq!(args.get_product(), Some("X310"));
+        assert_eq!(args.get("fpga"), Some("HG"));
+    }
+
+    #[test]
+    fn parse_missing_keys() {
+        let args: DeviceArgs = "type=b200, serial=3123ABC".parse().unwrap();
+        assert_eq!(args.get_type(), Some("b200"));
+        assert_eq!(args.get_serial(), Some("3123ABC"));
+        assert_eq!(args.get_addr(), None);
+        assert_eq!(args.get_product(), None);
+        assert_eq!("".parse::<DeviceArgs>().unwrap(), DeviceArgs::new());
+    }
+
+    #[test]
+    fn parse_whitespace_and_empty_pairs() {
+        let args: DeviceArgs = " addr = 192.168.10.2 ,, ,second_addr=192.168.40.2,no_value"
+            .parse()
+            .unwrap();
+        assert_eq!(args.len(), 3);
+        assert_eq!(args.get_addr(), Some("192.168.10.2"));
+        assert_eq!(args.get("second_addr"), Some("192.168.40.2"));
+        assert_eq!(args.get("no_value"), Some(""));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "addr=a=b".parse::<DeviceArgs>(),
+            Err(DeviceArgsError::InvalidPair("addr=a=b".to_owned()))
+        );
+        assert_eq!(
+            "type=b200,=x".parse::<DeviceArgs>(),
+            Err(DeviceArgsError::EmptyKey("=x".to_owned()))
+        );
+        assert!(matches!(
+            "serial=12\x003".parse::<DeviceArgs>(),
+            Err(DeviceArgsError::InvalidCharacter(_, '\0'))
+        ));
+        assert!(DeviceArgs::try_from("fpga=HG").is_ok());
+    }
+
+    #[test]
+    fn set_and_get() {
+        let mut args = DeviceArgs::new();
+        args.set("master_clock_rate", "200e6")
+            .unwrap()
+            .set("num_recv_frames", "512")
+            .unwrap();
+        assert_eq!(args.get("master_clock_rate"), Some("200e6"));
+        assert_eq!(args.get("num_recv_frames"), Some("512"));
+        assert_eq!(args.get("recv_frame_size"), None);
+
+        args.set("master_clock_rate", "184.32e6").unwrap();
+        assert_eq!(args.get("master_clock_rate"), Some("184.32e6"));
+        assert_eq!(args.remove("master_clock_rate"), Some("184.32e6".to_owned()));
+        assert_eq!(args.get("master_clock_rate"), None);
+
+        assert!(matches!(
+            args.set("mgmt_addr", "a,b"),
+            Err(DeviceArgsError::InvalidCharacter(_, ','))
+        ));
+        assert!(matches!(
+            args.set("", "x"),
+            Err(DeviceArgsError::EmptyKey(_))
+        ));
+    }
+
+    #[test]
+    fn builder_defers_validation() {
+        let args = DeviceArgs::new().type_("b200");
+        assert_eq!(args.validate(), Ok(()));
+        assert!(matches!(
+            args.clone().serial("12\x003").validate(),
+            Err(DeviceArgsError::InvalidCharacter(_, '\0'))
+        ));
+        assert!(matches!(
+            args.clone().addr("a,b").validate(),
+            Err(DeviceArgsError::InvalidCharacter(_, ','))
+        ));
+        assert!(matches!(
+            args.arg(" ", "x").validate(),
+            Err(DeviceArgsError::EmptyKey(_))
+        ));
+    }
+
+    #[test]
+    fn round_trip() {
+        let args = DeviceArgs::new()
+            .type_("b200")
+            .serial("3123ABC")
+            .vid_pid("2500", "0020");
+        assert_eq!(args.to_string(), "type=b200,serial=3123ABC,vid=2500,pid=0020");
+        assert_eq!(args.to_string().parse::<DeviceArgs>().unwrap(), args);
+
+        let s = "type=x300,addr=192.168.10.2,fpga=HG,name=radio1,serial=31A4B5C,product=X310";
+        assert_eq!(s.parse::<DeviceArgs>().unwrap().to_string(), s);
+    }
+
+    #[test]
+    fn setters_replace_existing_values() {
+        let args = DeviceArgs::new().addr("192.168.10.2").addr("192.168.10.3");
+        assert_eq!(args.to_string(), "addr=192.168.10.3");
     }
 }
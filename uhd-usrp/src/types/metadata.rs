@@ -4,9 +4,21 @@ use num_enum::TryFromPrimitive;
 
 use crate::{ffi::OwnedHandle, Result, TimeSpec, UhdError};
 
-#[derive(Debug)]
+/// RX metadata structure for describing received IF data.
+///
+/// Includes time specification, fragmentation flags, burst flags, and error codes.
+/// The metadata is filled in by the stream reader when using
+/// [`RxStreamReader::with_metadata_output`](crate::stream::RxStreamReader::with_metadata_output).
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct RxMetadata {
-    handle: OwnedHandle<uhd_usrp_sys::uhd_rx_metadata_t>,
+    pub(crate) time_spec: Option<TimeSpec>,
+    /// `None` if UHD reported an error code unknown to this crate.
+    pub(crate) error_code: Option<RxErrorCode>,
+    pub(crate) start_of_burst: bool,
+    pub(crate) end_of_burst: bool,
+    pub(crate) more_fragments: bool,
+    pub(crate) fragment_offset: usize,
+    pub(crate) out_of_sequence: bool,
 }
 
 #[derive(Debug)]
@@ -14,7 +26,7 @@ pub struct TxMetadataBuilder {
     inner: TxMetadata,
 }
 
-#[derive(Clone, Copy, Debug, num_enum::TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, num_enum::TryFromPrimitive)]
 #[repr(u32)]
 pub enum RxErrorCode {
     None = uhd_usrp_sys::uhd_rx_metadata_error_code_t::UHD_RX_METADATA_ERROR_CODE_NONE,
@@ -68,115 +80,98 @@ pub struct TxMetadata {
 impl RxMetadata {
     pub fn new() -> Self {
         Self {
-            // `uhd_rx_metadata_make` is a simple allocation so it is safe in
-            // the overwhelming majority of cases, and handling OOM makes this
-            // type unergonomic.
-            handle: OwnedHandle::new(
-                uhd_usrp_sys::uhd_rx_metadata_make,
-                uhd_usrp_sys::uhd_rx_metadata_free,
-            )
-            .expect("uhd_rx_metadata_make failed"),
+            time_spec: None,
+            error_code: Some(RxErrorCode::None),
+            start_of_burst: false,
+            end_of_burst: false,
+            more_fragments: false,
+            fragment_offset: 0,
+            out_of_sequence: false,
         }
     }
 
-    pub(crate) fn handle(&self) -> &OwnedHandle<uhd_usrp_sys::uhd_rx_metadata_t> {
-        &self.handle
-    }
-
-    #[allow(dead_code)]
-    pub(crate) fn handle_mut(&mut self) -> &mut OwnedHandle<uhd_usrp_sys::uhd_rx_metadata_t> {
-        &mut self.handle
-    }
-
-    pub fn end_of_burst(&self) -> bool {
-        let mut result = false;
+    /// Copy the metadata out of a UHD metadata handle.
+    pub(crate) fn from_handle(handle: &OwnedHandle<uhd_usrp_sys::uhd_rx_metadata_t>) -> Self {
+        let mut md = Self::new();
+        let mut has_time_spec = false;
+        let mut full_secs = 0;
+        let mut frac_secs = 0.0;
+        let mut error_code =
+            uhd_usrp_sys::uhd_rx_metadata_error_code_t::UHD_RX_METADATA_ERROR_CODE_NONE;
         unsafe {
+            uhd_usrp_sys::uhd_rx_metadata_has_time_spec(
+                handle.as_mut_ptr(),
+                addr_of_mut!(has_time_spec),
+            );
+            uhd_usrp_sys::uhd_rx_metadata_time_spec(
+                handle.as_mut_ptr(),
+                addr_of_mut!(full_secs),
+                addr_of_mut!(frac_secs),
+            );
+            uhd_usrp_sys::uhd_rx_metadata_error_code(handle.as_mut_ptr(), addr_of_mut!(error_code));
+            uhd_usrp_sys::uhd_rx_metadata_start_of_burst(
+                handle.as_mut_ptr(),
+                addr_of_mut!(md.start_of_burst),
+            );
             uhd_usrp_sys::uhd_rx_metadata_end_of_burst(
-                self.handle.as_mut_ptr(),
-                addr_of_mut!(result),
+                handle.as_mut_ptr(),
+                addr_of_mut!(md.end_of_burst),
+            );
+            uhd_usrp_sys::uhd_rx_metadata_more_fragments(
+                handle.as_mut_ptr(),
+                addr_of_mut!(md.more_fragments),
+            );
+            uhd_usrp_sys::uhd_rx_metadata_fragment_offset(
+                handle.as_mut_ptr(),
+                addr_of_mut!(md.fragment_offset),
+            );
+            uhd_usrp_sys::uhd_rx_metadata_out_of_sequence(
+                handle.as_mut_ptr(),
+                addr_of_mut!(md.out_of_sequence),
             );
         }
-        result
+        // `TimeSpec::from_parts_unchecked` is an option, but it safer to check
+        // since we don't have any solid guarantees about the validity of the
+        // returned timespec.
+        md.time_spec = has_time_spec
+            .then(|| TimeSpec::try_from_parts(full_secs, frac_secs))
+            .flatten();
+        md.error_code = RxErrorCode::try_from_primitive(error_code).ok();
+        md
+    }
+
+    pub fn end_of_burst(&self) -> bool {
+        self.end_of_burst
     }
 
     pub fn error_code(&self) -> Result<RxErrorCode> {
-        let mut result =
-            uhd_usrp_sys::uhd_rx_metadata_error_code_t::UHD_RX_METADATA_ERROR_CODE_NONE;
-        unsafe {
-            uhd_usrp_sys::uhd_rx_metadata_error_code(self.handle.as_mut_ptr(), addr_of_mut!(result))
-        };
-        Ok(RxErrorCode::try_from_primitive(result).or(Err(UhdError::Unknown))?)
+        self.error_code.ok_or(UhdError::Unknown)
     }
 
     pub fn fragment_offset(&self) -> usize {
-        let mut result = 0;
-        unsafe {
-            uhd_usrp_sys::uhd_rx_metadata_fragment_offset(
-                self.handle.as_mut_ptr(),
-                addr_of_mut!(result),
-            );
-        }
-        result
+        self.fragment_offset
     }
 
     pub fn more_fragments(&self) -> bool {
-        let mut result = false;
-        unsafe {
-            uhd_usrp_sys::uhd_rx_metadata_more_fragments(
-                self.handle.as_mut_ptr(),
-                addr_of_mut!(result),
-            );
-        }
-        result
+        self.more_fragments
     }
 
     pub fn out_of_sequence(&self) -> bool {
-        let mut result = false;
-        unsafe {
-            uhd_usrp_sys::uhd_rx_metadata_out_of_sequence(
-                self.handle.as_mut_ptr(),
-                addr_of_mut!(result),
-            );
-        }
-        result
+        self.out_of_sequence
     }
 
     pub fn start_of_burst(&self) -> bool {
-        let mut result = false;
-        unsafe {
-            uhd_usrp_sys::uhd_rx_metadata_start_of_burst(
-                self.handle.as_mut_ptr(),
-                addr_of_mut!(result),
-            );
-        }
-        result
+        self.start_of_burst
     }
 
     pub fn time_spec(&self) -> Option<TimeSpec> {
-        let mut has_time_spec = false;
-        unsafe {
-            uhd_usrp_sys::uhd_rx_metadata_has_time_spec(
-                self.handle.as_mut_ptr(),
-                addr_of_mut!(has_time_spec),
-            );
-        }
-        if !has_time_spec {
-            return None;
-        }
+        self.time_spec
+    }
+}
 
-        let mut full_secs = 0;
-        let mut frac_secs = 0.0;
-        unsafe {
-            uhd_usrp_sys::uhd_rx_metadata_time_spec(
-                self.handle.as_mut_ptr(),
-                addr_of_mut!(full_secs),
-                addr_of_mut!(frac_secs),
-            );
-        }
-        // `TimeSpec::from_parts_unchecked` is an option, but it safer to check
-        // since we don't have any solid guarantees about the validity of the
-        // returned timespec.
-        TimeSpec::try_from_parts(full_secs, frac_secs)
+impl Default for RxMetadata {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -248,3 +243,125 @@ impl TxMetadata {
         unsafe { OwnedHandle::from_ptr(handle, uhd_usrp_sys::uhd_tx_metadata_free) }
     }
 }
+
+/// The kind of event reported in a TX asynchronous message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, num_enum::TryFromPrimitive)]
+#[repr(u32)]
+pub enum TxEventCode {
+    /// A burst was successfully transmitted.
+    BurstAck =
+        uhd_usrp_sys::uhd_async_metadata_event_code_t::UHD_ASYNC_METADATA_EVENT_CODE_BURST_ACK,
+    /// An internal send buffer has emptied.
+    Underflow =
+        uhd_usrp_sys::uhd_async_metadata_event_code_t::UHD_ASYNC_METADATA_EVENT_CODE_UNDERFLOW,
+    /// Packet loss between host and device.
+    SeqError =
+        uhd_usrp_sys::uhd_async_metadata_event_code_t::UHD_ASYNC_METADATA_EVENT_CODE_SEQ_ERROR,
+    /// Packet had time that was late.
+    TimeError =
+        uhd_usrp_sys::uhd_async_metadata_event_code_t::UHD_ASYNC_METADATA_EVENT_CODE_TIME_ERROR,
+    /// Underflow occurred inside a packet.
+    UnderflowInPacket = uhd_usrp_sys::uhd_async_metadata_event_code_t::UHD_ASYNC_METADATA_EVENT_CODE_UNDERFLOW_IN_PACKET,
+    /// Packet loss within a burst.
+    SeqErrorInBurst = uhd_usrp_sys::uhd_async_metadata_event_code_t::UHD_ASYNC_METADATA_EVENT_CODE_SEQ_ERROR_IN_BURST,
+    /// Some kind of custom user payload.
+    UserPayload =
+        uhd_usrp_sys::uhd_async_metadata_event_code_t::UHD_ASYNC_METADATA_EVENT_CODE_USER_PAYLOAD,
+}
+
+/// An asynchronous message reported by a TX stream.
+///
+/// See [`TxStream::recv_async_msg`](crate::TxStream::recv_async_msg).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TxAsyncEvent {
+    pub(crate) channel: usize,
+    pub(crate) time_spec: Option<TimeSpec>,
+    pub(crate) event_code: TxEventCode,
+    pub(crate) user_payload: [u32; 4],
+}
+
+impl TxAsyncEvent {
+    pub(crate) fn new(
+        channel: usize,
+        time_spec: Option<TimeSpec>,
+        event_code: TxEventCode,
+    ) -> Self {
+        Self {
+            channel,
+            time_spec,
+            event_code,
+            user_payload: [0; 4],
+        }
+    }
+
+    /// Copy the message out of a UHD async metadata handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Unknown`] if the event code is not recognized.
+    pub(crate) fn from_handle(
+        handle: &OwnedHandle<uhd_usrp_sys::uhd_async_metadata_t>,
+    ) -> Result<Self> {
+        let mut channel = 0;
+        let mut has_time_spec = false;
+        let mut full_secs = 0;
+        let mut frac_secs = 0.0;
+        let mut event_code = 0;
+        let mut user_payload = [0u32; 4];
+        unsafe {
+            uhd_usrp_sys::uhd_async_metadata_channel(handle.as_mut_ptr(), addr_of_mut!(channel));
+            uhd_usrp_sys::uhd_async_metadata_has_time_spec(
+                handle.as_mut_ptr(),
+                addr_of_mut!(has_time_spec),
+            );
+            uhd_usrp_sys::uhd_async_metadata_time_spec(
+                handle.as_mut_ptr(),
+                addr_of_mut!(full_secs),
+                addr_of_mut!(frac_secs),
+            );
+            uhd_usrp_sys::uhd_async_metadata_event_code(
+                handle.as_mut_ptr(),
+                addr_of_mut!(event_code),
+            );
+            uhd_usrp_sys::uhd_async_metadata_user_payload(
+                handle.as_mut_ptr(),
+                user_payload.as_mut_ptr(),
+            );
+        }
+        Ok(Self {
+            channel,
+            time_spec: has_time_spec
+                .then(|| TimeSpec::try_from_parts(full_secs, frac_secs))
+                .flatten(),
+            event_code: TxEventCode::try_from_primitive(event_code).or(Err(UhdError::Unknown))?,
+            user_payload,
+        })
+    }
+
+    /// The stream channel the message applies to.
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// The device time at which the event occurred, if known.
+    pub fn time_spec(&self) -> Option<TimeSpec> {
+        self.time_spec
+    }
+
+    pub fn event_code(&self) -> TxEventCode {
+        self.event_code
+    }
+
+    /// Custom data, only meaningful for [`TxEventCode::UserPayload`] events.
+    pub fn user_payload(&self) -> [u32; 4] {
+        self.user_payload
+    }
+
+    /// Returns `true` if the event indicates a problem with transmission.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self.event_code,
+            TxEventCode::BurstAck | TxEventCode::UserPayload
+        )
+    }
+}
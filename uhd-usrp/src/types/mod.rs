@@ -5,9 +5,11 @@ mod sensor;
 mod time;
 mod tune;
 
-pub use device_args::DeviceArgs;
-pub use metadata::{RxErrorCode, RxMetadata, TxMetadata, TxMetadataBuilder};
+pub use device_args::{DeviceArgs, DeviceArgsError};
+pub use metadata::{
+    RxErrorCode, RxMetadata, TxAsyncEvent, TxEventCode, TxMetadata, TxMetadataBuilder,
+};
 pub use range::{MetaRange, Range};
-pub use sensor::SensorValue;
+pub use sensor::{SensorValue, SensorValueValue, Temperature};
 pub use time::TimeSpec;
-pub use tune::{TuneRequest, TuneResult};
+pub use tune::{TuneError, TuneRequest, TuneResult};
@@ -59,7 +59,7 @@ impl MetaRange {
                 ranges.push(Range {
                     start: temp.start,
                     stop: temp.stop,
-                    step: temp.stop,
+                    step: temp.step,
                 });
             }
         };
@@ -5,14 +5,24 @@ use crate::{
     try_uhd, Result, UhdError,
 };
 
+/// Initial buffer size for strings read from a sensor value.
+const INITIAL_STRING_LEN: usize = 64;
+/// Largest string read from a sensor value.
+const MAX_STRING_LEN: usize = 64 * 1024;
+
 /// A sensor value stores a sensor reading as a string with unit and data type.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SensorValue {
+    #[cfg_attr(feature = "serde", serde(rename = "value"))]
     kind: SensorValueValue,
     unit: String,
     name: String,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum SensorValueValue {
     Boolean(bool),
     Real(f64),
@@ -20,28 +30,79 @@ pub enum SensorValueValue {
     String(String),
 }
 
+/// A temperature reading.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Temperature {
+    celsius: f64,
+}
+
+impl Temperature {
+    pub fn from_celsius(celsius: f64) -> Self {
+        Self { celsius }
+    }
+
+    pub fn from_kelvin(kelvin: f64) -> Self {
+        Self::from_celsius(kelvin - 273.15)
+    }
+
+    pub fn from_fahrenheit(fahrenheit: f64) -> Self {
+        Self::from_celsius((fahrenheit - 32.0) / 1.8)
+    }
+
+    pub fn celsius(&self) -> f64 {
+        self.celsius
+    }
+
+    pub fn kelvin(&self) -> f64 {
+        self.celsius + 273.15
+    }
+
+    pub fn fahrenheit(&self) -> f64 {
+        self.celsius * 1.8 + 32.0
+    }
+}
+
+impl std::fmt::Display for Temperature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} °C", self.celsius)
+    }
+}
+
 impl SensorValue {
+    /// Create a sensor value.
+    ///
+    /// This is mostly useful for testing code which consumes sensor values.
+    pub fn new(name: &str, value: SensorValueValue, unit: &str) -> Self {
+        Self {
+            kind: value,
+            unit: unit.to_string(),
+            name: name.to_string(),
+        }
+    }
+
     /// Try to create a new sensor value from a C handle.
     pub(crate) fn from_handle(
         handle: &OwnedHandle<uhd_usrp_sys::uhd_sensor_value_t>,
     ) -> Result<Self> {
-        let mut buf = FfiString::with_capacity(32);
-        unsafe {
-            uhd_usrp_sys::uhd_sensor_value_unit(
-                handle.as_mut_ptr(),
-                buf.as_mut_ptr(),
-                buf.max_chars(),
-            );
-        }
-        let unit = buf.to_string()?;
-        unsafe {
-            uhd_usrp_sys::uhd_sensor_value_name(
-                handle.as_mut_ptr(),
-                buf.as_mut_ptr(),
-                buf.max_chars(),
-            );
-        }
-        let name = buf.to_string()?;
+        let unit = FfiString::read_growing(INITIAL_STRING_LEN, MAX_STRING_LEN, |buf| {
+            try_uhd!(unsafe {
+                uhd_usrp_sys::uhd_sensor_value_unit(
+                    handle.as_mut_ptr(),
+                    buf.as_mut_ptr(),
+                    buf.max_chars(),
+                )
+            })
+        })?;
+        let name = FfiString::read_growing(INITIAL_STRING_LEN, MAX_STRING_LEN, |buf| {
+            try_uhd!(unsafe {
+                uhd_usrp_sys::uhd_sensor_value_name(
+                    handle.as_mut_ptr(),
+                    buf.as_mut_ptr(),
+                    buf.max_chars(),
+                )
+            })
+        })?;
         Ok(Self {
             kind: SensorValueValue::from_handle(handle)?,
             unit,
@@ -91,6 +152,53 @@ impl SensorValue {
             _ => None,
         }
     }
+
+    /// Returns `Some` if the value is a string, `None` otherwise.
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.kind {
+            SensorValueValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns `Some` if the value is a number with a temperature unit
+    /// (Celsius, Kelvin or Fahrenheit), `None` otherwise.
+    pub fn as_temperature(&self) -> Option<Temperature> {
+        let value = self.as_f64()?;
+        match self.unit.trim().trim_start_matches(['°', ' ']) {
+            "C" | "c" | "degC" | "deg C" | "Celsius" => Some(Temperature::from_celsius(value)),
+            "K" | "Kelvin" => Some(Temperature::from_kelvin(value)),
+            "F" | "f" | "degF" | "deg F" | "Fahrenheit" => {
+                Some(Temperature::from_fahrenheit(value))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `Some` if the value is a number in decibels (`dB` or `dBm`),
+    /// `None` otherwise.
+    pub fn as_decibels(&self) -> Option<f64> {
+        match self.unit.trim() {
+            "dB" | "dBm" | "dBFS" => self.as_f64(),
+            _ => None,
+        }
+    }
+}
+
+/// Conversions used by the typed sensor accessors, which fail with
+/// [`UhdError::Type`] if the sensor doesn't have the expected type.
+impl SensorValue {
+    pub(crate) fn try_bool(&self) -> Result<bool> {
+        self.as_bool().ok_or(UhdError::Type)
+    }
+
+    pub(crate) fn try_temperature(&self) -> Result<Temperature> {
+        self.as_temperature().ok_or(UhdError::Type)
+    }
+
+    pub(crate) fn try_decibels(&self) -> Result<f64> {
+        self.as_decibels().ok_or(UhdError::Type)
+    }
 }
 
 impl std::fmt::Display for SensorValue {
@@ -142,13 +250,79 @@ impl SensorValueValue {
                 Ok(Self::Integer(val))
             }
             uhd_usrp_sys::uhd_sensor_value_data_type_t::UHD_SENSOR_VALUE_STRING => {
-                let mut val = FfiString::with_capacity(32);
-                try_uhd!(unsafe {
-                    uhd_usrp_sys::uhd_sensor_value_value(handle.as_mut_ptr(), val.as_mut_ptr(), val.max_chars())
+                // Values such as NMEA sentences can be arbitrarily long.
+                let val = FfiString::read_growing(INITIAL_STRING_LEN, MAX_STRING_LEN, |buf| {
+                    try_uhd!(unsafe {
+                        uhd_usrp_sys::uhd_sensor_value_value(
+                            handle.as_mut_ptr(),
+                            buf.as_mut_ptr(),
+                            buf.max_chars(),
+                        )
+                    })
                 })?;
-                Ok(Self::String(val.to_string()?))
+                Ok(Self::String(val))
             }
             _ => Err(UhdError::NotImplemented),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn temperatures() {
+        let value = SensorValue::new("temp", SensorValueValue::Real(45.5), "C");
+        assert_eq!(value.as_temperature().unwrap().celsius(), 45.5);
+        let value = SensorValue::new("temp", SensorValueValue::Integer(300), "K");
+        assert!((value.as_temperature().unwrap().celsius() - 26.85).abs() < 1e-9);
+        let value = SensorValue::new("temp", SensorValueValue::Real(212.0), "°F");
+        assert!((value.as_temperature().unwrap().celsius() - 100.0).abs() < 1e-9);
+        let value = SensorValue::new("rssi", SensorValueValue::Real(-40.0), "dB");
+        assert_eq!(value.as_temperature(), None);
+        assert_eq!(value.as_decibels(), Some(-40.0));
+        let value = SensorValue::new("temp", SensorValueValue::String("hot".into()), "C");
+        assert_eq!(value.as_temperature(), None);
+    }
+
+    #[test]
+    fn accessors() {
+        let value = SensorValue::new("lo_locked", SensorValueValue::Boolean(true), "locked");
+        assert_eq!(value.as_bool(), Some(true));
+        assert_eq!(value.as_f64(), None);
+        assert_eq!(value.to_string(), "lo_locked: locked");
+        let value = SensorValue::new("gps_gpgga", SensorValueValue::String("$GPGGA".into()), "");
+        assert_eq!(value.as_str(), Some("$GPGGA"));
+        assert_eq!(value.clone(), value);
+    }
+
+    #[test]
+    fn long_strings() {
+        let long = "x".repeat(1000);
+        let read = FfiString::read_growing(8, 4096, |buf| {
+            let len = buf.max_chars().min(long.len());
+            let mut bytes = long.as_bytes()[..len].to_vec();
+            bytes.resize(buf.max_chars(), 0);
+            unsafe { std::ptr::copy(bytes.as_ptr(), buf.as_mut_ptr().cast(), bytes.len()) };
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(read, long);
+
+        let too_long = FfiString::read_growing(8, 512, |buf| {
+            unsafe { std::ptr::write_bytes(buf.as_mut_ptr().cast::<u8>(), b'x', buf.max_chars()) };
+            Ok(())
+        });
+        assert!(too_long.is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let value = SensorValue::new("temp", SensorValueValue::Real(45.5), "C");
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"value":{"real":45.5},"unit":"C","name":"temp"}"#);
+        assert_eq!(serde_json::from_str::<SensorValue>(&json).unwrap(), value);
+    }
+}
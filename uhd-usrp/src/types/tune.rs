@@ -7,7 +7,7 @@ use std::{
 
 use uhd_usrp_sys::uhd_tune_request_policy_t::*;
 
-use crate::ffi::FfiString;
+use crate::{ffi::FfiString, UhdError};
 
 /// A tune request instructs the implementation how to tune the RF chain.
 ///
@@ -26,6 +26,25 @@ pub struct TuneResult {
     inner: uhd_usrp_sys::uhd_tune_result_t,
 }
 
+/// An error which occurred during a tolerance-checked tune.
+#[derive(thiserror::Error, Clone, Debug)]
+pub enum TuneError {
+    /// The channel tuned further from the requested frequency than allowed.
+    ///
+    /// The channel is left tuned to the achieved frequency.
+    #[error(
+        "tuned to {achieved} Hz, more than {tolerance} Hz away from the requested {requested} Hz"
+    )]
+    OutOfTolerance {
+        requested: f64,
+        achieved: f64,
+        tolerance: f64,
+        result: TuneResult,
+    },
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
 impl TuneRequest {
     /// Make a new tune request for a particular center frequency.
     ///
@@ -58,6 +77,11 @@ impl TuneRequest {
             .dsp_freq_auto()
     }
 
+    /// The center frequency requested in Hz.
+    pub fn target_freq(&self) -> f64 {
+        self.inner.target_freq
+    }
+
     /// Retrieve the inner [`uhd_tune_request_t`](uhd_usrp_sys::uhd_tune_request_t) struct.
     pub(crate) fn inner(&self) -> &uhd_usrp_sys::uhd_tune_request_t {
         &self.inner
@@ -124,6 +148,25 @@ impl TuneResult {
         &self.inner
     }
 
+    /// Check that the achieved frequency is within `tolerance` Hz of the requested one.
+    pub(crate) fn check(
+        self,
+        requested: f64,
+        achieved: f64,
+        tolerance: f64,
+    ) -> Result<Self, TuneError> {
+        if (achieved - requested).abs() <= tolerance {
+            Ok(self)
+        } else {
+            Err(TuneError::OutOfTolerance {
+                requested,
+                achieved,
+                tolerance,
+                result: self,
+            })
+        }
+    }
+
     /// Retrieve the inner [`uhd_tune_result_t`](uhd_usrp_sys::uhd_tune_result_t) struct.
     pub(crate) fn inner_mut(&mut self) -> &mut uhd_usrp_sys::uhd_tune_result_t {
         &mut self.inner
@@ -135,7 +178,7 @@ impl TuneResult {
     /// requested resolution wasn't possible or something went wrong in the DSP.
     /// In most cases, it should equal the `target_dsp_freq` above.
     pub fn actual_dsp_freq(&self) -> f64 {
-        self.inner.actual_rf_freq
+        self.inner.actual_dsp_freq
     }
 
     /// The frequency to which the RF LO actually tuned
@@ -203,3 +246,35 @@ impl ToString for TuneResult {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accessors() {
+        let mut result = TuneResult::default();
+        result.inner_mut().actual_rf_freq = 1e9;
+        result.inner_mut().actual_dsp_freq = -250e3;
+        result.inner_mut().clipped_rf_freq = 1.1e9;
+        assert_eq!(result.actual_rf_freq(), 1e9);
+        assert_eq!(result.actual_dsp_freq(), -250e3);
+        assert_eq!(result.clipped_rf_freq(), 1.1e9);
+        assert_eq!(TuneRequest::new(915e6).target_freq(), 915e6);
+    }
+
+    #[test]
+    fn check_tolerance() {
+        let result = TuneResult::default();
+        assert!(result.clone().check(100e6, 100e6 + 0.5, 1.0).is_ok());
+        assert!(result.clone().check(100e6, 100e6 - 1.0, 1.0).is_ok());
+        match result.check(100e6, 100e6 + 2.0, 1.0) {
+            Err(TuneError::OutOfTolerance {
+                requested,
+                achieved,
+                ..
+            }) => assert_eq!((requested, achieved), (100e6, 100e6 + 2.0)),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
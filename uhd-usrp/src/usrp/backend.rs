@@ -0,0 +1,296 @@
+//! Device abstraction shared by real and simulated USRPs.
+//!
+//! Application code written against [`Device`] and [`StreamDevice`] can run
+//! on a [`Usrp`] as well as on a [`SimDevice`](crate::sim::SimDevice), which
+//! makes it possible to test it without a radio attached.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use num_complex::Complex32;
+//! use uhd_usrp::{
+//!     stream::StreamCommand, ArrayBuffer, Channel, Device, Result, RxMetadata, RxStreamer,
+//!     StreamDevice, Usrp,
+//! };
+//!
+//! fn capture<D: StreamDevice<Complex32>>(device: &D, samples: usize) -> Result<Vec<Complex32>> {
+//!     device.set_center_freq(Channel::Rx(0), 915e6)?;
+//!     device.set_sample_rate(Channel::Rx(0), 1e6)?;
+//!
+//!     let mut stream = device.open_rx_stream(&[0])?;
+//!     stream.issue_stream_cmd(StreamCommand::NumSamples {
+//!         samples,
+//!         done: true,
+//!         time: None,
+//!     })?;
+//!
+//!     let mut buff = ArrayBuffer::new(1, samples);
+//!     let mut md = RxMetadata::new();
+//!     let received = stream.recv(&mut buff, Duration::from_secs(1), &mut md)?;
+//!     Ok(buff[0][..received].to_vec())
+//! }
+//!
+//! let usrp = Usrp::open_any().unwrap();
+//! let samples = capture(&usrp, 1000).unwrap();
+//! ```
+
+use std::time::Duration;
+
+use crate::{
+    stream::{RxErrorPolicy, RxStreamError, StreamCommand},
+    types::TxAsyncEvent,
+    Channel, Result, RxMetadata, RxStream, Sample, SampleBuffer, TimeSpec, TxMetadata, TxStream,
+    UhdError, Usrp,
+};
+
+/// Channel configuration and timekeeping common to all devices.
+///
+/// Channels are addressed the same way as in [`Usrp::channel`].
+pub trait Device {
+    /// Get the number of motherboards.
+    fn n_mboards(&self) -> Result<usize>;
+    /// Get the total number of RX channels.
+    fn rx_channels(&self) -> Result<usize>;
+    /// Get the total number of TX channels.
+    fn tx_channels(&self) -> Result<usize>;
+
+    fn antenna(&self, channel: Channel) -> Result<String>;
+    fn set_antenna(&self, channel: Channel, name: &str) -> Result<()>;
+    fn bandwidth(&self, channel: Channel) -> Result<f64>;
+    fn set_bandwidth(&self, channel: Channel, bw: f64) -> Result<()>;
+    fn center_freq(&self, channel: Channel) -> Result<f64>;
+    fn set_center_freq(&self, channel: Channel, freq: f64) -> Result<()>;
+    /// Get the gain of the named gain element, or the overall gain if `None`.
+    fn gain(&self, channel: Channel, name: Option<&str>) -> Result<f64>;
+    /// Set the gain of the named gain element, or the overall gain if `None`.
+    fn set_gain(&self, channel: Channel, name: Option<&str>, gain: f64) -> Result<()>;
+    fn sample_rate(&self, channel: Channel) -> Result<f64>;
+    fn set_sample_rate(&self, channel: Channel, rate: f64) -> Result<()>;
+
+    /// Get the current device time of a motherboard.
+    fn time(&self, mboard: usize) -> Result<TimeSpec>;
+    /// Set the device time of a motherboard immediately.
+    fn set_time(&self, mboard: usize, time: TimeSpec) -> Result<()>;
+}
+
+/// A device that can stream samples of type `T`.
+pub trait StreamDevice<T: Sample>: Device {
+    type RxStream: RxStreamer<T>;
+    type TxStream: TxStreamer<T>;
+
+    /// Open an RX stream on the given channels.
+    fn open_rx_stream(&self, channels: &[usize]) -> Result<Self::RxStream>;
+    /// Open a TX stream on the given channels.
+    fn open_tx_stream(&self, channels: &[usize]) -> Result<Self::TxStream>;
+}
+
+/// A stream receiving samples from a device.
+pub trait RxStreamer<T: Sample> {
+    /// The number of channels received by the stream.
+    fn channels(&self) -> usize;
+    /// The maximum number of samples per channel in a single packet.
+    fn max_samples_per_channel(&self) -> usize;
+    /// Issue a stream command to the device.
+    fn issue_stream_cmd(&mut self, cmd: StreamCommand) -> Result<()>;
+    /// Receive samples into the buffer, returning the number of samples
+    /// received per channel.
+    ///
+    /// Errors reported by the device (e.g. timeouts or overflows) are written
+    /// to `metadata` rather than returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Index`] if the buffer's channel count does not
+    /// match the stream's.
+    fn recv(
+        &mut self,
+        buff: &mut impl SampleBuffer<T>,
+        timeout: Duration,
+        metadata: &mut RxMetadata,
+    ) -> Result<usize>;
+    /// Receive samples, surfacing error conditions reported by the device as errors.
+    ///
+    /// See [`RxStreamReader::recv_checked`](crate::stream::RxStreamReader::recv_checked).
+    fn recv_checked(
+        &mut self,
+        buff: &mut impl SampleBuffer<T>,
+        timeout: Duration,
+        metadata: &mut RxMetadata,
+        policy: &RxErrorPolicy,
+    ) -> Result<usize, RxStreamError> {
+        let mut retries = 0;
+        loop {
+            let received = self.recv(buff, timeout, metadata)?;
+            if !policy.check(metadata, retries)? {
+                return Ok(received);
+            }
+            retries += 1;
+        }
+    }
+}
+
+/// A stream sending samples to a device.
+pub trait TxStreamer<T: Sample> {
+    /// The number of channels sent by the stream.
+    fn channels(&self) -> usize;
+    /// The maximum number of samples per channel in a single packet.
+    fn max_samples_per_channel(&self) -> usize;
+    /// Send samples from the buffer, returning the number of samples
+    /// sent per channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Index`] if the buffer's channel count does not
+    /// match the stream's.
+    fn send(
+        &mut self,
+        buff: &impl SampleBuffer<T>,
+        metadata: &TxMetadata,
+        timeout: Duration,
+    ) -> Result<usize>;
+    /// Wait up to `timeout` for an asynchronous message from the device.
+    fn recv_async_msg(&mut self, timeout: Duration) -> Result<Option<TxAsyncEvent>>;
+}
+
+impl Device for Usrp {
+    fn n_mboards(&self) -> Result<usize> {
+        Usrp::n_mboards(self)
+    }
+
+    fn rx_channels(&self) -> Result<usize> {
+        Usrp::rx_channels(self)
+    }
+
+    fn tx_channels(&self) -> Result<usize> {
+        Usrp::tx_channels(self)
+    }
+
+    fn antenna(&self, channel: Channel) -> Result<String> {
+        self.channel(channel)?.antenna()
+    }
+
+    fn set_antenna(&self, channel: Channel, name: &str) -> Result<()> {
+        self.channel(channel)?.set_antenna(name)?;
+        Ok(())
+    }
+
+    fn bandwidth(&self, channel: Channel) -> Result<f64> {
+        self.channel(channel)?.bandwidth()
+    }
+
+    fn set_bandwidth(&self, channel: Channel, bw: f64) -> Result<()> {
+        self.channel(channel)?.set_bandwidth(bw)?;
+        Ok(())
+    }
+
+    fn center_freq(&self, channel: Channel) -> Result<f64> {
+        self.channel(channel)?.center_freq()
+    }
+
+    fn set_center_freq(&self, channel: Channel, freq: f64) -> Result<()> {
+        self.channel(channel)?.set_center_freq(freq)?;
+        Ok(())
+    }
+
+    fn gain(&self, channel: Channel, name: Option<&str>) -> Result<f64> {
+        self.channel(channel)?.gain(name)
+    }
+
+    fn set_gain(&self, channel: Channel, name: Option<&str>, gain: f64) -> Result<()> {
+        self.channel(channel)?.set_gain(name, gain)?;
+        Ok(())
+    }
+
+    fn sample_rate(&self, channel: Channel) -> Result<f64> {
+        self.channel(channel)?.sample_rate()
+    }
+
+    fn set_sample_rate(&self, channel: Channel, rate: f64) -> Result<()> {
+        self.channel(channel)?.set_sample_rate(rate)?;
+        Ok(())
+    }
+
+    fn time(&self, mboard: usize) -> Result<TimeSpec> {
+        self.mboard(mboard).time()
+    }
+
+    fn set_time(&self, mboard: usize, time: TimeSpec) -> Result<()> {
+        self.mboard(mboard).set_time(time)
+    }
+}
+
+impl<T: Sample> StreamDevice<T> for Usrp {
+    type RxStream = RxStream<T>;
+    type TxStream = TxStream<T>;
+
+    fn open_rx_stream(&self, channels: &[usize]) -> Result<RxStream<T>> {
+        self.rx_stream::<T>().with_channels(channels).open()
+    }
+
+    fn open_tx_stream(&self, channels: &[usize]) -> Result<TxStream<T>> {
+        self.tx_stream::<T>().with_channels(channels).open()
+    }
+}
+
+impl<T: Sample> RxStreamer<T> for RxStream<T> {
+    fn channels(&self) -> usize {
+        RxStream::channels(self)
+    }
+
+    fn max_samples_per_channel(&self) -> usize {
+        RxStream::max_samples_per_channel(self)
+    }
+
+    fn issue_stream_cmd(&mut self, cmd: StreamCommand) -> Result<()> {
+        RxStream::issue_stream_cmd(self, cmd)
+    }
+
+    fn recv(
+        &mut self,
+        buff: &mut impl SampleBuffer<T>,
+        timeout: Duration,
+        metadata: &mut RxMetadata,
+    ) -> Result<usize> {
+        if buff.channels() != RxStream::channels(self) {
+            return Err(UhdError::Index);
+        }
+        let samples = buff.samples();
+        unsafe {
+            self.reader()
+                .with_timeout(timeout)
+                .with_metadata_output(metadata)
+                .recv_raw(buff.as_mut_ptr(), samples)
+        }
+    }
+}
+
+impl<T: Sample> TxStreamer<T> for TxStream<T> {
+    fn channels(&self) -> usize {
+        TxStream::channels(self)
+    }
+
+    fn max_samples_per_channel(&self) -> usize {
+        TxStream::max_samples_per_channel(self)
+    }
+
+    fn send(
+        &mut self,
+        buff: &impl SampleBuffer<T>,
+        metadata: &TxMetadata,
+        timeout: Duration,
+    ) -> Result<usize> {
+        if buff.channels() != TxStream::channels(self) {
+            return Err(UhdError::Index);
+        }
+        let mut metadata = *metadata;
+        self.writer()
+            .with_timeout(timeout)
+            .with_metadata(&mut metadata)
+            .send(buff)
+    }
+
+    fn recv_async_msg(&mut self, timeout: Duration) -> Result<Option<TxAsyncEvent>> {
+        TxStream::recv_async_msg(self, timeout)
+    }
+}
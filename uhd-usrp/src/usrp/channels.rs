@@ -3,7 +3,7 @@ use std::{ffi::CString, fmt::Display, ptr::addr_of_mut};
 use crate::{
     ffi::{FfiString, FfiStringVec, OwnedHandle},
     try_uhd,
-    types::{MetaRange, SensorValue, TuneRequest, TuneResult},
+    types::{MetaRange, SensorValue, Temperature, TuneError, TuneRequest, TuneResult},
     HardwareInfo, Result, UhdError, Usrp,
 };
 
@@ -23,8 +23,8 @@ impl Display for Channel {
 }
 
 pub struct ChannelConfig<'u> {
-    usrp: &'u Usrp,
-    channel: Channel,
+    pub(crate) usrp: &'u Usrp,
+    pub(crate) channel: Channel,
 }
 
 impl Channel {
@@ -149,6 +149,11 @@ impl<'u> ChannelConfig<'u> {
 
 // --------------------------------------------------------------------------
 /// Gain configuration
+///
+/// Gain profiles (`set_rx_gain_profile` and friends) are only part of UHD's
+/// C++ API and aren't exposed through its C API, so they can't be used here.
+/// [`GainDistribution`](crate::GainDistribution) can be used to control how
+/// gain is split across the gain elements instead.
 impl<'u> ChannelConfig<'u> {
     /// Enable or disable the RX AGC module.
     ///
@@ -180,6 +185,10 @@ impl<'u> ChannelConfig<'u> {
 
     /// Set the gain for the channel and given name.
     pub fn gain(&self, name: Option<&str>) -> Result<f64> {
+        #[cfg(feature = "num")]
+        if let (None, Some(gain)) = (name, self.calibrated_gain()) {
+            return Ok(gain);
+        }
         let name = CString::new(name.unwrap_or("")).unwrap();
         let mut result = std::mem::MaybeUninit::uninit();
         let f = match self.channel {
@@ -197,6 +206,26 @@ impl<'u> ChannelConfig<'u> {
         .and_then(|_| Ok(unsafe { result.assume_init() }))
     }
 
+    /// Get the names of the gain elements of the channel.
+    ///
+    /// These names can be passed to [`Self::gain`], [`Self::set_gain`] and
+    /// [`Self::gain_ranges`] to control individual gain stages.
+    pub fn gain_names(&self) -> Result<Vec<String>> {
+        let mut vec = FfiStringVec::new();
+        let f = match self.channel {
+            Channel::Rx(_) => uhd_usrp_sys::uhd_usrp_get_rx_gain_names,
+            Channel::Tx(_) => uhd_usrp_sys::uhd_usrp_get_tx_gain_names,
+        };
+        try_uhd!(unsafe {
+            f(
+                self.usrp.handle().as_mut_ptr(),
+                self.channel.index(),
+                vec.as_mut_ptr(),
+            )
+        })?;
+        Ok(vec.to_vec())
+    }
+
     /// Get the RX gain range for the specified gain element.
     ///
     /// If `None` is provided, the overall gain range is returned.
@@ -272,6 +301,7 @@ impl<'u> ChannelConfig<'u> {
     /// The name of the gain element to set can be provided.
     /// If `None`, it is distributed across all gain elements.
     pub fn set_gain(&self, name: Option<&str>, gain: f64) -> Result<&Self> {
+        let gain = self.device_gain(name, gain);
         let name = CString::new(name.unwrap_or("")).unwrap();
         let f = match self.channel {
             Channel::Rx(_) => uhd_usrp_sys::uhd_usrp_set_rx_gain,
@@ -287,6 +317,15 @@ impl<'u> ChannelConfig<'u> {
         })?;
         Ok(self)
     }
+
+    /// The gain to set on the device for a requested gain, see [`Self::set_calibration`].
+    fn device_gain(&self, name: Option<&str>, gain: f64) -> f64 {
+        match name {
+            #[cfg(feature = "num")]
+            None => self.calibrate_gain(gain),
+            _ => gain,
+        }
+    }
 }
 
 // --------------------------------------------------------------------------
@@ -419,7 +458,15 @@ impl<'u> ChannelConfig<'u> {
     /// Set the tuning parameters for the channel.
     ///
     /// This function allows setting more advanced parameters.
+    /// Use [`Self::tune_with_result`] to find out the frequencies actually tuned to.
     pub fn tune(&self, req: &TuneRequest) -> Result<&Self> {
+        self.tune_with_result(req)?;
+        Ok(self)
+    }
+
+    /// Set the tuning parameters for the channel and return the RF and DSP
+    /// frequencies actually tuned to.
+    pub fn tune_with_result(&self, req: &TuneRequest) -> Result<TuneResult> {
         let req = req.inner();
         let mut result = TuneResult::default();
         let f = match self.channel {
@@ -434,7 +481,47 @@ impl<'u> ChannelConfig<'u> {
                 result.inner_mut(),
             )
         })?;
-        Ok(self)
+        // The device may not have tuned yet if a command time is set, so use the result.
+        #[cfg(feature = "num")]
+        self.update_calibration(self.tuned_freq(&result))?;
+        Ok(result)
+    }
+
+    /// The center frequency resulting from a tune.
+    fn tuned_freq(&self, result: &TuneResult) -> f64 {
+        // UHD spins the DSP in opposite directions for RX and TX.
+        match self.channel {
+            Channel::Rx(_) => result.actual_rf_freq() - result.actual_dsp_freq(),
+            Channel::Tx(_) => result.actual_rf_freq() + result.actual_dsp_freq(),
+        }
+    }
+
+    /// Tune the channel, failing if the achieved center frequency is more
+    /// than `tolerance` Hz away from the requested one.
+    ///
+    /// The achieved frequency combines the RF and DSP frequencies of the
+    /// [`TuneResult`], so this also works while a command time is set. If it is
+    /// out of tolerance the channel is left tuned to it, and the error holds
+    /// the result.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use uhd_usrp::{types::TuneRequest, Channel, Usrp};
+    ///
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let ch = usrp.channel(Channel::Rx(0)).unwrap();
+    /// let result = ch.tune_within(&TuneRequest::new(915e6), 1.0).unwrap();
+    /// println!("LO at {} Hz", result.actual_rf_freq());
+    /// ```
+    pub fn tune_within(
+        &self,
+        req: &TuneRequest,
+        tolerance: f64,
+    ) -> std::result::Result<TuneResult, TuneError> {
+        let result = self.tune_with_result(req)?;
+        let achieved = self.tuned_freq(&result);
+        result.check(req.target_freq(), achieved, tolerance)
     }
 }
 
@@ -492,7 +579,7 @@ impl<'u> ChannelConfig<'u> {
         let mut vec = FfiStringVec::new();
         let f = match self.channel {
             Channel::Rx(_) => uhd_usrp_sys::uhd_usrp_get_rx_lo_names,
-            Channel::Tx(_) => uhd_usrp_sys::uhd_usrp_get_rx_lo_names,
+            Channel::Tx(_) => uhd_usrp_sys::uhd_usrp_get_tx_lo_names,
         };
         try_uhd!(unsafe {
             f(
@@ -598,8 +685,12 @@ impl<'u> ChannelConfig<'u> {
     /// given name is invalid.
     pub fn set_lo_export_enabled(&self, name: Option<&str>, en: bool) -> Result<&Self> {
         let name = CString::new(name.unwrap_or("")).unwrap();
+        let f = match self.channel {
+            Channel::Rx(_) => uhd_usrp_sys::uhd_usrp_set_rx_lo_export_enabled,
+            Channel::Tx(_) => uhd_usrp_sys::uhd_usrp_set_tx_lo_export_enabled,
+        };
         try_uhd!(unsafe {
-            uhd_usrp_sys::uhd_usrp_set_rx_lo_export_enabled(
+            f(
                 self.usrp.handle().as_mut_ptr(),
                 en,
                 name.as_ptr(),
@@ -608,6 +699,32 @@ impl<'u> ChannelConfig<'u> {
         })?;
         Ok(self)
     }
+
+    /// Set the LO source for the channel.
+    ///
+    /// Calling [`Self::lo_sources`] will return a list of valid LO sources.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel does not support selecting the LO source,
+    /// or if the source or LO name is invalid.
+    pub fn set_lo_source(&self, name: Option<&str>, source: &str) -> Result<&Self> {
+        let name = CString::new(name.unwrap_or("")).unwrap();
+        let source = CString::new(source).unwrap();
+        let f = match self.channel {
+            Channel::Rx(_) => uhd_usrp_sys::uhd_usrp_set_rx_lo_source,
+            Channel::Tx(_) => uhd_usrp_sys::uhd_usrp_set_tx_lo_source,
+        };
+        try_uhd!(unsafe {
+            f(
+                self.usrp.handle().as_mut_ptr(),
+                source.as_ptr(),
+                name.as_ptr(),
+                self.channel.index(),
+            )
+        })?;
+        Ok(self)
+    }
 }
 
 // --------------------------------------------------------------------------
@@ -707,8 +824,8 @@ impl<'u> ChannelConfig<'u> {
             uhd_usrp_sys::uhd_sensor_value_free,
         )?;
         let f = match self.channel {
-            Channel::Rx(_) => uhd_usrp_sys::uhd_usrp_get_tx_sensor,
-            Channel::Tx(_) => uhd_usrp_sys::uhd_usrp_get_rx_sensor,
+            Channel::Rx(_) => uhd_usrp_sys::uhd_usrp_get_rx_sensor,
+            Channel::Tx(_) => uhd_usrp_sys::uhd_usrp_get_tx_sensor,
         };
         try_uhd!(unsafe {
             f(
@@ -729,6 +846,47 @@ impl<'u> ChannelConfig<'u> {
             .collect::<Result<Vec<_>>>()?;
         Ok(vals.into_iter())
     }
+
+    /// Returns `true` if the frontend's local oscillator is locked (`lo_locked` sensor).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frontend has no such sensor, or
+    /// [`UhdError::Type`](crate::UhdError::Type) if it isn't a boolean.
+    pub fn lo_locked(&self) -> Result<bool> {
+        self.sensor_value("lo_locked")?.try_bool()
+    }
+
+    /// Get the received signal strength in dB (`rssi` sensor).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frontend has no such sensor, or
+    /// [`UhdError::Type`](crate::UhdError::Type) if it isn't a number in decibels.
+    pub fn rssi(&self) -> Result<f64> {
+        self.sensor_value("rssi")?.try_decibels()
+    }
+
+    /// Get the temperature of the frontend (`temp` sensor).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frontend has no such sensor, or
+    /// [`UhdError::Type`](crate::UhdError::Type) if it isn't a temperature.
+    pub fn temperature(&self) -> Result<Temperature> {
+        self.sensor_value("temp")?.try_temperature()
+    }
+
+    /// Get the temperature of the AD9361 transceiver on devices using one
+    /// (`ad9361_temperature` sensor).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frontend has no such sensor, or
+    /// [`UhdError::Type`](crate::UhdError::Type) if it isn't a temperature.
+    pub fn ad9361_temperature(&self) -> Result<Temperature> {
+        self.sensor_value("ad9361_temperature")?.try_temperature()
+    }
 }
 
 // --------------------------------------------------------------------------
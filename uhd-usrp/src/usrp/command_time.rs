@@ -0,0 +1,100 @@
+use crate::{Result, TimeSpec, UhdError, Usrp};
+
+use super::{
+    channels::{Channel, ChannelConfig},
+    mboard::GpioBank,
+};
+
+/// An error which occurred while setting the command time.
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum CommandTimeError {
+    #[error("motherboard {mboard} does not support timed commands")]
+    Unsupported { mboard: usize },
+    #[error("channel {channel:?} is not on motherboard {mboard}")]
+    WrongMboard { channel: Channel, mboard: usize },
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+/// Queues control commands on a motherboard to be executed at a given time.
+///
+/// While the guard is alive, setters called through it (or otherwise on the
+/// same motherboard) such as tuning, gain changes and GPIO writes are executed
+/// once the device time reaches the command time. This allows retuning several
+/// channels at exactly the same instant, for example.
+///
+/// The command time is cleared when the guard is dropped.
+/// Use [`CommandTimeGuard::clear`] to handle errors from clearing it.
+///
+/// Created by [`Usrp::at_time`].
+pub struct CommandTimeGuard<'a> {
+    usrp: &'a Usrp,
+    mboard: usize,
+    time: TimeSpec,
+    cleared: bool,
+}
+
+impl<'a> CommandTimeGuard<'a> {
+    pub(crate) fn new(
+        usrp: &'a Usrp,
+        time: TimeSpec,
+        mboard: usize,
+    ) -> Result<Self, CommandTimeError> {
+        match usrp.mboard(mboard).set_command_time(time) {
+            Ok(()) => Ok(Self {
+                usrp,
+                mboard,
+                time,
+                cleared: false,
+            }),
+            Err(UhdError::NotImplemented) => Err(CommandTimeError::Unsupported { mboard }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The time at which commands are executed.
+    pub fn time(&self) -> TimeSpec {
+        self.time
+    }
+
+    /// The motherboard the command time applies to.
+    pub fn mboard(&self) -> usize {
+        self.mboard
+    }
+
+    /// Get a channel whose settings are changed at the command time.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CommandTimeError::WrongMboard`] if the channel belongs to another
+    /// motherboard, since its settings would be changed immediately.
+    pub fn channel(&self, channel: Channel) -> Result<ChannelConfig<'a>, CommandTimeError> {
+        let config = self.usrp.channel(channel)?;
+        if self.usrp.channel_mboard(channel)? != self.mboard {
+            return Err(CommandTimeError::WrongMboard {
+                channel,
+                mboard: self.mboard,
+            });
+        }
+        Ok(config)
+    }
+
+    /// Get a GPIO bank whose attributes are changed at the command time.
+    pub fn gpio_bank(&self, name: &str) -> GpioBank<'a> {
+        GpioBank::new(self.usrp, self.mboard, name)
+    }
+
+    /// Clear the command time, so that subsequent commands are executed immediately.
+    pub fn clear(mut self) -> Result<()> {
+        self.cleared = true;
+        self.usrp.mboard(self.mboard).clear_command_time()
+    }
+}
+
+impl<'a> Drop for CommandTimeGuard<'a> {
+    fn drop(&mut self) {
+        if !self.cleared {
+            let _ = self.usrp.mboard(self.mboard).clear_command_time();
+        }
+    }
+}
@@ -1,16 +1,23 @@
 use std::{ffi::CString, marker::PhantomData, ptr::addr_of_mut};
+#[cfg(feature = "num")]
+use std::{cell::RefCell, collections::HashMap};
 
+#[cfg(feature = "num")]
+use crate::calibration::ChannelCalibration;
 use crate::{
     error::try_uhd,
-    ffi::OwnedHandle,
+    ffi::{FfiStringVec, OwnedHandle},
     stream::{RxStreamBuilder, TxStreamBuilder},
-    types::DeviceArgs,
+    types::{DeviceArgs, DeviceArgsError},
     Result, Sample, TimeSpec, UhdError,
 };
 
 use super::{
     channels::{Channel, ChannelConfig},
+    command_time::{CommandTimeError, CommandTimeGuard},
+    device_config::{self, ApplyReport, DeviceConfig},
     mboard::Motherboard,
+    time_sync::{self, TimeSyncError, TimeSyncPolicy, TimeSyncReport},
 };
 
 /// The entry point for interacting with a connected USRP.
@@ -20,6 +27,9 @@ use super::{
 /// - `Usrp::open_with_args()` and a typical "key=value"` string
 /// - `Usrp::open()` and a `DeviceArgs` struct for the most flexibility.
 ///
+/// `Usrp` is `Send` but not `Sync`. See [`SharedUsrp`](crate::SharedUsrp)
+/// for sharing a device between threads.
+///
 /// # Examples
 ///
 /// ```no_run
@@ -74,9 +84,16 @@ use super::{
 /// ```
 pub struct Usrp {
     handle: OwnedHandle<uhd_usrp_sys::uhd_usrp>,
+    /// Calibration tables attached to channels.
+    #[cfg(feature = "num")]
+    calibrations: RefCell<HashMap<Channel, ChannelCalibration>>,
     _unsync: PhantomData<std::cell::Cell<()>>,
 }
 
+// UHD's device handle may be used from any thread, as long as calls aren't
+// made from several threads at once.
+unsafe impl Send for Usrp {}
+
 impl Usrp {
     /// Attempts to open a USRP using the given [`DeviceArgs`].
     ///
@@ -90,8 +107,14 @@ impl Usrp {
     ///
     /// let usrp = Usrp::open(args).expect("failed to open USRP");
     /// ```
-    pub fn open(args: DeviceArgs) -> Result<Self> {
-        Self::open_with_args(&args.to_string())
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the arguments are invalid (see [`DeviceArgs::validate`])
+    /// or the device cannot be opened.
+    pub fn open(args: DeviceArgs) -> Result<Self, DeviceArgsError> {
+        args.validate()?;
+        Ok(Self::open_with_args(&args.to_string())?)
     }
 
     /// Open any connected USRP.
@@ -118,21 +141,62 @@ impl Usrp {
     ///
     /// let usrp = Usrp::open_with_args("addr=192.168.10.4").expect("failed to open USRP");
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Value`] if the arguments contain a null character.
     pub fn open_with_args(args: &str) -> Result<Self> {
         let mut handle = std::ptr::null_mut();
-        let args = CString::new(args).unwrap();
+        let args = CString::new(args).or(Err(UhdError::Value))?;
         try_uhd!(unsafe { uhd_usrp_sys::uhd_usrp_make(addr_of_mut!(handle), args.as_ptr()) })?;
         Ok(Self {
             handle: unsafe { OwnedHandle::from_ptr(handle, uhd_usrp_sys::uhd_usrp_free) },
+            #[cfg(feature = "num")]
+            calibrations: RefCell::default(),
             _unsync: PhantomData::default(),
         })
     }
 
+    /// Find all USRPs connected to the system that match the given hint.
+    ///
+    /// An empty hint ([`DeviceArgs::new()`]) finds every device UHD can discover.
+    /// Each returned [`DeviceArgs`] describes a single device and can be passed
+    /// directly to [`Usrp::open`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use uhd_usrp::{DeviceArgs, Usrp};
+    ///
+    /// let devices = Usrp::find(&DeviceArgs::new().type_("b200")).expect("failed to find USRPs");
+    /// for device in &devices {
+    ///     println!("{:?} {:?}", device.get_product(), device.get_serial());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the hint is invalid (see [`DeviceArgs::validate`]),
+    /// the search fails, or UHD describes a device with arguments which cannot be parsed.
+    pub fn find(hint: &DeviceArgs) -> Result<Vec<DeviceArgs>, DeviceArgsError> {
+        hint.validate()?;
+        // unwrap(): validated arguments contain no null characters.
+        let hint = CString::new(hint.to_string()).unwrap();
+        let mut addrs = FfiStringVec::new();
+        try_uhd!(unsafe { uhd_usrp_sys::uhd_usrp_find(hint.as_ptr(), addrs.as_mut_ptr()) })?;
+        addrs.to_vec().iter().map(|addr| addr.parse()).collect()
+    }
+
     /// Get a reference to the underlying [`OwnedHandle`].
     pub(crate) fn handle(&self) -> &OwnedHandle<uhd_usrp_sys::uhd_usrp> {
         &self.handle
     }
 
+    #[cfg(feature = "num")]
+    pub(crate) fn calibrations(&self) -> &RefCell<HashMap<Channel, ChannelCalibration>> {
+        &self.calibrations
+    }
+
     /// Access per-motherboard properties.
     ///
     /// # Examples
@@ -198,6 +262,112 @@ impl Usrp {
         })?;
         Ok(())
     }
+
+    /// Synchronize the clocks and times of all motherboards, then verify them.
+    ///
+    /// This sets the clock and time sources of every motherboard, waits for
+    /// each of them to lock to its reference (if it has a `ref_locked` sensor),
+    /// latches the policy's time on the next PPS edge using
+    /// [`Usrp::set_time_unknown_pps`] and reads the times back.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TimeSyncError::RefLockTimeout`] if a motherboard doesn't lock in time,
+    /// or [`TimeSyncError::Disagreement`] if the motherboards' PPS times differ by more
+    /// than the policy's tolerance.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use uhd_usrp::{TimeSyncPolicy, Usrp};
+    ///
+    /// let mut usrp = Usrp::open_with_args("addr0=192.168.10.2,addr1=192.168.10.3").unwrap();
+    /// let report = usrp.synchronize(TimeSyncPolicy::external()).unwrap();
+    /// println!("time offsets: {:?}", report.time_offsets());
+    /// ```
+    pub fn synchronize(&mut self, policy: TimeSyncPolicy) -> Result<TimeSyncReport, TimeSyncError> {
+        time_sync::synchronize(self, &policy)
+    }
+
+    /// Execute subsequent control commands on a motherboard at the given time.
+    ///
+    /// The command time stays in effect until the returned guard is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CommandTimeError::Unsupported`] if the device does not support
+    /// timed commands.
+    ///
+    /// # Examples
+    ///
+    /// Retune two channels at the same instant:
+    ///
+    /// ```no_run
+    /// use uhd_usrp::{timespec, Channel, Usrp};
+    ///
+    /// let usrp = Usrp::open_any().expect("failed to open USRP");
+    /// let time = usrp.mboard(0).time().unwrap() + timespec!(100 ms);
+    /// let guard = usrp.at_time(time, 0).expect("timed commands not supported");
+    /// for ch in 0..2 {
+    ///     guard.channel(Channel::Rx(ch)).unwrap().set_center_freq(915e6).unwrap();
+    /// }
+    /// guard.clear().unwrap();
+    /// ```
+    pub fn at_time(
+        &self,
+        time: TimeSpec,
+        mboard: usize,
+    ) -> Result<CommandTimeGuard<'_>, CommandTimeError> {
+        CommandTimeGuard::new(self, time, mboard)
+    }
+
+    /// Record the current configuration of every motherboard and channel.
+    ///
+    /// Settings which the device does not support reading are left as `None`.
+    /// UHD cannot read back whether DC offset or IQ imbalance correction is enabled,
+    /// so those are never recorded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of motherboards or channels cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use uhd_usrp::Usrp;
+    ///
+    /// let usrp = Usrp::open_any().expect("failed to open USRP");
+    /// let config = usrp.snapshot().unwrap();
+    /// println!("RX 0 is tuned to {:?} Hz", config.rx[0].center_freq);
+    /// ```
+    pub fn snapshot(&self) -> Result<DeviceConfig> {
+        device_config::snapshot(self)
+    }
+
+    /// Apply a configuration, such as one recorded by [`Usrp::snapshot`].
+    ///
+    /// Motherboard settings are applied first, followed by each channel's
+    /// sample rate, antenna, LO sources, frequency, LO frequencies, gains,
+    /// bandwidth and corrections. Settings which are `None` are skipped.
+    ///
+    /// Failing settings don't stop the rest of the configuration from being
+    /// applied; instead they are listed in the returned [`ApplyReport`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use uhd_usrp::Usrp;
+    ///
+    /// let usrp = Usrp::open_any().expect("failed to open USRP");
+    /// let config = usrp.snapshot().unwrap();
+    /// // ...
+    /// for failure in usrp.apply(&config).failures {
+    ///     eprintln!("{failure}");
+    /// }
+    /// ```
+    pub fn apply(&self, config: &DeviceConfig) -> ApplyReport {
+        device_config::apply(self, config)
+    }
 }
 
 /// RX and TX streaming.
@@ -282,6 +452,25 @@ impl Usrp {
         }
     }
 
+    /// Get the motherboard a channel belongs to.
+    ///
+    /// Channels are numbered across motherboards in order, using the number of
+    /// subdevices in each motherboard's subdevice specification.
+    pub fn channel_mboard(&self, channel: Channel) -> Result<usize> {
+        let mut end = 0;
+        for mboard in 0..self.n_mboards()? {
+            let spec = match channel {
+                Channel::Rx(_) => self.mboard(mboard).rx_subdev_spec()?,
+                Channel::Tx(_) => self.mboard(mboard).tx_subdev_spec()?,
+            };
+            end += spec.len();
+            if channel.index() < end {
+                return Ok(mboard);
+            }
+        }
+        Err(UhdError::Index)
+    }
+
     /// Get the total number of RX channels on this USRP.
     pub fn rx_channels(&self) -> Result<usize> {
         let mut channels = 0;
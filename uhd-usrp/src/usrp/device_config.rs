@@ -0,0 +1,476 @@
+use crate::{Channel, Result, SubdevSpec, UhdError, Usrp};
+
+/// A snapshot of the configuration of a device.
+///
+/// Created with [`Usrp::snapshot`] and replayed with [`Usrp::apply`].
+/// Settings which are `None` were not readable from the device and are
+/// left untouched when applied.
+///
+/// With the `serde` feature enabled, configurations can be saved to and
+/// loaded from formats such as TOML or JSON.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DeviceConfig {
+    pub mboards: Vec<MboardSettings>,
+    pub rx: Vec<ChannelSettings>,
+    pub tx: Vec<ChannelSettings>,
+}
+
+/// Settings of a single motherboard.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MboardSettings {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub clock_source: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub time_source: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub master_clock_rate: Option<f64>,
+    /// The RX frontend specification, e.g. `"A:0 B:0"`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub rx_subdev_spec: Option<String>,
+    /// The TX frontend specification, e.g. `"A:0 B:0"`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tx_subdev_spec: Option<String>,
+}
+
+/// Settings of a single RX or TX channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ChannelSettings {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub antenna: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub sample_rate: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub center_freq: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub bandwidth: Option<f64>,
+    /// The overall gain in dB.
+    ///
+    /// Only recorded for channels without named gain elements.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub gain: Option<f64>,
+    /// The gain in dB of each gain element, in the order reported by
+    /// [`ChannelConfig::gain_names`](crate::ChannelConfig::gain_names).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub gains: Vec<GainSetting>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub los: Vec<LoSettings>,
+    /// Whether automatic DC offset correction is enabled.
+    ///
+    /// UHD has no way of reading this back, so it is never recorded by
+    /// [`Usrp::snapshot`] and must be filled in by hand.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub dc_offset_enabled: Option<bool>,
+    /// Whether IQ imbalance correction is enabled.
+    ///
+    /// UHD has no way of reading this back, so it is never recorded by
+    /// [`Usrp::snapshot`] and must be filled in by hand.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub iq_balance_enabled: Option<bool>,
+}
+
+/// The gain of a single gain element.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GainSetting {
+    pub name: String,
+    pub gain: f64,
+}
+
+/// Settings of a single LO stage.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LoSettings {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub source: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub freq: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub export_enabled: Option<bool>,
+}
+
+/// A setting which could not be applied.
+#[derive(thiserror::Error, Clone, Debug)]
+#[error("failed to set {field}: {error}")]
+pub struct FieldError {
+    /// Path of the setting within the [`DeviceConfig`], e.g. `rx[0].gains.PGA0`.
+    pub field: String,
+    pub error: UhdError,
+}
+
+/// The outcome of [`Usrp::apply`].
+///
+/// Applying a configuration carries on past settings which fail,
+/// so that as much of the configuration as possible is applied.
+#[derive(Clone, Debug, Default)]
+pub struct ApplyReport {
+    pub failures: Vec<FieldError>,
+}
+
+impl ApplyReport {
+    /// Returns true if every setting was applied.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Turn the report into an error if any setting failed.
+    pub fn into_result(self) -> Result<(), Vec<FieldError>> {
+        if self.failures.is_empty() {
+            Ok(())
+        } else {
+            Err(self.failures)
+        }
+    }
+}
+
+/// A single setting to be applied to the device.
+#[derive(Clone, Debug, PartialEq)]
+enum Setting<'a> {
+    ClockSource(usize, &'a str),
+    TimeSource(usize, &'a str),
+    MasterClockRate(usize, f64),
+    RxSubdevSpec(usize, &'a str),
+    TxSubdevSpec(usize, &'a str),
+    SampleRate(Channel, f64),
+    Antenna(Channel, &'a str),
+    LoSource(Channel, &'a str, &'a str),
+    LoExport(Channel, &'a str, bool),
+    CenterFreq(Channel, f64),
+    LoFreq(Channel, &'a str, f64),
+    Gain(Channel, Option<&'a str>, f64),
+    Bandwidth(Channel, f64),
+    DcOffset(Channel, bool),
+    IqBalance(Channel, bool),
+}
+
+impl<'a> Setting<'a> {
+    /// Path of the setting within the [`DeviceConfig`].
+    fn field(&self) -> String {
+        fn ch(channel: &Channel) -> String {
+            match channel {
+                Channel::Rx(i) => format!("rx[{i}]"),
+                Channel::Tx(i) => format!("tx[{i}]"),
+            }
+        }
+        match self {
+            Self::ClockSource(mb, _) => format!("mboards[{mb}].clock_source"),
+            Self::TimeSource(mb, _) => format!("mboards[{mb}].time_source"),
+            Self::MasterClockRate(mb, _) => format!("mboards[{mb}].master_clock_rate"),
+            Self::RxSubdevSpec(mb, _) => format!("mboards[{mb}].rx_subdev_spec"),
+            Self::TxSubdevSpec(mb, _) => format!("mboards[{mb}].tx_subdev_spec"),
+            Self::SampleRate(c, _) => format!("{}.sample_rate", ch(c)),
+            Self::Antenna(c, _) => format!("{}.antenna", ch(c)),
+            Self::LoSource(c, lo, _) => format!("{}.los.{lo}.source", ch(c)),
+            Self::LoExport(c, lo, _) => format!("{}.los.{lo}.export_enabled", ch(c)),
+            Self::CenterFreq(c, _) => format!("{}.center_freq", ch(c)),
+            Self::LoFreq(c, lo, _) => format!("{}.los.{lo}.freq", ch(c)),
+            Self::Gain(c, Some(name), _) => format!("{}.gains.{name}", ch(c)),
+            Self::Gain(c, None, _) => format!("{}.gain", ch(c)),
+            Self::Bandwidth(c, _) => format!("{}.bandwidth", ch(c)),
+            Self::DcOffset(c, _) => format!("{}.dc_offset_enabled", ch(c)),
+            Self::IqBalance(c, _) => format!("{}.iq_balance_enabled", ch(c)),
+        }
+    }
+
+    fn apply(&self, usrp: &Usrp) -> Result<()> {
+        match *self {
+            Self::ClockSource(mb, source) => usrp.mboard(mb).set_clock_source(source),
+            Self::TimeSource(mb, source) => usrp.mboard(mb).set_time_source(source),
+            Self::MasterClockRate(mb, rate) => usrp.mboard(mb).set_master_clock_rate(rate),
+            Self::RxSubdevSpec(mb, spec) => {
+                SubdevSpec::try_from(spec).map_err(|_| UhdError::Value)?;
+                usrp.mboard(mb).set_rx_subdev_str(spec)
+            }
+            Self::TxSubdevSpec(mb, spec) => {
+                SubdevSpec::try_from(spec).map_err(|_| UhdError::Value)?;
+                usrp.mboard(mb).set_tx_subdev_str(spec)
+            }
+            Self::SampleRate(c, rate) => usrp.channel(c)?.set_sample_rate(rate).map(drop),
+            Self::Antenna(c, name) => usrp.channel(c)?.set_antenna(name).map(drop),
+            Self::LoSource(c, lo, source) => {
+                usrp.channel(c)?.set_lo_source(Some(lo), source).map(drop)
+            }
+            Self::LoExport(c, lo, en) => usrp
+                .channel(c)?
+                .set_lo_export_enabled(Some(lo), en)
+                .map(drop),
+            Self::CenterFreq(c, freq) => usrp.channel(c)?.set_center_freq(freq).map(drop),
+            Self::LoFreq(c, lo, freq) => usrp.channel(c)?.set_lo_freq(Some(lo), freq).map(drop),
+            Self::Gain(c, name, gain) => usrp.channel(c)?.set_gain(name, gain).map(drop),
+            Self::Bandwidth(c, bw) => usrp.channel(c)?.set_bandwidth(bw).map(drop),
+            Self::DcOffset(c, en) => usrp.channel(c)?.set_dc_offset_enabled(en).map(drop),
+            Self::IqBalance(c, en) => usrp.channel(c)?.set_iq_balance_enabled(en).map(drop),
+        }
+    }
+}
+
+/// List the settings of a configuration in the order they must be applied.
+///
+/// Motherboard settings come first, since the clock source and master clock
+/// rate constrain the available sample rates and the subdevice specifications
+/// determine which channels exist. Within a channel, the sample rate is set
+/// before tuning, the antenna and LO sources before the frequency, the LO
+/// frequencies after the center frequency (which would otherwise overwrite
+/// them), and the gains and bandwidth last, since their valid ranges can
+/// depend on the frequency.
+fn plan(config: &DeviceConfig) -> Vec<Setting<'_>> {
+    let mut plan = Vec::new();
+    for (mb, s) in config.mboards.iter().enumerate() {
+        if let Some(source) = &s.clock_source {
+            plan.push(Setting::ClockSource(mb, source));
+        }
+        if let Some(source) = &s.time_source {
+            plan.push(Setting::TimeSource(mb, source));
+        }
+        if let Some(rate) = s.master_clock_rate {
+            plan.push(Setting::MasterClockRate(mb, rate));
+        }
+        if let Some(spec) = &s.rx_subdev_spec {
+            plan.push(Setting::RxSubdevSpec(mb, spec));
+        }
+        if let Some(spec) = &s.tx_subdev_spec {
+            plan.push(Setting::TxSubdevSpec(mb, spec));
+        }
+    }
+    let channels = (config.rx.iter().enumerate())
+        .map(|(i, s)| (Channel::Rx(i), s))
+        .chain(
+            config
+                .tx
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (Channel::Tx(i), s)),
+        );
+    for (c, s) in channels {
+        if let Some(rate) = s.sample_rate {
+            plan.push(Setting::SampleRate(c, rate));
+        }
+        if let Some(antenna) = &s.antenna {
+            plan.push(Setting::Antenna(c, antenna));
+        }
+        for lo in &s.los {
+            if let Some(source) = &lo.source {
+                plan.push(Setting::LoSource(c, &lo.name, source));
+            }
+            if let Some(en) = lo.export_enabled {
+                plan.push(Setting::LoExport(c, &lo.name, en));
+            }
+        }
+        if let Some(freq) = s.center_freq {
+            plan.push(Setting::CenterFreq(c, freq));
+        }
+        for lo in &s.los {
+            if let Some(freq) = lo.freq {
+                plan.push(Setting::LoFreq(c, &lo.name, freq));
+            }
+        }
+        if let Some(gain) = s.gain {
+            plan.push(Setting::Gain(c, None, gain));
+        }
+        for g in &s.gains {
+            plan.push(Setting::Gain(c, Some(&g.name), g.gain));
+        }
+        if let Some(bw) = s.bandwidth {
+            plan.push(Setting::Bandwidth(c, bw));
+        }
+        if let Some(en) = s.dc_offset_enabled {
+            plan.push(Setting::DcOffset(c, en));
+        }
+        if let Some(en) = s.iq_balance_enabled {
+            plan.push(Setting::IqBalance(c, en));
+        }
+    }
+    plan
+}
+
+/// Implementation of [`Usrp::apply`].
+pub(crate) fn apply(usrp: &Usrp, config: &DeviceConfig) -> ApplyReport {
+    let failures = plan(config)
+        .into_iter()
+        .filter_map(|setting| {
+            setting.apply(usrp).err().map(|error| FieldError {
+                field: setting.field(),
+                error,
+            })
+        })
+        .collect();
+    ApplyReport { failures }
+}
+
+/// Implementation of [`Usrp::snapshot`].
+pub(crate) fn snapshot(usrp: &Usrp) -> Result<DeviceConfig> {
+    let mboards = (0..usrp.n_mboards()?)
+        .map(|mb| {
+            let mb = usrp.mboard(mb);
+            MboardSettings {
+                clock_source: mb.clock_source().ok(),
+                time_source: mb.time_source().ok(),
+                master_clock_rate: mb.master_clock_rate().ok(),
+                rx_subdev_spec: mb.rx_subdev_spec().ok().map(|s| s.to_string()),
+                tx_subdev_spec: mb.tx_subdev_spec().ok().map(|s| s.to_string()),
+            }
+        })
+        .collect();
+    let rx = (0..usrp.rx_channels()?)
+        .map(|i| channel_snapshot(usrp, Channel::Rx(i)))
+        .collect::<Result<_>>()?;
+    let tx = (0..usrp.tx_channels()?)
+        .map(|i| channel_snapshot(usrp, Channel::Tx(i)))
+        .collect::<Result<_>>()?;
+    Ok(DeviceConfig { mboards, rx, tx })
+}
+
+fn channel_snapshot(usrp: &Usrp, channel: Channel) -> Result<ChannelSettings> {
+    let ch = usrp.channel(channel)?;
+    let gain_names = ch.gain_names().unwrap_or_default();
+    let gains = gain_names
+        .iter()
+        .filter_map(|name| {
+            let gain = ch.gain(Some(name)).ok()?;
+            Some(GainSetting {
+                name: name.clone(),
+                gain,
+            })
+        })
+        .collect();
+    let los = (ch.lo_names().unwrap_or_default().into_iter())
+        .map(|name| LoSettings {
+            source: ch.lo_source(Some(&name)).ok(),
+            freq: ch.lo_freq(Some(&name)).ok(),
+            export_enabled: ch.lo_export_enabled(Some(&name)).ok(),
+            name,
+        })
+        .collect();
+    Ok(ChannelSettings {
+        antenna: ch.antenna().ok(),
+        sample_rate: ch.sample_rate().ok(),
+        center_freq: ch.center_freq().ok(),
+        bandwidth: ch.bandwidth().ok(),
+        gain: gain_names.is_empty().then(|| ch.gain(None).ok()).flatten(),
+        gains,
+        los,
+        dc_offset_enabled: None,
+        iq_balance_enabled: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> DeviceConfig {
+        DeviceConfig {
+            mboards: vec![MboardSettings {
+                clock_source: Some("external".to_string()),
+                time_source: Some("external".to_string()),
+                master_clock_rate: Some(200e6),
+                rx_subdev_spec: Some("A:0 B:0".to_string()),
+                tx_subdev_spec: None,
+            }],
+            rx: vec![ChannelSettings {
+                antenna: Some("RX2".to_string()),
+                sample_rate: Some(10e6),
+                center_freq: Some(2.4e9),
+                bandwidth: Some(8e6),
+                gain: None,
+                gains: vec![
+                    GainSetting {
+                        name: "LNA".to_string(),
+                        gain: 10.0,
+                    },
+                    GainSetting {
+                        name: "PGA".to_string(),
+                        gain: 20.5,
+                    },
+                ],
+                los: vec![LoSettings {
+                    name: "LO1".to_string(),
+                    source: Some("internal".to_string()),
+                    freq: Some(2.5e9),
+                    export_enabled: Some(false),
+                }],
+                dc_offset_enabled: Some(true),
+                iq_balance_enabled: None,
+            }],
+            tx: vec![ChannelSettings {
+                gain: Some(30.0),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn plan_order() {
+        let config = config();
+        let fields: Vec<String> = plan(&config).iter().map(Setting::field).collect();
+        assert_eq!(
+            fields,
+            [
+                "mboards[0].clock_source",
+                "mboards[0].time_source",
+                "mboards[0].master_clock_rate",
+                "mboards[0].rx_subdev_spec",
+                "rx[0].sample_rate",
+                "rx[0].antenna",
+                "rx[0].los.LO1.source",
+                "rx[0].los.LO1.export_enabled",
+                "rx[0].center_freq",
+                "rx[0].los.LO1.freq",
+                "rx[0].gains.LNA",
+                "rx[0].gains.PGA",
+                "rx[0].bandwidth",
+                "rx[0].dc_offset_enabled",
+                "tx[0].gain",
+            ]
+        );
+        assert!(plan(&DeviceConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn report() {
+        let report = ApplyReport::default();
+        assert!(report.is_ok());
+        assert!(report.into_result().is_ok());
+        let report = ApplyReport {
+            failures: vec![FieldError {
+                field: "rx[0].antenna".to_string(),
+                error: UhdError::Value,
+            }],
+        };
+        assert!(!report.is_ok());
+        let failures = report.into_result().unwrap_err();
+        assert!(failures[0]
+            .to_string()
+            .starts_with("failed to set rx[0].antenna"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let config = config();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<DeviceConfig>(&json).unwrap(), config);
+        let toml = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<DeviceConfig>(&toml).unwrap(), config);
+
+        let partial: DeviceConfig = toml::from_str(
+            r#"
+            [[rx]]
+            center_freq = 915e6
+            gains = [{ name = "PGA0", gain = 30.0 }]
+            "#,
+        )
+        .unwrap();
+        assert!(partial.mboards.is_empty());
+        assert_eq!(partial.rx[0].center_freq, Some(915e6));
+        assert_eq!(partial.rx[0].antenna, None);
+        assert_eq!(partial.rx[0].gains[0].gain, 30.0);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{types::Range, ChannelConfig, Result, UhdError};
+
+/// Distributes a total gain across a channel's gain elements in a fixed order.
+///
+/// UHD distributes an overall gain across the gain elements itself, in an order
+/// chosen by the driver. A `GainDistribution` instead fills the elements in the
+/// order they were added: every element starts at its minimum gain, then each
+/// one in turn is raised as far as its range allows until the total is reached.
+/// This makes it possible to, for example, prefer LNA gain over PGA gain to
+/// improve the noise figure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use uhd_usrp::{Channel, GainDistribution, Usrp};
+///
+/// let usrp = Usrp::open_any().unwrap();
+/// let ch = usrp.channel(Channel::Rx(0)).unwrap();
+/// let gains = GainDistribution::from_channel(&ch, &["LNA", "PGA"])
+///     .unwrap()
+///     .apply(&ch, 40.0)
+///     .unwrap();
+/// println!("{gains:?}");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GainDistribution {
+    stages: Vec<GainStage>,
+}
+
+/// A single gain element of a [`GainDistribution`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainStage {
+    pub name: String,
+    /// The gain range of the element in dB.
+    pub range: Range,
+}
+
+impl GainDistribution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the gain ranges of the named elements of a channel.
+    ///
+    /// The elements are filled in the order given. Use
+    /// [`ChannelConfig::gain_names`] to list the available elements.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::Key`] if the channel has no element with one of the names.
+    pub fn from_channel(ch: &ChannelConfig, order: &[&str]) -> Result<Self> {
+        let names = ch.gain_names()?;
+        order.iter().try_fold(Self::new(), |dist, &name| {
+            if !names.iter().any(|n| n == name) {
+                return Err(UhdError::Key);
+            }
+            let range = ch.gain_ranges(Some(name))?;
+            Ok(dist.with_stage(
+                name,
+                Range {
+                    start: range.start(),
+                    stop: range.stop(),
+                    step: range.step(),
+                },
+            ))
+        })
+    }
+
+    /// Add a gain element, which is filled after the ones already added.
+    pub fn with_stage(mut self, name: &str, range: Range) -> Self {
+        self.stages.push(GainStage {
+            name: name.to_string(),
+            range,
+        });
+        self
+    }
+
+    pub fn stages(&self) -> &[GainStage] {
+        &self.stages
+    }
+
+    /// The smallest and largest total gain which can be distributed.
+    pub fn range(&self) -> (f64, f64) {
+        self.stages.iter().fold((0.0, 0.0), |(min, max), s| {
+            (min + s.range.start, max + s.range.stop)
+        })
+    }
+
+    /// Split a total gain in dB across the gain elements.
+    ///
+    /// The total is clipped to [`Self::range`], and each element's gain is
+    /// rounded down to a multiple of its step, with the remainder carried
+    /// over to the following elements.
+    pub fn distribute(&self, total: f64) -> Vec<(String, f64)> {
+        let (min, max) = self.range();
+        let mut remaining = total.min(max).max(min) - min;
+        self.stages
+            .iter()
+            .map(|s| {
+                let mut extra = remaining.min(s.range.stop - s.range.start).max(0.0);
+                if s.range.step > 0.0 {
+                    // Tolerate rounding errors before rounding down to a step.
+                    extra = ((extra + 1e-9) / s.range.step).floor() * s.range.step;
+                }
+                remaining -= extra;
+                (s.name.clone(), s.range.start + extra)
+            })
+            .collect()
+    }
+
+    /// Distribute a total gain in dB and set it on a channel.
+    ///
+    /// Returns the gain set on each element.
+    pub fn apply(&self, ch: &ChannelConfig, total: f64) -> Result<Vec<(String, f64)>> {
+        let gains = self.distribute(total);
+        for (name, gain) in &gains {
+            ch.set_gain(Some(name), *gain)?;
+        }
+        Ok(gains)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dist() -> GainDistribution {
+        GainDistribution::new()
+            .with_stage(
+                "LNA",
+                Range {
+                    start: 0.0,
+                    stop: 30.0,
+                    step: 1.0,
+                },
+            )
+            .with_stage(
+                "PGA",
+                Range {
+                    start: -10.0,
+                    stop: 20.0,
+                    step: 0.5,
+                },
+            )
+    }
+
+    fn gains(dist: &GainDistribution, total: f64) -> Vec<f64> {
+        dist.distribute(total).into_iter().map(|(_, g)| g).collect()
+    }
+
+    #[test]
+    fn fills_in_order() {
+        let dist = dist();
+        assert_eq!(dist.range(), (-10.0, 50.0));
+        assert_eq!(gains(&dist, 0.0), [10.0, -10.0]);
+        assert_eq!(gains(&dist, 20.0), [30.0, -10.0]);
+        assert_eq!(gains(&dist, 35.0), [30.0, 5.0]);
+        assert_eq!(dist.distribute(10.0)[0].0, "LNA");
+    }
+
+    #[test]
+    fn clips_to_range() {
+        let dist = dist();
+        assert_eq!(gains(&dist, 100.0), [30.0, 20.0]);
+        assert_eq!(gains(&dist, -50.0), [0.0, -10.0]);
+        assert!(GainDistribution::new().distribute(10.0).is_empty());
+    }
+
+    #[test]
+    fn rounds_to_steps() {
+        let dist = dist();
+        // The LNA only takes whole dB, so the fraction is left to the PGA.
+        assert_eq!(gains(&dist, 5.7), [15.0, -9.5]);
+        assert_eq!(gains(&dist, 35.2), [30.0, 5.0]);
+    }
+}
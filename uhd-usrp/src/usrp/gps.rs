@@ -0,0 +1,572 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{Result, TimeSpec, UhdError, Usrp};
+
+use super::mboard::Motherboard;
+
+/// How often to poll sensors while waiting for the GPSDO.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the GPSDO's `gps_time` sensor takes to update after a PPS edge.
+const GPS_TIME_SETTLE: Duration = Duration::from_millis(200);
+
+/// How long to wait for a time set on the next PPS to be reflected in the last
+/// PPS time. Some devices, such as the N-series, don't update it on the first edge.
+const TIME_SET_SETTLE: Duration = Duration::from_secs(2);
+
+/// An error which occurred while parsing an NMEA sentence.
+#[derive(thiserror::Error, Clone, Debug, PartialEq, Eq)]
+pub enum NmeaError {
+    #[error("expected a {expected} sentence, got {actual:?}")]
+    UnexpectedSentence {
+        expected: &'static str,
+        actual: String,
+    },
+    #[error("checksum mismatch: sentence says {expected:02X}, computed {actual:02X}")]
+    Checksum { expected: u8, actual: u8 },
+    #[error("missing field {0}")]
+    MissingField(&'static str),
+    #[error("invalid field {0}")]
+    InvalidField(&'static str),
+}
+
+/// An error which occurred while using a GPSDO.
+#[derive(thiserror::Error, Clone, Debug)]
+pub enum GpsError {
+    #[error("motherboard {mboard} has no GPSDO")]
+    NotPresent { mboard: usize },
+    #[error("sensor {0} has an unexpected type")]
+    UnexpectedValue(&'static str),
+    #[error("GPSDO did not lock within {0:?}")]
+    LockTimeout(Duration),
+    #[error("no PPS edge within {0:?}")]
+    PpsTimeout(Duration),
+    #[error("device time {device} s does not match GPS time {gps} s")]
+    TimeMismatch { gps: i64, device: i64 },
+    #[error(transparent)]
+    Nmea(#[from] NmeaError),
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+/// Quality of a GPS fix, as reported in a GGA sentence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixQuality {
+    Invalid,
+    Gps,
+    Dgps,
+    Pps,
+    RtkFixed,
+    RtkFloat,
+    Estimated,
+    Manual,
+    Simulation,
+    Other(u8),
+}
+
+impl FixQuality {
+    /// Returns `true` if the receiver has a position fix.
+    pub fn is_valid(&self) -> bool {
+        !matches!(self, FixQuality::Invalid)
+    }
+}
+
+impl From<u8> for FixQuality {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => FixQuality::Invalid,
+            1 => FixQuality::Gps,
+            2 => FixQuality::Dgps,
+            3 => FixQuality::Pps,
+            4 => FixQuality::RtkFixed,
+            5 => FixQuality::RtkFloat,
+            6 => FixQuality::Estimated,
+            7 => FixQuality::Manual,
+            8 => FixQuality::Simulation,
+            n => FixQuality::Other(n),
+        }
+    }
+}
+
+/// A UTC time of day.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NmeaTime {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: f64,
+}
+
+impl NmeaTime {
+    /// Seconds since midnight.
+    pub fn seconds_of_day(&self) -> f64 {
+        self.hour as f64 * 3600.0 + self.minute as f64 * 60.0 + self.second
+    }
+}
+
+/// A position fix from a GGA sentence.
+///
+/// Latitude and longitude are in decimal degrees, positive to the north and
+/// east. Position fields are `None` while the receiver has no fix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GgaFix {
+    pub time: Option<NmeaTime>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub quality: FixQuality,
+    pub satellites: u32,
+    pub hdop: Option<f64>,
+    /// Altitude above mean sea level, in meters.
+    pub altitude: Option<f64>,
+}
+
+/// Recommended minimum data from an RMC sentence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RmcFix {
+    /// Whether the receiver considers the data valid.
+    pub valid: bool,
+    /// UTC time, as a [`TimeSpec`] since the Unix epoch.
+    pub utc: Option<TimeSpec>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Speed over ground, in knots.
+    pub speed: Option<f64>,
+    /// Course over ground, in degrees from true north.
+    pub course: Option<f64>,
+}
+
+/// Disciplining status reported by the `gps_servo` sensor.
+///
+/// The format is specific to the GPSDO; the fields are parsed from the servo
+/// trace of the Jackson Labs units shipped in USRPs, and are `None` if the
+/// string doesn't follow it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServoStatus {
+    pub raw: String,
+    /// Offset between the PPS and UTC, in nanoseconds.
+    pub pps_offset_ns: Option<f64>,
+    /// Estimated fractional frequency error.
+    pub frequency_error: Option<f64>,
+    pub satellites_visible: Option<u32>,
+    pub satellites_tracked: Option<u32>,
+}
+
+impl FromStr for GgaFix {
+    type Err = NmeaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = sentence_fields(s, "GGA")?;
+        let field = |i: usize| fields.get(i).copied().unwrap_or("");
+        let quality =
+            parse_field::<u8>(field(6), "quality")?.ok_or(NmeaError::MissingField("quality"))?;
+        Ok(Self {
+            time: parse_time(field(1))?,
+            latitude: parse_coord(field(2), field(3), 'N', 'S', "latitude")?,
+            longitude: parse_coord(field(4), field(5), 'E', 'W', "longitude")?,
+            quality: quality.into(),
+            satellites: parse_field(field(7), "satellites")?.unwrap_or(0),
+            hdop: parse_field(field(8), "hdop")?,
+            altitude: parse_field(field(9), "altitude")?,
+        })
+    }
+}
+
+impl FromStr for RmcFix {
+    type Err = NmeaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = sentence_fields(s, "RMC")?;
+        let field = |i: usize| fields.get(i).copied().unwrap_or("");
+        let valid = match field(2) {
+            "A" => true,
+            "V" => false,
+            "" => return Err(NmeaError::MissingField("status")),
+            _ => return Err(NmeaError::InvalidField("status")),
+        };
+        let utc = match (parse_time(field(1))?, parse_date(field(9))?) {
+            (Some(time), Some(days)) => Some(
+                TimeSpec::from_secs(days * 86_400) + TimeSpec::from_secs_f64(time.seconds_of_day()),
+            ),
+            _ => None,
+        };
+        Ok(Self {
+            valid,
+            utc,
+            latitude: parse_coord(field(3), field(4), 'N', 'S', "latitude")?,
+            longitude: parse_coord(field(5), field(6), 'E', 'W', "longitude")?,
+            speed: parse_field(field(7), "speed")?,
+            course: parse_field(field(8), "course")?,
+        })
+    }
+}
+
+impl FromStr for ServoStatus {
+    type Err = NmeaError;
+
+    /// Parse a servo trace such as
+    /// `09/16/14 16:50:32 12 35438 -21.93 -5.65E-11 14 10 6 0x0`.
+    ///
+    /// This never fails; unrecognized fields are left as `None`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let field = |i: usize| fields.get(i).and_then(|f| f.parse().ok());
+        Ok(Self {
+            raw: s.trim().to_string(),
+            pps_offset_ns: field(4),
+            frequency_error: field(5),
+            satellites_visible: field(6).map(|n: f64| n as u32),
+            satellites_tracked: field(7).map(|n: f64| n as u32),
+        })
+    }
+}
+
+/// Validate the checksum of a sentence and split it into fields.
+///
+/// The first field is the sentence type, which must end with `kind` (the
+/// talker ID, e.g. `GP` or `GN`, is ignored).
+fn sentence_fields<'s>(s: &'s str, kind: &'static str) -> Result<Vec<&'s str>, NmeaError> {
+    let s = s.trim();
+    let body = s.strip_prefix('$').unwrap_or(s);
+    let body = match body.split_once('*') {
+        Some((body, checksum)) => {
+            let expected =
+                u8::from_str_radix(checksum, 16).or(Err(NmeaError::InvalidField("checksum")))?;
+            let actual = body.bytes().fold(0, |acc, b| acc ^ b);
+            if expected != actual {
+                return Err(NmeaError::Checksum { expected, actual });
+            }
+            body
+        }
+        None => body,
+    };
+    let fields: Vec<&str> = body.split(',').collect();
+    if fields[0].len() != 5 || !fields[0].ends_with(kind) {
+        return Err(NmeaError::UnexpectedSentence {
+            expected: kind,
+            actual: fields[0].to_string(),
+        });
+    }
+    Ok(fields)
+}
+
+/// Parse an optional field.
+fn parse_field<T: FromStr>(field: &str, name: &'static str) -> Result<Option<T>, NmeaError> {
+    match field {
+        "" => Ok(None),
+        f => f.parse().map(Some).or(Err(NmeaError::InvalidField(name))),
+    }
+}
+
+/// Parse a time of day in `hhmmss.ss` format.
+fn parse_time(field: &str) -> Result<Option<NmeaTime>, NmeaError> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let invalid = NmeaError::InvalidField("time");
+    if field.len() < 6 || !field.is_char_boundary(6) {
+        return Err(invalid);
+    }
+    let hour: u8 = field[0..2].parse().or(Err(invalid.clone()))?;
+    let minute: u8 = field[2..4].parse().or(Err(invalid.clone()))?;
+    let second: f64 = field[4..].parse().or(Err(invalid.clone()))?;
+    if hour > 23 || minute > 59 || !(0.0..61.0).contains(&second) {
+        return Err(invalid);
+    }
+    Ok(Some(NmeaTime {
+        hour,
+        minute,
+        second,
+    }))
+}
+
+/// Parse a date in `ddmmyy` format into days since the Unix epoch.
+fn parse_date(field: &str) -> Result<Option<i64>, NmeaError> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let invalid = NmeaError::InvalidField("date");
+    if field.len() != 6 || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid);
+    }
+    let day: u32 = field[0..2].parse().unwrap();
+    let month: u32 = field[2..4].parse().unwrap();
+    let year: i64 = field[4..6].parse().unwrap();
+    if !(1..=31).contains(&day) || !(1..=12).contains(&month) {
+        return Err(invalid);
+    }
+    // Two-digit years; GPS receivers predate 1980 about as much as they postdate 2079.
+    let year = if year < 80 { 2000 + year } else { 1900 + year };
+    Ok(Some(days_from_civil(year, month, day)))
+}
+
+/// Parse a coordinate in `(d)ddmm.mmmm` format into decimal degrees.
+fn parse_coord(
+    value: &str,
+    hemisphere: &str,
+    positive: char,
+    negative: char,
+    name: &'static str,
+) -> Result<Option<f64>, NmeaError> {
+    let raw = match parse_field::<f64>(value, name)? {
+        Some(raw) => raw,
+        None => return Ok(None),
+    };
+    let degrees = (raw / 100.0).trunc() + (raw % 100.0) / 60.0;
+    match hemisphere.chars().next() {
+        Some(c) if c == positive => Ok(Some(degrees)),
+        Some(c) if c == negative => Ok(Some(-degrees)),
+        _ => Err(NmeaError::InvalidField(name)),
+    }
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar.
+///
+/// Inverse of the algorithm at <http://howardhinnant.github.io/date_algorithms.html>.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Access to the GPSDO of a motherboard.
+///
+/// Created by [`Motherboard::gps`].
+pub struct Gps<'a> {
+    usrp: &'a Usrp,
+    mboard: usize,
+}
+
+impl<'a> Gps<'a> {
+    pub(crate) fn new(usrp: &'a Usrp, mboard: usize) -> Self {
+        Self { usrp, mboard }
+    }
+
+    fn mb(&self) -> Motherboard<'a> {
+        Motherboard::new(self.usrp, self.mboard)
+    }
+
+    /// Returns `true` if the motherboard has a GPSDO.
+    pub fn is_present(&self) -> Result<bool> {
+        Ok(self.mb().sensor_names()?.iter().any(|s| s == "gps_locked"))
+    }
+
+    /// Returns `true` if the GPSDO is locked to GPS.
+    pub fn is_locked(&self) -> Result<bool, GpsError> {
+        self.mb()
+            .gps_locked()
+            .map_err(|e| match e {
+                UhdError::Type => GpsError::UnexpectedValue("gps_locked"),
+                e => e.into(),
+            })
+    }
+
+    /// Get the current GPS time, in whole seconds since the Unix epoch.
+    pub fn time(&self) -> Result<i64, GpsError> {
+        self.mb()
+            .sensor_value("gps_time")?
+            .as_i32()
+            .map(i64::from)
+            .ok_or(GpsError::UnexpectedValue("gps_time"))
+    }
+
+    /// Get the latest position fix.
+    pub fn gga(&self) -> Result<GgaFix, GpsError> {
+        Ok(self.string_sensor("gps_gpgga")?.parse()?)
+    }
+
+    /// Get the latest recommended minimum data.
+    pub fn rmc(&self) -> Result<RmcFix, GpsError> {
+        Ok(self.string_sensor("gps_gprmc")?.parse()?)
+    }
+
+    /// Get the disciplining status of the GPSDO.
+    pub fn servo(&self) -> Result<ServoStatus, GpsError> {
+        Ok(self.string_sensor("gps_servo")?.parse()?)
+    }
+
+    fn string_sensor(&self, name: &'static str) -> Result<String, GpsError> {
+        match self.mb().sensor_value(name)?.as_str() {
+            Some(s) => Ok(s.to_string()),
+            None => Err(GpsError::UnexpectedValue(name)),
+        }
+    }
+
+    /// Set the device time to GPS time on the next PPS edge, and verify it.
+    ///
+    /// This waits up to `timeout` for the GPSDO to lock, then follows the
+    /// procedure of UHD's `sync_to_gps` example: GPS time is read shortly after
+    /// a PPS edge and latched on the following one. After waiting two seconds
+    /// for the new time to show up in the last PPS time, the device time at the
+    /// last PPS must match GPS time. Takes about four seconds.
+    ///
+    /// The motherboard's time source should be set to `"gpsdo"` beforehand.
+    ///
+    /// Returns the device time at the PPS edge it was set on.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GpsError::NotPresent`] if there is no GPSDO,
+    /// [`GpsError::LockTimeout`] if it doesn't lock in time, and
+    /// [`GpsError::TimeMismatch`] if the device time doesn't match GPS time
+    /// afterwards.
+    pub fn set_device_time(&self, timeout: Duration) -> Result<TimeSpec, GpsError> {
+        if !self.is_present()? {
+            return Err(GpsError::NotPresent {
+                mboard: self.mboard,
+            });
+        }
+        let start = Instant::now();
+        while !self.is_locked()? {
+            if start.elapsed() >= timeout {
+                return Err(GpsError::LockTimeout(timeout));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        // Read GPS time just after a PPS edge, so there's almost a second to set it.
+        self.wait_for_pps()?;
+        std::thread::sleep(GPS_TIME_SETTLE);
+        let gps_time = self.time()?;
+        let time = TimeSpec::from_secs(gps_time + 1);
+        self.mb().set_time_next_pps(time)?;
+        std::thread::sleep(TIME_SET_SETTLE);
+
+        // Compare just after an edge, so both times refer to the same second.
+        self.wait_for_pps()?;
+        std::thread::sleep(GPS_TIME_SETTLE);
+        let gps_time = self.time()?;
+        let device_time = self.mb().last_pps_time()?.full_secs();
+        if gps_time != device_time {
+            return Err(GpsError::TimeMismatch {
+                gps: gps_time,
+                device: device_time,
+            });
+        }
+        Ok(time)
+    }
+
+    /// Wait for the next PPS edge.
+    fn wait_for_pps(&self) -> Result<(), GpsError> {
+        let timeout = Duration::from_millis(1500);
+        let last = self.mb().last_pps_time()?;
+        let start = Instant::now();
+        while self.mb().last_pps_time()? == last {
+            if start.elapsed() >= timeout {
+                return Err(GpsError::PpsTimeout(timeout));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gga() {
+        let fix: GgaFix = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            fix.time,
+            Some(NmeaTime {
+                hour: 12,
+                minute: 35,
+                second: 19.0
+            })
+        );
+        assert!((fix.latitude.unwrap() - 48.1173).abs() < 1e-9);
+        assert!((fix.longitude.unwrap() - 11.516_666_666).abs() < 1e-6);
+        assert_eq!(fix.quality, FixQuality::Gps);
+        assert_eq!(fix.satellites, 8);
+        assert_eq!(fix.hdop, Some(0.9));
+        assert_eq!(fix.altitude, Some(545.4));
+    }
+
+    #[test]
+    fn gga_without_fix() {
+        let fix: GgaFix = "$GNGGA,000012.00,,,,,0,00,99.99,,,,,,\r\n".parse().unwrap();
+        assert_eq!(fix.quality, FixQuality::Invalid);
+        assert!(!fix.quality.is_valid());
+        assert_eq!(fix.latitude, None);
+        assert_eq!(fix.altitude, None);
+        assert_eq!(fix.time.unwrap().seconds_of_day(), 12.0);
+    }
+
+    #[test]
+    fn rmc() {
+        let fix: RmcFix = "$GPRMC,225446,A,4916.45,N,12311.12,W,000.5,054.7,191194,020.3,E*68"
+            .parse()
+            .unwrap();
+        assert!(fix.valid);
+        // 1994-11-19T22:54:46Z
+        assert_eq!(fix.utc, Some(TimeSpec::from_secs(785_285_686)));
+        assert!((fix.latitude.unwrap() - 49.274_166_666).abs() < 1e-6);
+        assert!((fix.longitude.unwrap() + 123.185_333_333).abs() < 1e-6);
+        assert_eq!(fix.speed, Some(0.5));
+        assert_eq!(fix.course, Some(54.7));
+
+        let fix: RmcFix = "$GPRMC,,V,,,,,,,,,,N".parse().unwrap();
+        assert!(!fix.valid);
+        assert_eq!(fix.utc, None);
+    }
+
+    #[test]
+    fn rejects_bad_sentences() {
+        assert_eq!(
+            "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*48".parse::<GgaFix>(),
+            Err(NmeaError::Checksum {
+                expected: 0x48,
+                actual: 0x47
+            })
+        );
+        assert!(matches!(
+            "$GPRMC,225446,A,4916.45,N,12311.12,W,000.5,054.7,191194,020.3,E".parse::<GgaFix>(),
+            Err(NmeaError::UnexpectedSentence { .. })
+        ));
+        assert_eq!(
+            "$GPGGA,123519,4807.038,Q,01131.000,E,1,08,0.9,545.4,M,46.9,M,,".parse::<GgaFix>(),
+            Err(NmeaError::InvalidField("latitude"))
+        );
+        assert_eq!(
+            "$GPGGA,126519,,,,,1,08,,,,,,,".parse::<GgaFix>(),
+            Err(NmeaError::InvalidField("time"))
+        );
+        assert_eq!(
+            "$GPGGA,123519,,,,,,08,,,,,,,".parse::<GgaFix>(),
+            Err(NmeaError::MissingField("quality"))
+        );
+    }
+
+    #[test]
+    fn servo() {
+        let status: ServoStatus = "09/16/14 16:50:32 12 35438 -21.93 -5.65E-11 14 10 6 0x0"
+            .parse()
+            .unwrap();
+        assert_eq!(status.pps_offset_ns, Some(-21.93));
+        assert_eq!(status.frequency_error, Some(-5.65e-11));
+        assert_eq!(status.satellites_visible, Some(14));
+        assert_eq!(status.satellites_tracked, Some(10));
+
+        let status: ServoStatus = "unexpected".parse().unwrap();
+        assert_eq!(status.raw, "unexpected");
+        assert_eq!(status.pps_offset_ns, None);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(parse_date("010180").unwrap(), Some(3_652));
+        assert_eq!(parse_date("290224").unwrap(), Some(19_782));
+        assert!(parse_date("320124").is_err());
+    }
+}
@@ -3,11 +3,11 @@ use std::{ffi::CString, ptr::addr_of_mut};
 use crate::{
     ffi::{FfiString, FfiStringVec, OwnedHandle},
     try_uhd,
-    types::SensorValue,
+    types::{SensorValue, Temperature},
     Result, TimeSpec, Usrp,
 };
 
-use super::subdev_spec::SubdevSpec;
+use super::{gps::Gps, subdev_spec::SubdevSpec};
 
 /// Provides access to motherboard properties.
 pub struct Motherboard<'a> {
@@ -61,6 +61,14 @@ impl<'a> Motherboard<'a> {
         Ok(vec.to_vec())
     }
 
+    /// Clear the command time, so that subsequent commands are executed immediately.
+    pub fn clear_command_time(&self) -> Result<()> {
+        try_uhd!(unsafe {
+            uhd_usrp_sys::uhd_usrp_clear_command_time(self.usrp.handle().as_mut_ptr(), self.mboard)
+        })?;
+        Ok(())
+    }
+
     /// Fetch information about a daughterboard EEPROM.
     pub fn dboard_eeprom(&self, unit: &str, slot: &str) -> Result<DaughterboardEeprom> {
         let unit = CString::new(unit).unwrap();
@@ -114,6 +122,11 @@ impl<'a> Motherboard<'a> {
         GpioBank::new(self.usrp, self.mboard, name)
     }
 
+    /// Access the GPSDO of the motherboard, if it has one.
+    pub fn gps(&self) -> Gps<'a> {
+        Gps::new(self.usrp, self.mboard)
+    }
+
     /// Get the time when the last pps pulse occurred.
     ///
     /// For RFNoC devices with multiple timekeepers, this returns the time of the first timekeeper.
@@ -203,6 +216,38 @@ impl<'a> Motherboard<'a> {
         SensorValue::from_handle(&handle)
     }
 
+    /// Returns `true` if the motherboard is locked to its reference clock (`ref_locked` sensor).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the motherboard has no such sensor, or
+    /// [`UhdError::Type`](crate::UhdError::Type) if it isn't a boolean.
+    pub fn ref_locked(&self) -> Result<bool> {
+        self.sensor_value("ref_locked")?.try_bool()
+    }
+
+    /// Returns `true` if the motherboard's GPSDO is locked to GPS (`gps_locked` sensor).
+    ///
+    /// See [`Motherboard::gps`] for more GPS information.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the motherboard has no GPSDO, or
+    /// [`UhdError::Type`](crate::UhdError::Type) if the sensor isn't a boolean.
+    pub fn gps_locked(&self) -> Result<bool> {
+        self.sensor_value("gps_locked")?.try_bool()
+    }
+
+    /// Get the temperature of the motherboard (`temp` sensor).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the motherboard has no such sensor, or
+    /// [`UhdError::Type`](crate::UhdError::Type) if it isn't a temperature.
+    pub fn temperature(&self) -> Result<Temperature> {
+        self.sensor_value("temp")?.try_temperature()
+    }
+
     /// Set the clock source for the motherboard.
     ///
     /// This sets the source of the frequency reference, typically a 10 MHz signal.
@@ -250,6 +295,47 @@ impl<'a> Motherboard<'a> {
         Ok(())
     }
 
+    /// Set the time at which subsequent control commands are executed.
+    ///
+    /// Commands such as tuning, gain changes and GPIO writes are queued on the device
+    /// and executed once its time reaches `time`, until [`Motherboard::clear_command_time`]
+    /// is called. Prefer [`Usrp::at_time`], which clears the command time automatically.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UhdError::NotImplemented`](crate::UhdError::NotImplemented) if the
+    /// device does not support timed commands.
+    pub fn set_command_time(&self, time: TimeSpec) -> Result<()> {
+        try_uhd!(unsafe {
+            uhd_usrp_sys::uhd_usrp_set_command_time(
+                self.usrp.handle().as_mut_ptr(),
+                time.full_secs(),
+                time.frac_secs(),
+                self.mboard,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Set the master clock rate in Hz.
+    ///
+    /// The master clock rate determines which sample rates are available,
+    /// so it should be set before configuring the channels' sample rates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rate is not supported by the device.
+    pub fn set_master_clock_rate(&self, rate: f64) -> Result<()> {
+        try_uhd!(unsafe {
+            uhd_usrp_sys::uhd_usrp_set_master_clock_rate(
+                self.usrp.handle().as_mut_ptr(),
+                rate,
+                self.mboard,
+            )
+        })?;
+        Ok(())
+    }
+
     /// Set the Rx frontend specification.
     pub fn set_rx_subdev_str(&mut self, subdev: &str) -> Result<()> {
         let sudev = SubdevSpec::from_str(subdev);
@@ -1,13 +1,29 @@
+mod backend;
 mod channels;
+mod command_time;
 mod device;
+mod device_config;
+mod gain;
+mod gps;
 mod hw_info;
 mod mboard;
+mod shared;
 pub mod stream;
 mod subdev_spec;
+mod time_sync;
 
-pub use channels::Channel;
+pub use backend::{Device, RxStreamer, StreamDevice, TxStreamer};
+pub use channels::{Channel, ChannelConfig};
+pub use command_time::{CommandTimeError, CommandTimeGuard};
 pub use device::Usrp;
+pub use device_config::{
+    ApplyReport, ChannelSettings, DeviceConfig, FieldError, GainSetting, LoSettings, MboardSettings,
+};
+pub use gain::{GainDistribution, GainStage};
+pub use gps::{FixQuality, GgaFix, Gps, GpsError, NmeaError, NmeaTime, RmcFix, ServoStatus};
 pub use hw_info::HardwareInfo;
 pub use mboard::{GpioBank, Motherboard};
+pub use shared::SharedUsrp;
 pub use stream::{RxStream, TxStream};
 pub use subdev_spec::{SubdevPair, SubdevSpec, SubdevSpecParseError};
+pub use time_sync::{BoardTime, TimeSyncError, TimeSyncPolicy, TimeSyncReport};
@@ -0,0 +1,130 @@
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError};
+
+use crate::{types::DeviceArgsError, DeviceArgs, Usrp};
+
+/// A [`Usrp`] which can be shared between threads.
+///
+/// A `Usrp` can be moved to another thread but not used from several threads
+/// at once. A `SharedUsrp` wraps it in a lock so that it can be placed in an
+/// [`Arc`](std::sync::Arc) and used from any thread. Control-plane calls are
+/// serialized: each thread locks the device, configures it, and releases it.
+///
+/// Streams don't go through the lock. Once opened, an [`RxStream`](crate::RxStream)
+/// or [`TxStream`](crate::TxStream) can be moved to its own thread and keeps
+/// streaming while other threads retune the device.
+///
+/// A [`CommandTimeGuard`](crate::CommandTimeGuard) borrows the locked device,
+/// so no other thread's commands can be timed by accident while it is alive.
+///
+/// # Examples
+///
+/// Retune from a control thread while another thread receives:
+///
+/// ```no_run
+/// use std::{sync::Arc, thread, time::Duration};
+///
+/// use num_complex::Complex32;
+/// use uhd_usrp::{Channel, SharedUsrp, Usrp};
+///
+/// let usrp = Arc::new(SharedUsrp::new(Usrp::open_any().unwrap()));
+/// let mut stream = usrp.lock().rx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+///
+/// let rx = thread::spawn(move || {
+///     let mut buf = vec![Complex32::default(); 10_000];
+///     stream.start_command().send().unwrap();
+///     for _ in 0..100 {
+///         let n = stream.reader().recv(&mut buf).unwrap();
+///         println!("received {n} samples");
+///     }
+/// });
+///
+/// let control = Arc::clone(&usrp);
+/// thread::spawn(move || {
+///     for freq in [915e6, 920e6, 925e6] {
+///         control.with(|usrp| {
+///             usrp.channel(Channel::Rx(0))?.set_center_freq(freq)?;
+///             Ok::<_, uhd_usrp::UhdError>(())
+///         })
+///         .unwrap();
+///         thread::sleep(Duration::from_millis(100));
+///     }
+/// });
+///
+/// rx.join().unwrap();
+/// ```
+pub struct SharedUsrp {
+    usrp: Mutex<Usrp>,
+}
+
+impl SharedUsrp {
+    pub fn new(usrp: Usrp) -> Self {
+        Self {
+            usrp: Mutex::new(usrp),
+        }
+    }
+
+    /// Open a USRP using the given [`DeviceArgs`] and wrap it for sharing.
+    ///
+    /// See [`Usrp::open`].
+    pub fn open(args: DeviceArgs) -> Result<Self, DeviceArgsError> {
+        Usrp::open(args).map(Self::new)
+    }
+
+    /// Lock the device, blocking until no other thread holds it.
+    ///
+    /// The device is unlocked when the returned guard is dropped.
+    /// A panic on another thread while it held the lock does not poison the
+    /// device, since UHD's state doesn't depend on the panicking call finishing.
+    pub fn lock(&self) -> MutexGuard<'_, Usrp> {
+        self.usrp.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock the device if no other thread holds it.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, Usrp>> {
+        match self.usrp.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Run a closure with the device locked.
+    pub fn with<R>(&self, f: impl FnOnce(&mut Usrp) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Unwrap the device.
+    pub fn into_inner(self) -> Usrp {
+        self.usrp
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl From<Usrp> for SharedUsrp {
+    fn from(usrp: Usrp) -> Self {
+        Self::new(usrp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+    use super::*;
+    use crate::{ChannelConfig, Motherboard, RxStream, TxStream};
+
+    assert_impl_all!(Usrp: Send);
+    assert_not_impl_any!(Usrp: Sync);
+    assert_impl_all!(SharedUsrp: Send, Sync);
+    assert_impl_all!(std::sync::Arc<SharedUsrp>: Send, Sync);
+
+    // Views borrow the device, so they stay on the thread using it.
+    assert_not_impl_any!(ChannelConfig<'static>: Send, Sync);
+    assert_not_impl_any!(Motherboard<'static>: Send, Sync);
+
+    assert_impl_all!(RxStream<[f32; 2]>: Send);
+    assert_impl_all!(TxStream<[f32; 2]>: Send);
+    assert_not_impl_any!(RxStream<[f32; 2]>: Sync);
+    assert_not_impl_any!(TxStream<[f32; 2]>: Sync);
+}
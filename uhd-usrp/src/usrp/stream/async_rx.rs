@@ -0,0 +1,284 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use futures::{channel::mpsc, SinkExt, Stream};
+
+use super::{RxStream, StreamCommand};
+use crate::{
+    types::RxErrorCode, ArrayBuffer, Result, RxMetadata, RxStreamer, Sample, SampleBuffer,
+};
+
+/// Timeout of a single receive on the background thread.
+///
+/// Bounds how long dropping an [`AsyncRxStream`] may block.
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A block of samples received by an [`AsyncRxStream`], along with its metadata.
+pub type AsyncRxItem<T> = Result<(ArrayBuffer<T>, RxMetadata)>;
+
+/// An RX stream driven by a background thread, exposed as a [`futures::Stream`].
+///
+/// Each item is a block of at most [`RxStream::max_samples_per_channel`] samples
+/// per channel. Timeouts are not reported; other error conditions (e.g. overflows)
+/// are yielded as empty blocks so that their metadata can be inspected.
+///
+/// At most `capacity` blocks are buffered. When the buffer is full the receive
+/// thread waits for the consumer, which usually leads to overflows on the device.
+/// Such waits are counted in [`AsyncRxStats::backpressure_waits`].
+///
+/// Dropping the stream stops the background thread and issues a
+/// [`StreamCommand::StopContinuous`] command.
+pub struct AsyncRxStream<T: Sample> {
+    receiver: mpsc::Receiver<AsyncRxItem<T>>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Counters describing the activity of an [`AsyncRxStream`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AsyncRxStats {
+    /// Number of blocks received from the device.
+    pub blocks: u64,
+    /// Number of samples per channel received from the device.
+    pub samples: u64,
+    /// Number of overflows reported by the device.
+    pub overflows: u64,
+    /// Number of times the receive thread found the buffer full and
+    /// had to wait for the consumer.
+    pub backpressure_waits: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    stop: AtomicBool,
+    blocks: AtomicU64,
+    samples: AtomicU64,
+    overflows: AtomicU64,
+    backpressure_waits: AtomicU64,
+}
+
+impl<T> AsyncRxStream<T>
+where
+    T: Sample + Clone + Default + Send + 'static,
+{
+    /// Number of blocks buffered by [`RxStream::into_async`].
+    pub const DEFAULT_CAPACITY: usize = 16;
+
+    /// Drive an arbitrary [`RxStreamer`] from a background thread.
+    ///
+    /// The stream must already have been started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn spawn<S>(stream: S, capacity: usize) -> Self
+    where
+        S: RxStreamer<T> + Send + 'static,
+    {
+        assert!(capacity > 0, "capacity must be non-zero");
+        // The channel holds one item per sender on top of its buffer.
+        let (sender, receiver) = mpsc::channel(capacity - 1);
+        let shared = Arc::new(Shared::default());
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || receive_loop(stream, sender, &shared))
+        };
+        Self {
+            receiver,
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Get a snapshot of the stream's counters.
+    pub fn stats(&self) -> AsyncRxStats {
+        AsyncRxStats {
+            blocks: self.shared.blocks.load(Ordering::Relaxed),
+            samples: self.shared.samples.load(Ordering::Relaxed),
+            overflows: self.shared.overflows.load(Ordering::Relaxed),
+            backpressure_waits: self.shared.backpressure_waits.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T: Sample> Stream for AsyncRxStream<T> {
+    type Item = AsyncRxItem<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+impl<T: Sample> Drop for AsyncRxStream<T> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        // Wakes the thread if it is waiting for room in the buffer.
+        self.receiver.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<T> RxStream<T>
+where
+    T: Sample + Clone + Default + Send + 'static,
+{
+    /// Convert the stream into an [`AsyncRxStream`] buffering up to
+    /// [`AsyncRxStream::DEFAULT_CAPACITY`] blocks.
+    ///
+    /// The stream must already have been started.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use num_complex::Complex32;
+    /// use uhd_usrp::{SampleBuffer, Usrp};
+    ///
+    /// # futures::executor::block_on(async {
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let stream = usrp.rx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+    /// stream.start_command().send().unwrap();
+    ///
+    /// let mut blocks = stream.into_async();
+    /// while let Some(block) = blocks.next().await {
+    ///     let (buff, md) = block.unwrap();
+    ///     println!("{} samples at {:?}", buff.samples(), md.time_spec());
+    /// }
+    /// # });
+    /// ```
+    pub fn into_async(self) -> AsyncRxStream<T> {
+        self.into_async_with_capacity(AsyncRxStream::<T>::DEFAULT_CAPACITY)
+    }
+
+    /// Convert the stream into an [`AsyncRxStream`] buffering up to `capacity` blocks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn into_async_with_capacity(self, capacity: usize) -> AsyncRxStream<T> {
+        AsyncRxStream::spawn(self, capacity)
+    }
+}
+
+fn receive_loop<T, S>(mut stream: S, mut sender: mpsc::Sender<AsyncRxItem<T>>, shared: &Shared)
+where
+    T: Sample + Clone + Default,
+    S: RxStreamer<T>,
+{
+    let channels = stream.channels();
+    let samples = stream.max_samples_per_channel();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let mut buff = ArrayBuffer::new(channels, samples);
+        let mut md = RxMetadata::new();
+        let item = match stream.recv(&mut buff, RECV_TIMEOUT, &mut md) {
+            Ok(_) if md.error_code == Some(RxErrorCode::Timeout) => continue,
+            Ok(received) => {
+                if md.error_code == Some(RxErrorCode::Overflow) {
+                    shared.overflows.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok((truncate(buff, received), md))
+            }
+            Err(e) => Err(e),
+        };
+        let failed = item.is_err();
+        if let Ok((buff, _)) = &item {
+            shared.blocks.fetch_add(1, Ordering::Relaxed);
+            shared
+                .samples
+                .fetch_add(buff.samples() as u64, Ordering::Relaxed);
+        }
+        let delivered = match sender.try_send(item) {
+            Ok(()) => true,
+            Err(e) if e.is_full() => {
+                shared.backpressure_waits.fetch_add(1, Ordering::Relaxed);
+                futures::executor::block_on(sender.send(e.into_inner())).is_ok()
+            }
+            Err(_) => false,
+        };
+        if !delivered || failed {
+            break;
+        }
+    }
+    let _ = stream.issue_stream_cmd(StreamCommand::StopContinuous);
+}
+
+/// Shrink a buffer to the number of samples received.
+fn truncate<T: Sample>(buff: ArrayBuffer<T>, samples: usize) -> ArrayBuffer<T> {
+    if samples == buff.samples() {
+        return buff;
+    }
+    let mut channels = buff.into_vec();
+    channels.iter_mut().for_each(|c| c.truncate(samples));
+    ArrayBuffer::from_vec_channels(channels)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use futures::{executor::block_on, StreamExt};
+
+    use super::*;
+    use crate::{sim::SimDevice, Channel, Device, StreamDevice, TimeSpec};
+
+    #[test]
+    fn blocks_are_delivered_in_order() {
+        let device = SimDevice::new(1, 0);
+        device.set_sample_rate(Channel::Rx(0), 1e6).unwrap();
+        let mut stream = device.open_rx_stream(&[0]).unwrap();
+        stream
+            .issue_stream_cmd(StreamCommand::NumSamples {
+                samples: 5000,
+                done: true,
+                time: None,
+            })
+            .unwrap();
+
+        let mut blocks = AsyncRxStream::<[f32; 2]>::spawn(stream, 4);
+        let lengths: Vec<_> = block_on(async {
+            let mut lengths = Vec::new();
+            for _ in 0..3 {
+                let (buff, md) = blocks.next().await.unwrap().unwrap();
+                assert_eq!(md.error_code().unwrap(), RxErrorCode::None);
+                let expected = TimeSpec::from_secs_f64(lengths.iter().sum::<usize>() as f64 / 1e6);
+                assert_eq!(md.time_spec(), Some(expected));
+                lengths.push(buff.samples());
+            }
+            lengths
+        });
+        assert_eq!(lengths, [2000, 2000, 1000]);
+        let stats = blocks.stats();
+        assert_eq!(stats.blocks, 3);
+        assert_eq!(stats.samples, 5000);
+    }
+
+    #[test]
+    fn slow_consumer_applies_backpressure() {
+        let device = SimDevice::new(1, 0);
+        let mut stream = device.open_rx_stream(&[0]).unwrap();
+        stream
+            .issue_stream_cmd(StreamCommand::StartContinuous { time: None })
+            .unwrap();
+
+        let mut blocks = AsyncRxStream::<[f32; 2]>::spawn(stream, 2);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while blocks.stats().backpressure_waits == 0 {
+            assert!(Instant::now() < deadline, "receive thread never waited");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // Two blocks are buffered and a third is waiting for room.
+        assert_eq!(blocks.stats().blocks, 3);
+        block_on(blocks.next()).unwrap().unwrap();
+        drop(blocks);
+    }
+}
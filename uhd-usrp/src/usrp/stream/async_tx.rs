@@ -0,0 +1,433 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use futures::{channel::mpsc, task::AtomicWaker, FutureExt, Sink, Stream, StreamExt};
+
+use super::TxStream;
+use crate::{
+    types::TxAsyncEvent, ArrayBuffer, Result, Sample, SampleBuffer, TxMetadata, TxStreamer,
+    UhdError,
+};
+
+/// Timeout of a single send on the background thread.
+const SEND_TIMEOUT: Duration = Duration::from_millis(100);
+/// How often the background thread checks for async messages while idle.
+/// Queued blocks wake it up immediately.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long to wait for async messages (e.g. the burst ACK) after the last send.
+const CLOSE_EVENT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A block of samples sent by an [`AsyncTxSink`], along with its metadata.
+pub type AsyncTxItem<T> = (ArrayBuffer<T>, TxMetadata);
+
+/// A TX stream driven by a background thread, exposed as a [`futures::Sink`].
+///
+/// Blocks are sent in order with the metadata they were queued with. At most
+/// `capacity` blocks are queued; once the queue is full the sink is not ready
+/// until the background thread catches up. Flushing the sink waits until every
+/// queued block has been handed to the device.
+///
+/// Closing the sink sends the remaining blocks and, if a burst is still in
+/// progress, an empty end-of-burst packet. Dropping the sink without closing it
+/// discards any queued blocks but still ends the burst.
+///
+/// An error from the device stops the background thread, and is returned by
+/// the next call to the sink.
+pub struct AsyncTxSink<T: Sample> {
+    sender: mpsc::Sender<AsyncTxItem<T>>,
+    shared: Arc<Shared>,
+    queued: u64,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Asynchronous messages reported by the device to an [`AsyncTxSink`].
+///
+/// The stream ends once the sink has been closed or dropped.
+pub struct AsyncTxEvents {
+    receiver: mpsc::UnboundedReceiver<TxAsyncEvent>,
+}
+
+#[derive(Default)]
+struct Shared {
+    stop: AtomicBool,
+    done: AtomicBool,
+    sent: AtomicU64,
+    error: Mutex<Option<UhdError>>,
+    waker: AtomicWaker,
+}
+
+impl<T> AsyncTxSink<T>
+where
+    T: Sample + Send + 'static,
+{
+    /// Number of blocks queued by [`TxStream::into_async`].
+    pub const DEFAULT_CAPACITY: usize = 16;
+
+    /// Drive an arbitrary [`TxStreamer`] from a background thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn spawn<S>(stream: S, capacity: usize) -> (Self, AsyncTxEvents)
+    where
+        S: TxStreamer<T> + Send + 'static,
+    {
+        assert!(capacity > 0, "capacity must be non-zero");
+        // The channel holds one item per sender on top of its buffer.
+        let (sender, receiver) = mpsc::channel(capacity - 1);
+        let (events, events_receiver) = mpsc::unbounded();
+        let shared = Arc::new(Shared::default());
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let mut state = SendState {
+                    stream,
+                    events,
+                    in_burst: false,
+                };
+                if let Err(e) = state.run(receiver, &shared) {
+                    *shared.error.lock().unwrap() = Some(e);
+                }
+                shared.done.store(true, Ordering::Release);
+                shared.waker.wake();
+            })
+        };
+        let sink = Self {
+            sender,
+            shared,
+            queued: 0,
+            thread: Some(thread),
+        };
+        let events = AsyncTxEvents {
+            receiver: events_receiver,
+        };
+        (sink, events)
+    }
+}
+
+impl<T: Sample> AsyncTxSink<T> {
+    /// The error which stopped the background thread, if any.
+    fn error(&self) -> Option<UhdError> {
+        self.shared.error.lock().unwrap().clone()
+    }
+
+    /// Wake the background thread if it is waiting for blocks.
+    fn wake_thread(&self) {
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+
+    /// Returns the stored error, or a generic one if the thread exited without one.
+    fn stopped_error(&self) -> UhdError {
+        self.error().unwrap_or(UhdError::Unknown)
+    }
+
+    fn poll_until(&self, cx: &mut Context<'_>, done: impl Fn(&Shared) -> bool) -> Poll<()> {
+        if done(&self.shared) {
+            return Poll::Ready(());
+        }
+        self.shared.waker.register(cx.waker());
+        match done(&self.shared) {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+}
+
+impl<T: Sample> Sink<AsyncTxItem<T>> for AsyncTxSink<T> {
+    type Error = UhdError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(e) = self.error() {
+            return Poll::Ready(Err(e));
+        }
+        match self.sender.poll_ready(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(self.stopped_error())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: AsyncTxItem<T>) -> Result<()> {
+        self.sender
+            .start_send(item)
+            .map_err(|_| self.stopped_error())?;
+        self.queued += 1;
+        self.wake_thread();
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let queued = self.queued;
+        let flushed = self.poll_until(cx, |shared| {
+            shared.sent.load(Ordering::Acquire) >= queued || shared.done.load(Ordering::Acquire)
+        });
+        if flushed.is_pending() {
+            return Poll::Pending;
+        }
+        match self.error() {
+            Some(e) => Poll::Ready(Err(e)),
+            None if self.shared.sent.load(Ordering::Acquire) < queued => {
+                Poll::Ready(Err(self.stopped_error()))
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.sender.close_channel();
+        self.wake_thread();
+        if self
+            .poll_until(cx, |shared| shared.done.load(Ordering::Acquire))
+            .is_pending()
+        {
+            return Poll::Pending;
+        }
+        match self.error() {
+            Some(e) => Poll::Ready(Err(e)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl<T: Sample> Drop for AsyncTxSink<T> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        self.sender.close_channel();
+        self.wake_thread();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Stream for AsyncTxEvents {
+    type Item = TxAsyncEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<TxAsyncEvent>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+impl<T> TxStream<T>
+where
+    T: Sample + Send + 'static,
+{
+    /// Convert the stream into an [`AsyncTxSink`] queueing up to
+    /// [`AsyncTxSink::DEFAULT_CAPACITY`] blocks, along with a stream of
+    /// the device's asynchronous messages.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures::SinkExt;
+    /// use num_complex::Complex32;
+    /// use uhd_usrp::{ArrayBuffer, TxMetadataBuilder, Usrp};
+    ///
+    /// # futures::executor::block_on(async {
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let stream = usrp.tx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+    /// let (mut sink, _events) = stream.into_async();
+    ///
+    /// let md = TxMetadataBuilder::new().with_start_of_burst(true).build();
+    /// sink.send((ArrayBuffer::new(1, 1000), md)).await.unwrap();
+    /// // Ends the burst.
+    /// sink.close().await.unwrap();
+    /// # });
+    /// ```
+    pub fn into_async(self) -> (AsyncTxSink<T>, AsyncTxEvents) {
+        self.into_async_with_capacity(AsyncTxSink::<T>::DEFAULT_CAPACITY)
+    }
+
+    /// Convert the stream into an [`AsyncTxSink`] queueing up to `capacity` blocks,
+    /// along with a stream of the device's asynchronous messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn into_async_with_capacity(self, capacity: usize) -> (AsyncTxSink<T>, AsyncTxEvents) {
+        AsyncTxSink::spawn(self, capacity)
+    }
+}
+
+/// The background thread of an [`AsyncTxSink`].
+struct SendState<S> {
+    stream: S,
+    events: mpsc::UnboundedSender<TxAsyncEvent>,
+    in_burst: bool,
+}
+
+impl<S> SendState<S> {
+    fn run<T>(
+        &mut self,
+        mut receiver: mpsc::Receiver<AsyncTxItem<T>>,
+        shared: &Shared,
+    ) -> Result<()>
+    where
+        T: Sample,
+        S: TxStreamer<T>,
+    {
+        while !shared.stop.load(Ordering::Relaxed) {
+            match receiver.next().now_or_never() {
+                Some(Some((buff, md))) => {
+                    self.send(buff, md, shared)?;
+                    shared.sent.fetch_add(1, Ordering::Release);
+                    shared.waker.wake();
+                    self.forward_events(Duration::ZERO)?;
+                }
+                // The sink was closed and the queue is empty.
+                Some(None) => break,
+                // Don't block on async messages while idle, or a block queued
+                // in the meantime would wait for the timeout. The sink unparks
+                // the thread when it queues a block.
+                None => {
+                    self.forward_events(Duration::ZERO)?;
+                    std::thread::park_timeout(IDLE_POLL_INTERVAL);
+                }
+            }
+        }
+        if self.in_burst {
+            let mut md = TxMetadata::new();
+            md.set_end_of_burst(true);
+            let channels = self.stream.channels();
+            self.send(
+                ArrayBuffer::from_iter_channels((0..channels).map(|_| std::iter::empty())),
+                md,
+                shared,
+            )?;
+        }
+        self.forward_events(CLOSE_EVENT_TIMEOUT)
+    }
+
+    /// Send a whole block, continuing after partial sends.
+    fn send<T>(
+        &mut self,
+        mut buff: ArrayBuffer<T>,
+        mut md: TxMetadata,
+        shared: &Shared,
+    ) -> Result<()>
+    where
+        T: Sample,
+        S: TxStreamer<T>,
+    {
+        loop {
+            let sent = self.stream.send(&buff, &md, SEND_TIMEOUT)?;
+            if sent > 0 || buff.samples() == 0 {
+                self.in_burst = !md.end_of_burst();
+            }
+            if sent >= buff.samples() {
+                return Ok(());
+            }
+            if shared.stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            if sent > 0 {
+                let mut channels = buff.into_vec();
+                channels.iter_mut().for_each(|c| drop(c.drain(..sent)));
+                buff = ArrayBuffer::from_vec_channels(channels);
+                // The rest of the block continues the burst.
+                md.set_start_of_burst(false);
+                md.set_time_spec(None);
+            }
+        }
+    }
+
+    /// Forward async messages until none arrive within `timeout`.
+    fn forward_events<T>(&mut self, timeout: Duration) -> Result<()>
+    where
+        T: Sample,
+        S: TxStreamer<T>,
+    {
+        let mut timeout = timeout;
+        while let Some(event) = self.stream.recv_async_msg(timeout)? {
+            // The events stream may have been dropped if the caller isn't interested.
+            let _ = self.events.unbounded_send(event);
+            timeout = Duration::ZERO;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{executor::block_on, SinkExt, StreamExt};
+
+    use super::*;
+    use crate::{sim::SimDevice, types::TxEventCode, StreamDevice};
+
+    fn block(samples: usize) -> ArrayBuffer<[f32; 2]> {
+        ArrayBuffer::with_fill(1, samples, [0.5, -0.5])
+    }
+
+    #[test]
+    fn close_ends_the_burst() {
+        let device = SimDevice::new(0, 1);
+        let stream = device.open_tx_stream(&[0]).unwrap();
+        let (mut sink, events) = AsyncTxSink::<[f32; 2]>::spawn(stream, 2);
+
+        block_on(async {
+            let mut sob = TxMetadata::new();
+            sob.set_start_of_burst(true);
+            sink.send((block(100), sob)).await.unwrap();
+            for _ in 0..4 {
+                sink.feed((block(100), TxMetadata::new())).await.unwrap();
+            }
+            sink.flush().await.unwrap();
+            assert_eq!(device.tx_samples(0).len(), 500);
+            sink.close().await.unwrap();
+        });
+
+        let captures = device.tx_captures();
+        assert_eq!(captures.len(), 6);
+        assert!(captures[0].start_of_burst);
+        let last = captures.last().unwrap();
+        assert!(last.end_of_burst);
+        assert!(last.samples.is_empty());
+
+        drop(sink);
+        let events: Vec<_> = block_on(events.collect());
+        let acks = events
+            .iter()
+            .filter(|e| e.event_code() == TxEventCode::BurstAck)
+            .count();
+        assert_eq!(acks, 1);
+    }
+
+    #[test]
+    fn drop_ends_the_burst() {
+        let device = SimDevice::new(0, 1);
+        let stream = device.open_tx_stream(&[0]).unwrap();
+        let (mut sink, _events) = AsyncTxSink::<[f32; 2]>::spawn(stream, 2);
+        block_on(sink.send((block(100), TxMetadata::new()))).unwrap();
+        drop(sink);
+
+        let captures = device.tx_captures();
+        assert!(captures.last().unwrap().end_of_burst);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let device = SimDevice::new(0, 1);
+        let stream = device.open_tx_stream(&[0]).unwrap();
+        let (mut sink, _events) = AsyncTxSink::<[f32; 2]>::spawn(stream, 2);
+        // Two channels sent to a single channel stream.
+        let buff = ArrayBuffer::new(2, 100);
+        block_on(async {
+            sink.feed((buff, TxMetadata::new())).await.unwrap();
+            assert!(matches!(sink.flush().await, Err(UhdError::Index)));
+            assert!(matches!(
+                sink.send((block(100), TxMetadata::new())).await,
+                Err(UhdError::Index)
+            ));
+        });
+    }
+}
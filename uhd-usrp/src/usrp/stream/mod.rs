@@ -1,15 +1,37 @@
+#[cfg(feature = "async")]
+mod async_rx;
+#[cfg(feature = "async")]
+mod async_tx;
+mod rx_demux;
+mod rx_error;
+mod rx_receiver;
 mod rx_stream;
 mod tx_stream;
 
-pub use rx_stream::{RxStream, RxStreamBuilder, RxStreamReader};
-pub use tx_stream::{TxStream, TxStreamBuilder, TxStreamWriter};
+#[cfg(feature = "async")]
+pub use async_rx::{AsyncRxItem, AsyncRxStats, AsyncRxStream};
+#[cfg(feature = "async")]
+pub use async_tx::{AsyncTxEvents, AsyncTxItem, AsyncTxSink};
+pub use rx_demux::{RxChannelBlock, RxChannelReceiver, RxDemux, RxDemuxStats};
+pub use rx_error::{RxErrorAction, RxErrorPolicy, RxStreamError};
+pub use rx_receiver::{RxBlock, RxReceiver, RxReceiverStats};
+pub use rx_stream::{RxStartCommand, RxStream, RxStreamBuilder, RxStreamReader};
+pub use tx_stream::{TxEventListener, TxStream, TxStreamBuilder, TxStreamWriter};
+
+use crate::TimeSpec;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OtwFormat {
+    #[cfg_attr(feature = "serde", serde(rename = "sc16"))]
     ComplexInt16,
+    #[cfg_attr(feature = "serde", serde(rename = "sc12"))]
     ComplexInt12,
+    #[cfg_attr(feature = "serde", serde(rename = "sc8"))]
     ComplexInt8,
+    #[cfg_attr(feature = "serde", serde(rename = "s16"))]
     Int16,
+    #[cfg_attr(feature = "serde", serde(rename = "s8"))]
     Int8,
 }
 
@@ -24,3 +46,73 @@ impl OtwFormat {
         }
     }
 }
+
+/// A command controlling when and how many samples an RX stream produces.
+///
+/// If a time is given, the command takes effect at that device time.
+/// Otherwise it takes effect immediately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamCommand {
+    /// Stream samples continuously until stopped.
+    StartContinuous { time: Option<TimeSpec> },
+    /// Stream a fixed number of samples.
+    ///
+    /// If `done` is `true` the burst ends after the last sample,
+    /// otherwise another command is expected to continue the burst.
+    NumSamples {
+        samples: usize,
+        done: bool,
+        time: Option<TimeSpec>,
+    },
+    /// Stop a continuous stream.
+    StopContinuous,
+}
+
+impl StreamCommand {
+    /// The time the command takes effect, if any.
+    pub fn time(&self) -> Option<TimeSpec> {
+        match *self {
+            StreamCommand::StartContinuous { time } => time,
+            StreamCommand::NumSamples { time, .. } => time,
+            StreamCommand::StopContinuous => None,
+        }
+    }
+
+    pub(crate) fn to_sys(self) -> uhd_usrp_sys::uhd_stream_cmd_t {
+        let (stream_mode, num_samps) = match self {
+            StreamCommand::StartContinuous { .. } => (
+                uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_START_CONTINUOUS,
+                0,
+            ),
+            StreamCommand::NumSamples {
+                samples,
+                done: true,
+                ..
+            } => (
+                uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_NUM_SAMPS_AND_DONE,
+                samples,
+            ),
+            StreamCommand::NumSamples {
+                samples,
+                done: false,
+                ..
+            } => (
+                uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_NUM_SAMPS_AND_MORE,
+                samples,
+            ),
+            StreamCommand::StopContinuous => (
+                uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_STOP_CONTINUOUS,
+                0,
+            ),
+        };
+        let time = self.time();
+        let time_spec = time.unwrap_or(TimeSpec::ZERO);
+        uhd_usrp_sys::uhd_stream_cmd_t {
+            stream_mode,
+            num_samps,
+            stream_now: time.is_none(),
+            time_spec_full_secs: time_spec.full_secs(),
+            time_spec_frac_secs: time_spec.frac_secs(),
+        }
+    }
+}
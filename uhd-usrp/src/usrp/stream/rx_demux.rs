@@ -0,0 +1,556 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::{
+    rx_receiver::{RECV_TIMEOUT, SLEEP_INTERVAL, SPIN_LIMIT},
+    RxStream, StreamCommand,
+};
+use crate::{
+    buffer::{ring, RingConsumer, RingProducer},
+    types::RxErrorCode,
+    ArrayBuffer, Result, RxMetadata, RxStreamer, Sample, SampleBuffer, UhdError,
+};
+
+/// A background receiver splitting a multi-channel stream into per-channel queues.
+///
+/// The demultiplexer's thread owns the stream and receives each block directly
+/// into preallocated per-channel slots, so samples are copied only once, by the
+/// stream itself. Each channel is read through its own [`RxChannelReceiver`],
+/// which can be moved to a separate worker thread.
+///
+/// Every receive produces one block per channel, each holding the receive's
+/// metadata and the same [`RxChannelBlock::offset`], so blocks of different
+/// channels can be matched up even when they are read at different paces.
+///
+/// When a channel's queue is full, that channel's part of a receive is
+/// discarded without affecting the other channels. Such blocks are counted in
+/// [`RxDemuxStats::dropped_blocks`]. As with [`RxReceiver`](super::RxReceiver),
+/// timeouts are not reported and blocks with other error conditions are
+/// delivered with no samples.
+///
+/// Dropping the demultiplexer stops the thread and issues a
+/// [`StreamCommand::StopContinuous`] command. The thread also stops once every
+/// channel receiver has been dropped.
+pub struct RxDemux {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The queue of blocks received on a single channel of an [`RxDemux`].
+pub struct RxChannelReceiver<T: Sample> {
+    channel: usize,
+    consumer: RingConsumer<Block<T>>,
+    shared: Arc<Shared>,
+}
+
+/// A block of samples received on a single channel, borrowed from an [`RxChannelReceiver`].
+///
+/// The block is returned to the receiver when the guard is dropped.
+pub struct RxChannelBlock<'a, T: Sample> {
+    consumer: &'a mut RingConsumer<Block<T>>,
+}
+
+/// Counters describing the activity of an [`RxDemux`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RxDemuxStats {
+    /// Number of receives from the stream.
+    pub blocks: u64,
+    /// Number of samples per channel received from the stream.
+    pub samples: u64,
+    /// Number of overflows reported by the device.
+    pub overflows: u64,
+    /// Number of blocks discarded because the channel's queue was full, per channel.
+    pub dropped_blocks: Vec<u64>,
+    /// Number of samples discarded because the channel's queue was full, per channel.
+    pub dropped_samples: Vec<u64>,
+}
+
+/// A slot of a channel's ring.
+struct Block<T: Sample> {
+    buff: Vec<T>,
+    samples: usize,
+    offset: u64,
+    metadata: RxMetadata,
+}
+
+struct Shared {
+    stop: AtomicBool,
+    blocks: AtomicU64,
+    samples: AtomicU64,
+    overflows: AtomicU64,
+    dropped_blocks: Vec<AtomicU64>,
+    dropped_samples: Vec<AtomicU64>,
+    error: Mutex<Option<UhdError>>,
+}
+
+/// Pointers to the per-channel buffers a single receive is written to.
+struct ChannelPtrs<T> {
+    ptrs: Vec<*mut T>,
+    samples: usize,
+}
+
+impl<T: Sample> SampleBuffer<T> for ChannelPtrs<T> {
+    fn channels(&self) -> usize {
+        self.ptrs.len()
+    }
+
+    fn samples(&self) -> usize {
+        self.samples
+    }
+
+    fn as_ptr(&self) -> *const *const T {
+        self.ptrs.as_ptr().cast()
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut *mut T {
+        self.ptrs.as_mut_ptr()
+    }
+}
+
+impl RxDemux {
+    /// Drive an arbitrary [`RxStreamer`] from a background thread, buffering up
+    /// to `capacity` blocks per channel.
+    ///
+    /// Returns the demultiplexer and a receiver for each channel of the stream,
+    /// in the stream's channel order.
+    ///
+    /// The stream must already have been started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn spawn<T, S>(stream: S, capacity: usize) -> (Self, Vec<RxChannelReceiver<T>>)
+    where
+        T: Sample + Clone + Default + Send + 'static,
+        S: RxStreamer<T> + Send + 'static,
+    {
+        assert!(capacity > 0, "capacity must be non-zero");
+        let channels = stream.channels();
+        let samples = stream.max_samples_per_channel();
+        let shared = Arc::new(Shared {
+            stop: AtomicBool::new(false),
+            blocks: AtomicU64::new(0),
+            samples: AtomicU64::new(0),
+            overflows: AtomicU64::new(0),
+            dropped_blocks: (0..channels).map(|_| AtomicU64::new(0)).collect(),
+            dropped_samples: (0..channels).map(|_| AtomicU64::new(0)).collect(),
+            error: Mutex::new(None),
+        });
+        let (producers, receivers): (Vec<_>, Vec<_>) = (0..channels)
+            .map(|channel| {
+                let (producer, consumer) = ring((0..capacity).map(|_| Block {
+                    buff: vec![T::default(); samples],
+                    samples: 0,
+                    offset: 0,
+                    metadata: RxMetadata::new(),
+                }));
+                let receiver = RxChannelReceiver {
+                    channel,
+                    consumer,
+                    shared: shared.clone(),
+                };
+                (producer, receiver)
+            })
+            .unzip();
+        let thread = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("uhd-rx-demux".to_string())
+                .spawn(move || demux_loop(stream, producers, &shared))
+                .expect("failed to spawn demultiplexer thread")
+        };
+        let demux = Self {
+            shared,
+            thread: Some(thread),
+        };
+        (demux, receivers)
+    }
+
+    /// Get a snapshot of the demultiplexer's counters.
+    pub fn stats(&self) -> RxDemuxStats {
+        let load =
+            |counters: &[AtomicU64]| counters.iter().map(|c| c.load(Ordering::Relaxed)).collect();
+        RxDemuxStats {
+            blocks: self.shared.blocks.load(Ordering::Relaxed),
+            samples: self.shared.samples.load(Ordering::Relaxed),
+            overflows: self.shared.overflows.load(Ordering::Relaxed),
+            dropped_blocks: load(&self.shared.dropped_blocks),
+            dropped_samples: load(&self.shared.dropped_samples),
+        }
+    }
+}
+
+impl Drop for RxDemux {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<T: Sample> RxChannelReceiver<T> {
+    /// The index of the channel within the stream.
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Get the oldest block received on the channel, if one is available.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error which stopped the demultiplexer's thread once all
+    /// blocks received before it have been read.
+    pub fn try_recv(&mut self) -> Result<Option<RxChannelBlock<'_, T>>> {
+        if self.consumer.peek().is_some() {
+            return Ok(Some(RxChannelBlock {
+                consumer: &mut self.consumer,
+            }));
+        }
+        match self.shared.error.lock().unwrap().clone() {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    /// Wait up to `timeout` for a block to be received on the channel.
+    ///
+    /// # Errors
+    ///
+    /// See [`RxChannelReceiver::try_recv`].
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<RxChannelBlock<'_, T>>> {
+        let start = Instant::now();
+        let mut spins = 0;
+        while self.consumer.peek().is_none() && start.elapsed() < timeout {
+            if self.shared.error.lock().unwrap().is_some() {
+                break;
+            }
+            match spins < SPIN_LIMIT {
+                true => {
+                    spins += 1;
+                    std::thread::yield_now();
+                }
+                false => std::thread::sleep(SLEEP_INTERVAL),
+            }
+        }
+        self.try_recv()
+    }
+
+    /// Number of received blocks waiting to be read.
+    pub fn pending(&self) -> usize {
+        self.consumer.len()
+    }
+
+    /// The number of blocks the receiver can hold.
+    pub fn capacity(&self) -> usize {
+        self.consumer.capacity()
+    }
+}
+
+impl<'a, T: Sample> RxChannelBlock<'a, T> {
+    fn block(&self) -> &Block<T> {
+        // The guard is only created for a committed block, which it holds until dropped.
+        self.consumer.peek().unwrap()
+    }
+
+    /// The number of samples in the block.
+    pub fn samples(&self) -> usize {
+        self.block().samples
+    }
+
+    /// The samples received on the channel.
+    pub fn as_slice(&self) -> &[T] {
+        let block = self.block();
+        &block.buff[..block.samples]
+    }
+
+    /// The number of samples per channel received from the stream before this block.
+    ///
+    /// Blocks of different channels filled by the same receive have the same offset.
+    /// Samples lost to overflows aren't counted; use the metadata's time for that.
+    pub fn offset(&self) -> u64 {
+        self.block().offset
+    }
+
+    /// The metadata of the receive which filled the block.
+    pub fn metadata(&self) -> &RxMetadata {
+        &self.block().metadata
+    }
+}
+
+impl<'a, T: Sample> Drop for RxChannelBlock<'a, T> {
+    fn drop(&mut self) {
+        self.consumer.release();
+    }
+}
+
+impl<T> RxStream<T>
+where
+    T: Sample + Clone + Default + Send + 'static,
+{
+    /// Move the stream onto a dedicated thread which splits it into per-channel
+    /// queues of `capacity` preallocated blocks.
+    ///
+    /// The stream must already have been started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use num_complex::Complex32;
+    /// use uhd_usrp::Usrp;
+    ///
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let stream = usrp.rx_stream::<Complex32>().with_channels(&[0, 1]).open().unwrap();
+    /// stream.start_command().send().unwrap();
+    ///
+    /// let (demux, receivers) = stream.spawn_demux(64);
+    /// let workers: Vec<_> = receivers
+    ///     .into_iter()
+    ///     .map(|mut receiver| {
+    ///         std::thread::spawn(move || {
+    ///             while let Some(block) = receiver.recv_timeout(Duration::from_secs(1)).unwrap() {
+    ///                 let samples = block.as_slice();
+    ///                 // process channel `receiver.channel()` in place...
+    ///             }
+    ///         })
+    ///     })
+    ///     .collect();
+    /// for worker in workers {
+    ///     worker.join().unwrap();
+    /// }
+    /// println!("{:?}", demux.stats());
+    /// ```
+    pub fn spawn_demux(self, capacity: usize) -> (RxDemux, Vec<RxChannelReceiver<T>>) {
+        RxDemux::spawn(self, capacity)
+    }
+}
+
+fn demux_loop<T, S>(mut stream: S, mut producers: Vec<RingProducer<Block<T>>>, shared: &Shared)
+where
+    T: Sample + Clone + Default,
+    S: RxStreamer<T>,
+{
+    let samples = stream.max_samples_per_channel();
+    let mut scratch = ArrayBuffer::new(stream.channels(), samples);
+    let mut offset = 0;
+    while !shared.stop.load(Ordering::Relaxed) && !producers.iter().all(|p| p.is_disconnected()) {
+        // Receive straight into each channel's next slot, or into scratch
+        // space for channels whose queue is full.
+        let mut dropped = vec![false; producers.len()];
+        let ptrs = producers
+            .iter_mut()
+            .zip(scratch.iter_channels_mut())
+            .zip(&mut dropped)
+            .map(|((producer, scratch), dropped)| match producer.slot() {
+                Some(block) => block.buff.as_mut_ptr(),
+                None => {
+                    *dropped = true;
+                    scratch.as_mut_ptr()
+                }
+            })
+            .collect();
+        let mut buff = ChannelPtrs { ptrs, samples };
+        let mut md = RxMetadata::new();
+        let received = match stream.recv(&mut buff, RECV_TIMEOUT, &mut md) {
+            Ok(received) => received,
+            Err(e) => {
+                *shared.error.lock().unwrap() = Some(e);
+                break;
+            }
+        };
+        match md.error_code {
+            Some(RxErrorCode::Timeout) => continue,
+            Some(RxErrorCode::Overflow) => {
+                shared.overflows.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+        // Update the counters before committing, so that they are up to date
+        // by the time a consumer sees the block.
+        shared.blocks.fetch_add(1, Ordering::Relaxed);
+        shared.samples.fetch_add(received as u64, Ordering::Relaxed);
+        for (c, _) in dropped.iter().enumerate().filter(|(_, &d)| d) {
+            shared.dropped_blocks[c].fetch_add(1, Ordering::Relaxed);
+            shared.dropped_samples[c].fetch_add(received as u64, Ordering::Relaxed);
+        }
+        for (producer, _) in producers.iter_mut().zip(&dropped).filter(|(_, &d)| !d) {
+            let block = producer.slot().unwrap();
+            block.samples = received;
+            block.offset = offset;
+            block.metadata = md;
+            producer.commit();
+        }
+        offset += received as u64;
+    }
+    let _ = stream.issue_stream_cmd(StreamCommand::StopContinuous);
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::{sim::SimDevice, Channel, Device, StreamDevice, TimeSpec};
+
+    fn demux(
+        capacity: usize,
+        cmd: StreamCommand,
+    ) -> (SimDevice, RxDemux, Vec<RxChannelReceiver<[f32; 2]>>) {
+        let device = SimDevice::new(2, 0);
+        device.set_sample_rate(Channel::Rx(0), 1e6).unwrap();
+        let mut stream = device.open_rx_stream(&[0, 1]).unwrap();
+        stream.issue_stream_cmd(cmd).unwrap();
+        let (demux, receivers) = RxDemux::spawn(stream, capacity);
+        (device, demux, receivers)
+    }
+
+    #[test]
+    fn channels_are_consumed_independently() {
+        let cmd = StreamCommand::NumSamples {
+            samples: 5000,
+            done: true,
+            time: None,
+        };
+        let (_device, demux, receivers) = demux(4, cmd);
+        let workers: Vec<_> = receivers
+            .into_iter()
+            .map(|mut receiver| {
+                std::thread::spawn(move || {
+                    let mut blocks = Vec::new();
+                    while blocks.len() < 3 {
+                        let block = receiver
+                            .recv_timeout(Duration::from_secs(1))
+                            .unwrap()
+                            .unwrap();
+                        assert_eq!(block.as_slice().len(), block.samples());
+                        let time = block.metadata().time_spec().unwrap();
+                        blocks.push((block.offset(), block.samples(), time));
+                    }
+                    (receiver.channel(), blocks)
+                })
+            })
+            .collect();
+        let results: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(results[0].0, 0);
+        assert_eq!(results[1].0, 1);
+        assert_eq!(results[0].1, results[1].1);
+        for (offset, _, time) in &results[0].1 {
+            assert_eq!(*time, TimeSpec::from_secs_f64(*offset as f64 / 1e6));
+        }
+        let lengths: Vec<_> = results[0].1.iter().map(|b| b.1).collect();
+        assert_eq!(lengths, [2000, 2000, 1000]);
+        let stats = demux.stats();
+        assert_eq!((stats.blocks, stats.samples), (3, 5000));
+        assert_eq!(stats.dropped_blocks, [0, 0]);
+    }
+
+    #[test]
+    fn full_queue_drops_only_its_channel() {
+        let (tx, stream) = ScriptStream::new();
+        let (demux, mut receivers) = RxDemux::spawn(stream, 2);
+        let mut rx1 = receivers.pop().unwrap();
+        let mut rx0 = receivers.pop().unwrap();
+
+        // Channel 0 is read after every receive, channel 1 is left to fill up.
+        let mut offsets = Vec::new();
+        for _ in 0..4 {
+            tx.send(Ok(())).unwrap();
+            let block = rx0.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+            offsets.push(block.offset());
+        }
+        assert_eq!(offsets, [0, 10, 20, 30]);
+        let stats = demux.stats();
+        assert_eq!(stats.blocks, 4);
+        assert_eq!(stats.dropped_blocks, [0, 2]);
+        assert_eq!(stats.dropped_samples, [0, 20]);
+
+        assert_eq!(rx1.pending(), 2);
+        assert_eq!(rx1.try_recv().unwrap().unwrap().offset(), 0);
+        assert_eq!(rx1.try_recv().unwrap().unwrap().offset(), 10);
+        assert!(rx1.try_recv().unwrap().is_none());
+    }
+
+    #[test]
+    fn errors_are_reported_to_every_channel() {
+        let (tx, stream) = ScriptStream::new();
+        let (_demux, mut receivers) = RxDemux::spawn(stream, 2);
+        tx.send(Ok(())).unwrap();
+        tx.send(Err(UhdError::Io)).unwrap();
+        for receiver in &mut receivers {
+            let channel = receiver.channel() as f32;
+            let block = receiver
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap()
+                .unwrap();
+            assert_eq!(block.samples(), 10);
+            assert!(block.as_slice().iter().all(|&s| s == [channel, 0.0]));
+            drop(block);
+            assert!(matches!(
+                receiver.recv_timeout(Duration::from_secs(1)),
+                Err(UhdError::Io)
+            ));
+        }
+    }
+
+    /// A two-channel stream which delivers a block, filling each channel with
+    /// its index, or fails for each message sent to it.
+    struct ScriptStream {
+        script: mpsc::Receiver<Result<()>>,
+    }
+
+    impl ScriptStream {
+        fn new() -> (mpsc::Sender<Result<()>>, Self) {
+            let (tx, script) = mpsc::channel();
+            (tx, Self { script })
+        }
+    }
+
+    impl RxStreamer<[f32; 2]> for ScriptStream {
+        fn channels(&self) -> usize {
+            2
+        }
+
+        fn max_samples_per_channel(&self) -> usize {
+            10
+        }
+
+        fn issue_stream_cmd(&mut self, _cmd: StreamCommand) -> Result<()> {
+            Ok(())
+        }
+
+        fn recv(
+            &mut self,
+            buff: &mut impl SampleBuffer<[f32; 2]>,
+            timeout: Duration,
+            metadata: &mut RxMetadata,
+        ) -> Result<usize> {
+            *metadata = RxMetadata::new();
+            match self.script.recv_timeout(timeout) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    metadata.error_code = Some(RxErrorCode::Timeout);
+                    return Ok(0);
+                }
+            }
+            let ptrs = buff.as_mut_ptr();
+            for c in 0..buff.channels() {
+                // Safety: the buffer has `channels` channels of `samples` samples.
+                let out = unsafe { std::slice::from_raw_parts_mut(*ptrs.add(c), buff.samples()) };
+                out.fill([c as f32, 0.0]);
+            }
+            Ok(buff.samples())
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{types::RxErrorCode, RxMetadata, UhdError};
+
+/// An error condition reported while receiving samples.
+///
+/// Conditions reported by the device carry the metadata of the failed receive.
+#[derive(thiserror::Error, Clone, Debug)]
+pub enum RxStreamError {
+    #[error("timed out waiting for samples")]
+    Timeout(RxMetadata),
+    #[error("stream command was issued too late")]
+    LateCommand(RxMetadata),
+    #[error("broken chain: expected another stream command")]
+    BrokenChain(RxMetadata),
+    #[error("samples were dropped due to an overflow")]
+    Overflow(RxMetadata),
+    #[error("multi-channel alignment failed")]
+    Alignment(RxMetadata),
+    #[error("received a malformed packet")]
+    BadPacket(RxMetadata),
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+/// What to do when a receive reports an error condition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxErrorAction {
+    /// Return an [`RxStreamError`].
+    Fail,
+    /// Return the number of samples received, as if no error occurred.
+    Ignore,
+    /// Receive again, up to the given number of times, before failing.
+    Retry(usize),
+}
+
+/// Determines which error conditions fail a checked receive.
+///
+/// The default policy fails on every error condition. Alignment errors and
+/// bad packets always fail.
+///
+/// # Examples
+///
+/// ```
+/// use uhd_usrp::stream::{RxErrorAction, RxErrorPolicy};
+///
+/// let policy = RxErrorPolicy::new()
+///     .on_overflow(RxErrorAction::Ignore)
+///     .on_timeout(RxErrorAction::Retry(3));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RxErrorPolicy {
+    timeout: RxErrorAction,
+    late_command: RxErrorAction,
+    broken_chain: RxErrorAction,
+    overflow: RxErrorAction,
+}
+
+impl RxErrorPolicy {
+    pub fn new() -> Self {
+        Self {
+            timeout: RxErrorAction::Fail,
+            late_command: RxErrorAction::Fail,
+            broken_chain: RxErrorAction::Fail,
+            overflow: RxErrorAction::Fail,
+        }
+    }
+
+    pub fn on_timeout(mut self, action: RxErrorAction) -> Self {
+        self.timeout = action;
+        self
+    }
+
+    pub fn on_late_command(mut self, action: RxErrorAction) -> Self {
+        self.late_command = action;
+        self
+    }
+
+    pub fn on_broken_chain(mut self, action: RxErrorAction) -> Self {
+        self.broken_chain = action;
+        self
+    }
+
+    pub fn on_overflow(mut self, action: RxErrorAction) -> Self {
+        self.overflow = action;
+        self
+    }
+
+    /// Decide how to proceed after a receive which has already been retried
+    /// `retries` times.
+    ///
+    /// Returns `Ok(true)` if the receive should be retried, and `Ok(false)` if
+    /// its result should be returned.
+    pub(crate) fn check(&self, md: &RxMetadata, retries: usize) -> Result<bool, RxStreamError> {
+        let (action, error) = match md.error_code()? {
+            RxErrorCode::None => return Ok(false),
+            RxErrorCode::Timeout => (self.timeout, RxStreamError::Timeout(*md)),
+            RxErrorCode::LateCommand => (self.late_command, RxStreamError::LateCommand(*md)),
+            RxErrorCode::BrokenChain => (self.broken_chain, RxStreamError::BrokenChain(*md)),
+            RxErrorCode::Overflow => (self.overflow, RxStreamError::Overflow(*md)),
+            RxErrorCode::Alignment => (RxErrorAction::Fail, RxStreamError::Alignment(*md)),
+            RxErrorCode::BadPacket => (RxErrorAction::Fail, RxStreamError::BadPacket(*md)),
+        };
+        match action {
+            RxErrorAction::Ignore => Ok(false),
+            RxErrorAction::Retry(n) if retries < n => Ok(true),
+            RxErrorAction::Retry(_) | RxErrorAction::Fail => Err(error),
+        }
+    }
+}
+
+impl Default for RxErrorPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RxStreamError {
+    /// The metadata of the receive that failed, if the error was reported by the device.
+    pub fn metadata(&self) -> Option<&RxMetadata> {
+        match self {
+            RxStreamError::Timeout(md)
+            | RxStreamError::LateCommand(md)
+            | RxStreamError::BrokenChain(md)
+            | RxStreamError::Overflow(md)
+            | RxStreamError::Alignment(md)
+            | RxStreamError::BadPacket(md) => Some(md),
+            RxStreamError::Uhd(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn md(error_code: RxErrorCode) -> RxMetadata {
+        RxMetadata {
+            error_code: Some(error_code),
+            ..RxMetadata::new()
+        }
+    }
+
+    #[test]
+    fn default_policy_fails() {
+        let policy = RxErrorPolicy::default();
+        assert!(!policy.check(&md(RxErrorCode::None), 0).unwrap());
+        assert!(matches!(
+            policy.check(&md(RxErrorCode::Overflow), 0),
+            Err(RxStreamError::Overflow(_))
+        ));
+        assert!(matches!(
+            policy.check(&md(RxErrorCode::Timeout), 0),
+            Err(RxStreamError::Timeout(_))
+        ));
+        assert!(matches!(
+            policy.check(&md(RxErrorCode::LateCommand), 0),
+            Err(RxStreamError::LateCommand(_))
+        ));
+        assert!(matches!(
+            policy.check(
+                &RxMetadata {
+                    error_code: None,
+                    ..RxMetadata::new()
+                },
+                0
+            ),
+            Err(RxStreamError::Uhd(UhdError::Unknown))
+        ));
+    }
+
+    #[test]
+    fn ignore_and_retry() {
+        let policy = RxErrorPolicy::new()
+            .on_overflow(RxErrorAction::Ignore)
+            .on_timeout(RxErrorAction::Retry(2));
+        assert!(!policy.check(&md(RxErrorCode::Overflow), 0).unwrap());
+        assert!(policy.check(&md(RxErrorCode::Timeout), 0).unwrap());
+        assert!(policy.check(&md(RxErrorCode::Timeout), 1).unwrap());
+        let err = policy.check(&md(RxErrorCode::Timeout), 2).unwrap_err();
+        assert_eq!(err.metadata(), Some(&md(RxErrorCode::Timeout)));
+        assert!(matches!(
+            policy.check(&md(RxErrorCode::Alignment), 0),
+            Err(RxStreamError::Alignment(_))
+        ));
+    }
+}
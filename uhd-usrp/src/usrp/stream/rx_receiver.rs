@@ -0,0 +1,513 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::{RxStream, StreamCommand};
+use crate::{
+    buffer::{ring, RingConsumer, RingProducer},
+    types::RxErrorCode,
+    ArrayBuffer, Result, RxMetadata, RxStreamer, Sample, SampleBuffer, UhdError,
+};
+
+/// Timeout of a single receive on the background thread.
+///
+/// Bounds how long dropping an [`RxReceiver`] may block.
+pub(super) const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+/// Number of times [`RxReceiver::recv_timeout`] yields before it starts sleeping.
+pub(super) const SPIN_LIMIT: u32 = 64;
+/// How long [`RxReceiver::recv_timeout`] sleeps between checks once it stops spinning.
+pub(super) const SLEEP_INTERVAL: Duration = Duration::from_micros(50);
+
+/// A background receiver filling a ring of preallocated sample blocks.
+///
+/// The receiver's thread owns the stream and receives into blocks of
+/// [`RxStream::max_samples_per_channel`] samples per channel which are
+/// allocated up front and reused. Blocks are read in place through
+/// [`RxBlock`] guards, and returned to the ring when the guard is dropped.
+///
+/// When every block is waiting to be read, the thread keeps receiving into a
+/// scratch block and discards it, so that the device doesn't overflow. Such
+/// blocks are counted in [`RxReceiverStats::dropped_blocks`].
+///
+/// Timeouts are not reported; blocks with other error conditions (e.g.
+/// overflows) are delivered with no samples so that their metadata can be
+/// inspected.
+///
+/// The thread is named `uhd-rx-receiver` but is neither pinned to a core nor
+/// given a raised priority, since both are platform-specific. Use
+/// [`RxReceiver::spawn_with`] to run a hook on the thread before it starts
+/// receiving, e.g. to set its affinity or priority.
+///
+/// Dropping the receiver stops the thread and issues a
+/// [`StreamCommand::StopContinuous`] command.
+pub struct RxReceiver<T: Sample> {
+    consumer: RingConsumer<Block<T>>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// A block of received samples, borrowed from an [`RxReceiver`].
+///
+/// The block is returned to the receiver when the guard is dropped.
+pub struct RxBlock<'a, T: Sample> {
+    consumer: &'a mut RingConsumer<Block<T>>,
+}
+
+/// Counters describing the activity of an [`RxReceiver`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RxReceiverStats {
+    /// Number of blocks delivered to the ring.
+    pub blocks: u64,
+    /// Number of samples per channel delivered to the ring.
+    pub samples: u64,
+    /// Number of overflows reported by the device.
+    pub overflows: u64,
+    /// Number of blocks discarded because the ring was full.
+    pub dropped_blocks: u64,
+    /// Number of samples per channel discarded because the ring was full.
+    pub dropped_samples: u64,
+}
+
+/// A slot of the ring.
+struct Block<T: Sample> {
+    buff: ArrayBuffer<T>,
+    samples: usize,
+    metadata: RxMetadata,
+}
+
+#[derive(Default)]
+struct Shared {
+    stop: AtomicBool,
+    blocks: AtomicU64,
+    samples: AtomicU64,
+    overflows: AtomicU64,
+    dropped_blocks: AtomicU64,
+    dropped_samples: AtomicU64,
+    error: Mutex<Option<UhdError>>,
+}
+
+impl<T> RxReceiver<T>
+where
+    T: Sample + Clone + Default + Send + 'static,
+{
+    /// Drive an arbitrary [`RxStreamer`] from a background thread,
+    /// buffering up to `capacity` blocks.
+    ///
+    /// The stream must already have been started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn spawn<S>(stream: S, capacity: usize) -> Self
+    where
+        S: RxStreamer<T> + Send + 'static,
+    {
+        Self::spawn_with(stream, capacity, || {})
+    }
+
+    /// Like [`RxReceiver::spawn`], but calls `on_start` on the background
+    /// thread before it starts receiving.
+    ///
+    /// This is the place to pin the thread to a core or raise its priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn spawn_with<S, F>(stream: S, capacity: usize, on_start: F) -> Self
+    where
+        S: RxStreamer<T> + Send + 'static,
+        F: FnOnce() + Send + 'static,
+    {
+        assert!(capacity > 0, "capacity must be non-zero");
+        let channels = stream.channels();
+        let samples = stream.max_samples_per_channel();
+        let (producer, consumer) = ring((0..capacity).map(|_| Block {
+            buff: ArrayBuffer::new(channels, samples),
+            samples: 0,
+            metadata: RxMetadata::new(),
+        }));
+        let shared = Arc::new(Shared::default());
+        let thread = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("uhd-rx-receiver".to_string())
+                .spawn(move || {
+                    on_start();
+                    receive_loop(stream, producer, &shared)
+                })
+                .expect("failed to spawn receiver thread")
+        };
+        Self {
+            consumer,
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<T: Sample> RxReceiver<T> {
+    /// Get the oldest received block, if one is available.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error which stopped the receiver's thread once all blocks
+    /// received before it have been read.
+    pub fn try_recv(&mut self) -> Result<Option<RxBlock<'_, T>>> {
+        if self.consumer.peek().is_some() {
+            return Ok(Some(RxBlock {
+                consumer: &mut self.consumer,
+            }));
+        }
+        match self.shared.error.lock().unwrap().clone() {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    /// Wait up to `timeout` for a block to be received.
+    ///
+    /// # Errors
+    ///
+    /// See [`RxReceiver::try_recv`].
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<RxBlock<'_, T>>> {
+        let start = Instant::now();
+        let mut spins = 0;
+        // Polled rather than notified so the receive thread never has to wake anyone.
+        while self.consumer.peek().is_none() && start.elapsed() < timeout {
+            if self.shared.error.lock().unwrap().is_some() {
+                break;
+            }
+            match spins < SPIN_LIMIT {
+                true => {
+                    spins += 1;
+                    std::thread::yield_now();
+                }
+                false => std::thread::sleep(SLEEP_INTERVAL),
+            }
+        }
+        self.try_recv()
+    }
+
+    /// Number of received blocks waiting to be read.
+    pub fn pending(&self) -> usize {
+        self.consumer.len()
+    }
+
+    /// The number of blocks the receiver can hold.
+    pub fn capacity(&self) -> usize {
+        self.consumer.capacity()
+    }
+
+    /// Get a snapshot of the receiver's counters.
+    pub fn stats(&self) -> RxReceiverStats {
+        RxReceiverStats {
+            blocks: self.shared.blocks.load(Ordering::Relaxed),
+            samples: self.shared.samples.load(Ordering::Relaxed),
+            overflows: self.shared.overflows.load(Ordering::Relaxed),
+            dropped_blocks: self.shared.dropped_blocks.load(Ordering::Relaxed),
+            dropped_samples: self.shared.dropped_samples.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T: Sample> Drop for RxReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<'a, T: Sample> RxBlock<'a, T> {
+    fn block(&self) -> &Block<T> {
+        // The guard is only created for a committed block, which it holds until dropped.
+        self.consumer.peek().unwrap()
+    }
+
+    /// The number of channels in the block.
+    pub fn channels(&self) -> usize {
+        self.block().buff.channels()
+    }
+
+    /// The number of samples per channel in the block.
+    pub fn samples(&self) -> usize {
+        self.block().samples
+    }
+
+    /// Returns the samples received on the given channel.
+    ///
+    /// `None` is returned if the channel is out of bounds.
+    pub fn channel(&self, channel: usize) -> Option<&[T]> {
+        let block = self.block();
+        block.buff.channel(channel).map(|c| &c[..block.samples])
+    }
+
+    /// Returns an iterator over the samples received on each channel.
+    pub fn iter_channels(&self) -> impl Iterator<Item = &[T]> {
+        let block = self.block();
+        block.buff.iter_channels().map(|c| &c[..block.samples])
+    }
+
+    /// The metadata of the receive which filled the block.
+    pub fn metadata(&self) -> &RxMetadata {
+        &self.block().metadata
+    }
+}
+
+impl<'a, T: Sample> Drop for RxBlock<'a, T> {
+    fn drop(&mut self) {
+        self.consumer.release();
+    }
+}
+
+impl<T> RxStream<T>
+where
+    T: Sample + Clone + Default + Send + 'static,
+{
+    /// Move the stream onto a dedicated thread which receives into a ring of
+    /// `capacity` preallocated blocks.
+    ///
+    /// The stream must already have been started. The thread is not pinned
+    /// or prioritised; see [`RxStream::spawn_receiver_with`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use num_complex::Complex32;
+    /// use uhd_usrp::Usrp;
+    ///
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let stream = usrp.rx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+    /// stream.start_command().send().unwrap();
+    ///
+    /// let mut receiver = stream.spawn_receiver(64);
+    /// while let Some(block) = receiver.recv_timeout(Duration::from_secs(1)).unwrap() {
+    ///     let samples = block.channel(0).unwrap();
+    ///     // process the samples in place...
+    /// }
+    /// println!("{:?}", receiver.stats());
+    /// ```
+    pub fn spawn_receiver(self, capacity: usize) -> RxReceiver<T> {
+        RxReceiver::spawn(self, capacity)
+    }
+
+    /// Like [`RxStream::spawn_receiver`], but calls `on_start` on the
+    /// receiver's thread before it starts receiving.
+    ///
+    /// The thread is not pinned or prioritised by default; `on_start` can do
+    /// either.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use num_complex::Complex32;
+    /// use uhd_usrp::Usrp;
+    ///
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let stream = usrp.rx_stream::<Complex32>().with_channels(&[0]).open().unwrap();
+    /// stream.start_command().send().unwrap();
+    ///
+    /// let receiver = stream.spawn_receiver_with(64, || {
+    ///     // set the thread's affinity or priority here
+    /// });
+    /// ```
+    pub fn spawn_receiver_with<F>(self, capacity: usize, on_start: F) -> RxReceiver<T>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        RxReceiver::spawn_with(self, capacity, on_start)
+    }
+}
+
+fn receive_loop<T, S>(mut stream: S, mut producer: RingProducer<Block<T>>, shared: &Shared)
+where
+    T: Sample + Clone + Default,
+    S: RxStreamer<T>,
+{
+    let mut scratch = ArrayBuffer::new(stream.channels(), stream.max_samples_per_channel());
+    while !shared.stop.load(Ordering::Relaxed) && !producer.is_disconnected() {
+        let mut md = RxMetadata::new();
+        let (result, dropped) = match producer.slot() {
+            Some(block) => (stream.recv(&mut block.buff, RECV_TIMEOUT, &mut md), false),
+            None => (stream.recv(&mut scratch, RECV_TIMEOUT, &mut md), true),
+        };
+        let received = match result {
+            Ok(received) => received,
+            Err(e) => {
+                *shared.error.lock().unwrap() = Some(e);
+                break;
+            }
+        };
+        match md.error_code {
+            Some(RxErrorCode::Timeout) => continue,
+            Some(RxErrorCode::Overflow) => {
+                shared.overflows.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+        if dropped {
+            shared.dropped_blocks.fetch_add(1, Ordering::Relaxed);
+            shared
+                .dropped_samples
+                .fetch_add(received as u64, Ordering::Relaxed);
+            continue;
+        }
+        let block = producer.slot().unwrap();
+        block.samples = received;
+        block.metadata = md;
+        // Count the block before the consumer can see it.
+        shared.blocks.fetch_add(1, Ordering::Relaxed);
+        shared.samples.fetch_add(received as u64, Ordering::Relaxed);
+        producer.commit();
+    }
+    let _ = stream.issue_stream_cmd(StreamCommand::StopContinuous);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{sim::SimDevice, Channel, Device, StreamDevice, TimeSpec};
+
+    fn receiver(capacity: usize, cmd: StreamCommand) -> (SimDevice, RxReceiver<[f32; 2]>) {
+        let device = SimDevice::new(2, 0);
+        device.set_sample_rate(Channel::Rx(0), 1e6).unwrap();
+        let mut stream = device.open_rx_stream(&[0, 1]).unwrap();
+        stream.issue_stream_cmd(cmd).unwrap();
+        (device, RxReceiver::spawn(stream, capacity))
+    }
+
+    fn wait_until(mut done: impl FnMut() -> bool) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(std::time::Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn blocks_are_read_in_place() {
+        let cmd = StreamCommand::NumSamples {
+            samples: 5000,
+            done: true,
+            time: None,
+        };
+        let (_device, mut receiver) = receiver(4, cmd);
+        let mut lengths = Vec::new();
+        while let Some(block) = receiver.recv_timeout(Duration::from_secs(1)).unwrap() {
+            assert_eq!(block.channels(), 2);
+            assert!(block.iter_channels().all(|c| c.len() == block.samples()));
+            let offset = lengths.iter().sum::<usize>() as f64 / 1e6;
+            assert_eq!(
+                block.metadata().time_spec(),
+                Some(TimeSpec::from_secs_f64(offset))
+            );
+            lengths.push(block.samples());
+            if lengths.len() == 3 {
+                break;
+            }
+        }
+        assert_eq!(lengths, [2000, 2000, 1000]);
+        assert_eq!(receiver.pending(), 0);
+        let stats = receiver.stats();
+        assert_eq!(stats.blocks, 3);
+        assert_eq!(stats.samples, 5000);
+        assert_eq!(stats.dropped_blocks, 0);
+    }
+
+    #[test]
+    fn full_ring_drops_blocks() {
+        let (_device, mut receiver) = receiver(2, StreamCommand::StartContinuous { time: None });
+        wait_until(|| receiver.stats().dropped_blocks > 0);
+        assert_eq!(receiver.pending(), 2);
+        let stats = receiver.stats();
+        assert_eq!(stats.blocks, 2);
+        assert!(stats.dropped_blocks > 0);
+        assert_eq!(stats.dropped_samples, stats.dropped_blocks * 2000);
+
+        // The oldest blocks are kept; the next one starts after a gap.
+        let first = receiver.try_recv().unwrap().unwrap().metadata().time_spec();
+        assert_eq!(first, Some(TimeSpec::ZERO));
+        receiver.try_recv().unwrap().unwrap();
+        let next = receiver
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+            .unwrap();
+        assert!(next.metadata().time_spec().unwrap() > TimeSpec::from_secs_f64(4000.0 / 1e6));
+    }
+
+    #[test]
+    fn errors_are_reported_after_pending_blocks() {
+        let stream = ErrStream { delivered: false };
+        let mut receiver = RxReceiver::spawn(stream, 2);
+        // Let the thread fail after delivering the block.
+        wait_until(|| receiver.shared.error.lock().unwrap().is_some());
+        let block = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(block.samples(), 10);
+        drop(block);
+        assert!(matches!(
+            receiver.recv_timeout(Duration::from_secs(1)),
+            Err(UhdError::Io)
+        ));
+    }
+
+    #[test]
+    fn start_hook_runs_on_receiver_thread() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let stream = ErrStream { delivered: false };
+        let mut receiver = RxReceiver::spawn_with(stream, 2, move || {
+            let name = std::thread::current().name().map(str::to_string);
+            tx.send(name).unwrap();
+        });
+        let name = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(name.as_deref(), Some("uhd-rx-receiver"));
+        let block = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(block.unwrap().samples(), 10);
+    }
+
+    /// Delivers a single block, then fails.
+    struct ErrStream {
+        delivered: bool,
+    }
+
+    impl RxStreamer<[f32; 2]> for ErrStream {
+        fn channels(&self) -> usize {
+            1
+        }
+
+        fn max_samples_per_channel(&self) -> usize {
+            10
+        }
+
+        fn issue_stream_cmd(&mut self, _cmd: StreamCommand) -> Result<()> {
+            Ok(())
+        }
+
+        fn recv(
+            &mut self,
+            buff: &mut impl SampleBuffer<[f32; 2]>,
+            _timeout: Duration,
+            metadata: &mut RxMetadata,
+        ) -> Result<usize> {
+            *metadata = RxMetadata::new();
+            match std::mem::replace(&mut self.delivered, true) {
+                false => Ok(buff.samples()),
+                true => Err(UhdError::Io),
+            }
+        }
+    }
+}
@@ -7,7 +7,7 @@ use std::{
     time::Duration,
 };
 
-use super::OtwFormat;
+use super::{OtwFormat, RxErrorPolicy, RxStreamError, StreamCommand};
 use crate::{
     buffer::SampleBuffer, error::try_uhd, ffi::OwnedHandle, types::RxMetadata, usrp::Usrp, Result,
     Sample, TimeSpec, UhdError,
@@ -20,6 +20,7 @@ where
     T: Sample,
 {
     handle: RxStreamHandle,
+    metadata: OwnedHandle<uhd_usrp_sys::uhd_rx_metadata_t>,
     samples_per_buffer: usize,
     channels: usize,
 
@@ -45,6 +46,7 @@ where
     timeout: Option<Duration>,
     one_packet: bool,
     metadata: Option<&'md mut RxMetadata>,
+    error_policy: RxErrorPolicy,
 }
 
 pub struct RxStartCommand<'stream, T>
@@ -70,8 +72,14 @@ where
             uhd_usrp_sys::uhd_rx_streamer_num_channels(handle.as_mut_ptr(), addr_of_mut!(channels))
         })?;
 
+        let metadata = OwnedHandle::new(
+            uhd_usrp_sys::uhd_rx_metadata_make,
+            uhd_usrp_sys::uhd_rx_metadata_free,
+        )?;
+
         Ok(Self {
             handle,
+            metadata,
             samples_per_buffer: spb,
             channels,
             _unsync: PhantomData::default(),
@@ -95,17 +103,21 @@ where
         RxStartCommand::new(self)
     }
 
-    pub fn stop_now(&self) -> Result<()> {
-        let cmd = uhd_usrp_sys::uhd_stream_cmd_t {
-            stream_mode: uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_STOP_CONTINUOUS,
-            ..Default::default()
-        };
+    /// Issue a stream command to the device.
+    ///
+    /// See also [`RxStream::start_command`] for a builder-style interface.
+    pub fn issue_stream_cmd(&self, cmd: StreamCommand) -> Result<()> {
+        let cmd = cmd.to_sys();
         try_uhd!(unsafe {
             uhd_usrp_sys::uhd_rx_streamer_issue_stream_cmd(self.handle.as_mut_ptr(), addr_of!(cmd))
         })?;
         Ok(())
     }
 
+    pub fn stop_now(&self) -> Result<()> {
+        self.issue_stream_cmd(StreamCommand::StopContinuous)
+    }
+
     pub fn reader(&mut self) -> RxStreamReader<T> {
         RxStreamReader::new(self)
     }
@@ -227,34 +239,19 @@ where
     }
 
     pub fn send(&self) -> Result<()> {
-        let cmd = uhd_usrp_sys::uhd_stream_cmd_t {
-            stream_mode: self.stream_mode(),
-            num_samps: self.n_samples(),
-            stream_now: !self.at_time.is_zero(),
-            time_spec_full_secs: self.at_time.full_secs(),
-            time_spec_frac_secs: self.at_time.frac_secs(),
-        };
-        try_uhd!(unsafe {
-            uhd_usrp_sys::uhd_rx_streamer_issue_stream_cmd(
-                self.stream.handle().as_mut_ptr(),
-                addr_of!(cmd),
-            )
-        })?;
-        Ok(())
+        self.stream.issue_stream_cmd(self.to_command())
     }
 
-    fn n_samples(&self) -> usize {
+    /// The stream command this builder describes.
+    pub fn to_command(&self) -> StreamCommand {
+        let time = (!self.at_time.is_zero()).then(|| self.at_time);
         match self.limit {
-            Some((n_samples, _)) => n_samples,
-            None => usize::MAX,
-        }
-    }
-
-    fn stream_mode(&self) -> uhd_usrp_sys::uhd_stream_mode_t::Type {
-        match self.limit {
-            Some((_, true)) => uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_NUM_SAMPS_AND_DONE,
-            Some((_, false)) => uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_NUM_SAMPS_AND_MORE,
-            None => uhd_usrp_sys::uhd_stream_mode_t::UHD_STREAM_MODE_START_CONTINUOUS,
+            Some((samples, done)) => StreamCommand::NumSamples {
+                samples,
+                done,
+                time,
+            },
+            None => StreamCommand::StartContinuous { time },
         }
     }
 }
@@ -269,9 +266,14 @@ where
             timeout: None,
             one_packet: false,
             metadata: None,
+            error_policy: RxErrorPolicy::new(),
         }
     }
 
+    pub(crate) fn stream(&self) -> &RxStream<T> {
+        self.stream
+    }
+
     pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.timeout = Some(timeout);
         self
@@ -287,6 +289,18 @@ where
         self
     }
 
+    /// Specify which error conditions fail [`RxStreamReader::recv_checked`].
+    ///
+    /// Defaults to failing on every error condition.
+    pub fn with_error_policy(&mut self, policy: RxErrorPolicy) -> &mut Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Receive samples, returning the number of samples received per channel.
+    ///
+    /// Error conditions reported by the device are only available through the
+    /// metadata. See [`RxStreamReader::recv_checked`] for a strict alternative.
     pub fn recv(&mut self, buff: &mut impl SampleBuffer<T>) -> Result<usize> {
         if buff.channels() != self.stream.channels()
             || buff.samples() != self.stream.samples_per_buffer
@@ -296,6 +310,51 @@ where
         unsafe { self.recv_unchecked(buff) }
     }
 
+    /// Receive samples, surfacing error conditions reported by the device as errors.
+    ///
+    /// Each condition is handled according to the reader's error policy,
+    /// which may ignore it or retry the receive instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`RxStreamError`] carrying the metadata of the failed receive
+    /// if the policy does not allow the condition, or if UHD fails to receive.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use num_complex::Complex32;
+    /// use uhd_usrp::{stream::{RxErrorAction, RxErrorPolicy}, ArrayBuffer, Usrp};
+    ///
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let mut rx_stream = usrp.rx_stream::<Complex32>().open().unwrap();
+    /// let mut buff = ArrayBuffer::new(1, rx_stream.max_samples_per_channel());
+    /// rx_stream.start_command().send().unwrap();
+    ///
+    /// let policy = RxErrorPolicy::new().on_timeout(RxErrorAction::Retry(3));
+    /// let samples = rx_stream
+    ///     .reader()
+    ///     .with_timeout(Duration::from_millis(100))
+    ///     .with_error_policy(policy)
+    ///     .recv_checked(&mut buff)
+    ///     .unwrap();
+    /// ```
+    pub fn recv_checked(
+        &mut self,
+        buff: &mut impl SampleBuffer<T>,
+    ) -> Result<usize, RxStreamError> {
+        let mut retries = 0;
+        loop {
+            let received = self.recv(buff)?;
+            let md = RxMetadata::from_handle(&self.stream.metadata);
+            if !self.error_policy.check(&md, retries)? {
+                return Ok(received);
+            }
+            retries += 1;
+        }
+    }
+
     pub fn recv_until<F, B>(&mut self, buff: &mut B, predicate: F) -> Result<()>
     where
         F: Fn(&mut B, Option<&RxMetadata>) -> bool,
@@ -320,20 +379,18 @@ where
         samples_per_channel: usize,
     ) -> Result<usize> {
         let mut received = 0;
-        let metadata_handle = self
-            .metadata
-            .as_ref()
-            .map(|md| md.handle().as_mut_mut_ptr())
-            .unwrap_or(std::ptr::null_mut());
         try_uhd!(uhd_usrp_sys::uhd_rx_streamer_recv(
             self.stream.handle().as_mut_ptr(),
             buff.cast(),
             samples_per_channel,
-            metadata_handle,
+            self.stream.metadata.as_mut_mut_ptr(),
             self.timeout.unwrap_or(Duration::ZERO).as_secs_f64(),
             self.one_packet,
             addr_of_mut!(received),
         ))?;
+        if let Some(md) = self.metadata.as_deref_mut() {
+            *md = RxMetadata::from_handle(&self.stream.metadata);
+        }
         Ok(received)
     }
 }
@@ -1,16 +1,41 @@
 use std::{
-    cell::Cell, collections::HashMap, ffi::CString, marker::PhantomData, ptr::addr_of_mut,
+    cell::Cell,
+    collections::HashMap,
+    ffi::CString,
+    marker::PhantomData,
+    ptr::addr_of_mut,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
     time::Duration,
 };
 
 use super::OtwFormat;
 use crate::{
-    error::try_uhd, ffi::OwnedHandle, types::TxMetadata, usrp::Usrp, Result, Sample, SampleBuffer,
+    error::try_uhd,
+    ffi::OwnedHandle,
+    types::{TxAsyncEvent, TxMetadata},
+    usrp::Usrp,
+    Result, Sample, SampleBuffer, UhdError,
 };
 
 /// An owned handle for a USRP TX stream.
 pub(crate) type TxStreamHandle = OwnedHandle<uhd_usrp_sys::uhd_tx_streamer>;
 
+/// A TX stream handle which can be shared with an event listener thread.
+struct SharedTxStreamHandle(TxStreamHandle);
+
+// UHD allows async messages to be received concurrently with sending samples.
+unsafe impl Send for SharedTxStreamHandle {}
+unsafe impl Sync for SharedTxStreamHandle {}
+
+/// How long the event listener thread waits for a message before
+/// checking whether it should stop.
+const EVENT_POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
 /// Arguments for creating a TX stream.
 pub struct TxStreamBuilder<'usrp, T>
 where
@@ -24,7 +49,7 @@ where
 }
 
 pub struct TxStream<T: Sample> {
-    handle: TxStreamHandle,
+    handle: Arc<SharedTxStreamHandle>,
     samples_per_buffer: usize,
     channels: usize,
 
@@ -142,7 +167,7 @@ impl<T: Sample> TxStream<T> {
         })?;
 
         Ok(Self {
-            handle,
+            handle: Arc::new(SharedTxStreamHandle(handle)),
             samples_per_buffer: spb,
             channels,
             _unsync: PhantomData::default(),
@@ -150,7 +175,7 @@ impl<T: Sample> TxStream<T> {
     }
 
     pub(crate) fn handle(&self) -> &TxStreamHandle {
-        &self.handle
+        &self.handle.0
     }
 
     pub fn max_samples_per_channel(&self) -> usize {
@@ -164,6 +189,182 @@ impl<T: Sample> TxStream<T> {
     pub fn writer(&mut self) -> TxStreamWriter<T> {
         TxStreamWriter::new(self)
     }
+
+    /// Wait up to `timeout` for an asynchronous message from the device.
+    ///
+    /// Messages report burst acknowledgements as well as errors such as
+    /// underflows and late packets. Returns `None` if no message arrived in time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if UHD fails to receive the message, or
+    /// [`UhdError::Unknown`](crate::UhdError::Unknown) if the message has
+    /// an unrecognized event code.
+    pub fn recv_async_msg(&self, timeout: Duration) -> Result<Option<TxAsyncEvent>> {
+        recv_async_msg(self.handle(), timeout)
+    }
+
+    /// Receive asynchronous messages on a background thread.
+    ///
+    /// This avoids having to poll [`TxStream::recv_async_msg`] from the transmit loop.
+    /// Messages are queued until they are read from the returned listener.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use num_complex::Complex32;
+    /// use uhd_usrp::{types::TxEventCode, Usrp};
+    ///
+    /// let usrp = Usrp::open_any().unwrap();
+    /// let tx_stream = usrp.tx_stream::<Complex32>().open().unwrap();
+    /// let events = tx_stream.spawn_event_listener();
+    ///
+    /// // ... transmit ...
+    ///
+    /// for event in events.try_iter() {
+    ///     if event.event_code() == TxEventCode::Underflow {
+    ///         eprintln!("underflow at {:?}", event.time_spec());
+    ///     }
+    /// }
+    /// ```
+    pub fn spawn_event_listener(&self) -> TxEventListener {
+        TxEventListener::spawn(self.handle.clone())
+    }
+}
+
+fn recv_async_msg(handle: &TxStreamHandle, timeout: Duration) -> Result<Option<TxAsyncEvent>> {
+    match recv_async_metadata(handle, timeout)? {
+        Some(md) => TxAsyncEvent::from_handle(&md).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Wait for an asynchronous message without decoding it.
+fn recv_async_metadata(
+    handle: &TxStreamHandle,
+    timeout: Duration,
+) -> Result<Option<OwnedHandle<uhd_usrp_sys::uhd_async_metadata_t>>> {
+    let md = OwnedHandle::new(
+        uhd_usrp_sys::uhd_async_metadata_make,
+        uhd_usrp_sys::uhd_async_metadata_free,
+    )?;
+    let mut valid = false;
+    try_uhd!(unsafe {
+        uhd_usrp_sys::uhd_tx_streamer_recv_async_msg(
+            handle.as_mut_ptr(),
+            md.as_mut_mut_ptr(),
+            timeout.as_secs_f64(),
+            addr_of_mut!(valid),
+        )
+    })?;
+    Ok(valid.then(|| md))
+}
+
+/// Receives TX asynchronous messages on a background thread.
+///
+/// Created by [`TxStream::spawn_event_listener`]. The listener keeps the
+/// underlying stream alive, and the thread is stopped when it is dropped.
+///
+/// Iterating over the listener blocks until the next message arrives.
+///
+/// Messages with an event code this crate doesn't recognize are skipped and
+/// counted, see [`TxEventListener::skipped`]. If receiving fails, the thread
+/// stops and the error is available from [`TxEventListener::error`].
+pub struct TxEventListener {
+    receiver: Receiver<TxAsyncEvent>,
+    shared: Arc<ListenerShared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// State shared with the listener thread.
+#[derive(Default)]
+struct ListenerShared {
+    stop: AtomicBool,
+    skipped: AtomicU64,
+    error: Mutex<Option<UhdError>>,
+}
+
+impl TxEventListener {
+    fn spawn(handle: Arc<SharedTxStreamHandle>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let shared = Arc::new(ListenerShared::default());
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                while !shared.stop.load(Ordering::Relaxed) {
+                    let md = match recv_async_metadata(&handle.0, EVENT_POLL_TIMEOUT) {
+                        Ok(Some(md)) => md,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            *shared.error.lock().unwrap() = Some(e);
+                            break;
+                        }
+                    };
+                    match TxAsyncEvent::from_handle(&md) {
+                        Ok(event) => {
+                            if sender.send(event).is_err() {
+                                break;
+                            }
+                        }
+                        Err(_) => {
+                            shared.skipped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            })
+        };
+        Self {
+            receiver,
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// The number of messages skipped because their event code was not recognized.
+    pub fn skipped(&self) -> u64 {
+        self.shared.skipped.load(Ordering::Relaxed)
+    }
+
+    /// The error which stopped the listener thread, if any.
+    pub fn error(&self) -> Option<UhdError> {
+        self.shared.error.lock().unwrap().clone()
+    }
+
+    /// Get the next message if one is available.
+    pub fn try_recv(&self) -> Option<TxAsyncEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next message.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<TxAsyncEvent> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Iterate over the messages received so far without blocking.
+    pub fn try_iter(&self) -> impl Iterator<Item = TxAsyncEvent> + '_ {
+        self.receiver.try_iter()
+    }
+}
+
+impl Iterator for TxEventListener {
+    type Item = TxAsyncEvent;
+
+    /// Block until the next message arrives.
+    ///
+    /// Returns `None` once the listener thread stopped due to an error,
+    /// see [`TxEventListener::error`].
+    fn next(&mut self) -> Option<TxAsyncEvent> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for TxEventListener {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
 }
 
 unsafe impl<T: Sample + Send> Send for TxStream<T> {}
@@ -132,6 +132,20 @@ impl TryFrom<&str> for SubdevSpec {
     }
 }
 
+/// Formats the specification as space-separated `db_name:sd_name` pairs,
+/// the same format accepted by [`SubdevSpec::from_str`].
+impl std::fmt::Display for SubdevSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, pair) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}:{}", pair.db_name(), pair.sd_name())?;
+        }
+        Ok(())
+    }
+}
+
 impl PartialEq for SubdevSpec {
     fn eq(&self, other: &Self) -> bool {
         if self.0.as_ptr() == other.0.as_ptr() {
@@ -0,0 +1,299 @@
+use std::time::{Duration, Instant};
+
+use crate::{Result, TimeSpec, UhdError, Usrp};
+
+/// Name of the sensor reporting whether a motherboard is locked to its reference.
+const REF_LOCKED_SENSOR: &str = "ref_locked";
+/// How often to poll the reference lock sensor.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How many times to re-read the board times if a PPS edge occurs while reading them.
+const READ_ATTEMPTS: usize = 3;
+
+/// An error which occurred while synchronizing motherboards.
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum TimeSyncError {
+    #[error("motherboard {mboard} did not lock to its reference within {timeout:?}")]
+    RefLockTimeout { mboard: usize, timeout: Duration },
+    #[error("motherboard {mboard} is {offset} s away from motherboard 0")]
+    Disagreement {
+        mboard: usize,
+        offset: TimeSpec,
+        report: TimeSyncReport,
+    },
+    #[error(transparent)]
+    Uhd(#[from] UhdError),
+}
+
+/// How to synchronize the motherboards of a device.
+///
+/// See [`Usrp::synchronize`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeSyncPolicy {
+    clock_source: String,
+    time_source: String,
+    time: TimeSpec,
+    lock_timeout: Duration,
+    tolerance: Duration,
+}
+
+impl TimeSyncPolicy {
+    /// Synchronize using the given clock and time sources.
+    pub fn new(clock_source: &str, time_source: &str) -> Self {
+        Self {
+            clock_source: clock_source.to_string(),
+            time_source: time_source.to_string(),
+            time: TimeSpec::default(),
+            lock_timeout: Duration::from_secs(5),
+            tolerance: Duration::from_micros(1),
+        }
+    }
+
+    /// Synchronize to an external 10 MHz reference and PPS signal.
+    pub fn external() -> Self {
+        Self::new("external", "external")
+    }
+
+    /// Synchronize to the reference and PPS signals of a GPSDO.
+    pub fn gpsdo() -> Self {
+        Self::new("gpsdo", "gpsdo")
+    }
+
+    /// Set the time latched on the PPS edge. Defaults to zero.
+    pub fn with_time(mut self, time: TimeSpec) -> Self {
+        self.time = time;
+        self
+    }
+
+    /// How long to wait for each motherboard to lock to its reference.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// The largest difference in PPS times tolerated between motherboards.
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn clock_source(&self) -> &str {
+        &self.clock_source
+    }
+
+    pub fn time_source(&self) -> &str {
+        &self.time_source
+    }
+
+    pub fn time(&self) -> TimeSpec {
+        self.time
+    }
+
+    pub fn lock_timeout(&self) -> Duration {
+        self.lock_timeout
+    }
+
+    pub fn tolerance(&self) -> Duration {
+        self.tolerance
+    }
+}
+
+/// Times read back from a single motherboard after synchronizing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoardTime {
+    pub mboard: usize,
+    /// Whether the board is locked to its reference, if it has a lock sensor.
+    pub ref_locked: Option<bool>,
+    /// The device time, as read by the host.
+    pub time: TimeSpec,
+    /// The device time at the last PPS edge.
+    pub last_pps_time: TimeSpec,
+}
+
+/// The result of synchronizing the motherboards of a device.
+///
+/// Offsets are relative to motherboard 0.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimeSyncReport {
+    pub boards: Vec<BoardTime>,
+}
+
+impl TimeSyncReport {
+    /// Difference between each board's last PPS time and that of the first board.
+    ///
+    /// These should all be zero on synchronized boards.
+    pub fn pps_offsets(&self) -> Vec<TimeSpec> {
+        self.offsets(|b| b.last_pps_time)
+    }
+
+    /// Difference between each board's time and that of the first board.
+    ///
+    /// The boards are read one after another, so these include the time taken
+    /// to read each board and are only a rough indication of alignment.
+    pub fn time_offsets(&self) -> Vec<TimeSpec> {
+        self.offsets(|b| b.time)
+    }
+
+    /// The largest absolute PPS offset.
+    pub fn max_pps_offset(&self) -> TimeSpec {
+        self.pps_offsets()
+            .into_iter()
+            .map(TimeSpec::abs)
+            .fold(TimeSpec::default(), |max, o| if o > max { o } else { max })
+    }
+
+    fn offsets(&self, f: impl Fn(&BoardTime) -> TimeSpec) -> Vec<TimeSpec> {
+        match self.boards.first() {
+            Some(first) => self.boards.iter().map(|b| f(b) - f(first)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Fail if any board's PPS time is further than `tolerance` from the first board's.
+    fn check(self, tolerance: Duration) -> Result<Self, TimeSyncError> {
+        let offsets = self.pps_offsets();
+        let worst = offsets
+            .iter()
+            .enumerate()
+            .find(|(_, o)| o.abs().as_secs() > tolerance.as_secs_f64());
+        match worst {
+            Some((i, &offset)) => Err(TimeSyncError::Disagreement {
+                mboard: self.boards[i].mboard,
+                offset,
+                report: self,
+            }),
+            None => Ok(self),
+        }
+    }
+}
+
+/// Implementation of [`Usrp::synchronize`].
+pub(crate) fn synchronize(
+    usrp: &mut Usrp,
+    policy: &TimeSyncPolicy,
+) -> Result<TimeSyncReport, TimeSyncError> {
+    let mboards = usrp.n_mboards()?;
+    for mboard in 0..mboards {
+        let mb = usrp.mboard(mboard);
+        mb.set_clock_source(&policy.clock_source)?;
+        mb.set_time_source(&policy.time_source)?;
+    }
+    for mboard in 0..mboards {
+        wait_for_ref_lock(usrp, mboard, policy.lock_timeout)?;
+    }
+    usrp.set_time_unknown_pps(policy.time)?;
+    read_times(usrp, mboards)?.check(policy.tolerance)
+}
+
+/// Wait for a motherboard to lock to its reference.
+///
+/// Boards without a lock sensor are assumed to be locked.
+fn wait_for_ref_lock(usrp: &Usrp, mboard: usize, timeout: Duration) -> Result<(), TimeSyncError> {
+    let mb = usrp.mboard(mboard);
+    if !mb.sensor_names()?.iter().any(|s| s == REF_LOCKED_SENSOR) {
+        return Ok(());
+    }
+    let start = Instant::now();
+    while mb.sensor_value(REF_LOCKED_SENSOR)?.as_bool() != Some(true) {
+        if start.elapsed() >= timeout {
+            return Err(TimeSyncError::RefLockTimeout { mboard, timeout });
+        }
+        std::thread::sleep(LOCK_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Read back the times of all motherboards.
+///
+/// The reads are retried if a PPS edge occurs partway through, since the boards
+/// would otherwise appear to be a second apart.
+fn read_times(usrp: &Usrp, mboards: usize) -> Result<TimeSyncReport> {
+    let mut report = TimeSyncReport::default();
+    for _ in 0..READ_ATTEMPTS {
+        report.boards = (0..mboards)
+            .map(|mboard| {
+                let mb = usrp.mboard(mboard);
+                let ref_locked = if mb.sensor_names()?.iter().any(|s| s == REF_LOCKED_SENSOR) {
+                    mb.sensor_value(REF_LOCKED_SENSOR)?.as_bool()
+                } else {
+                    None
+                };
+                Ok(BoardTime {
+                    mboard,
+                    ref_locked,
+                    time: mb.time()?,
+                    last_pps_time: mb.last_pps_time()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        match report.boards.first() {
+            Some(first) if usrp.mboard(0).last_pps_time()? != first.last_pps_time => continue,
+            _ => break,
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report(pps: &[f64]) -> TimeSyncReport {
+        TimeSyncReport {
+            boards: pps
+                .iter()
+                .enumerate()
+                .map(|(mboard, &secs)| BoardTime {
+                    mboard,
+                    ref_locked: Some(true),
+                    time: TimeSpec::from_secs_f64(secs + 0.5 + mboard as f64 * 1e-3),
+                    last_pps_time: TimeSpec::from_secs_f64(secs),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        let report = report(&[10.0, 10.0, 11.0]);
+        assert_eq!(
+            report.pps_offsets(),
+            [
+                TimeSpec::default(),
+                TimeSpec::default(),
+                TimeSpec::from_secs(1)
+            ]
+        );
+        assert_eq!(report.max_pps_offset(), TimeSpec::from_secs(1));
+        let time_offsets = report.time_offsets();
+        assert!((time_offsets[1].as_secs() - 1e-3).abs() < 1e-9);
+        assert!(TimeSyncReport::default().pps_offsets().is_empty());
+    }
+
+    #[test]
+    fn agreeing_boards_pass() {
+        let report = report(&[10.0, 10.0]);
+        assert_eq!(
+            report.clone().check(Duration::from_micros(1)).unwrap(),
+            report
+        );
+    }
+
+    #[test]
+    fn disagreeing_boards_fail() {
+        let err = report(&[10.0, 10.0, 9.0])
+            .check(Duration::from_millis(1))
+            .unwrap_err();
+        match err {
+            TimeSyncError::Disagreement {
+                mboard,
+                offset,
+                report,
+            } => {
+                assert_eq!(mboard, 2);
+                assert_eq!(offset, TimeSpec::from_secs(-1));
+                assert_eq!(report.boards.len(), 3);
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+    }
+}